@@ -1,8 +1,10 @@
+use crate::diagram::colors::Colors;
 use crate::diagram::conversion::Conversion;
+use crate::diagram::index::Index;
 use crate::diagram::parser::Rule;
 use crate::diagram::rules::Rules;
 use crate::diagram::types::{Caption, Config, Displacement, Edge, Endings, Movement, ObjectEdge, Radius, ShapeConfig};
-use crate::skia::Effect;
+use crate::skia::{Cap, Effect, Join, Shading, Shadow};
 use pest::iterators::Pair;
 use skia_safe::Color;
 
@@ -23,9 +25,11 @@ pub enum Attributes<'a> {
     endings: Option<Endings>,
     stroke: Color,
     fill: Color,
+    shading: Option<Shading>,
     text: Color,
     thickness: f32,
     effect: Effect,
+    shadow: Option<Shadow>,
   },
   Open {
     id: Option<&'a str>,
@@ -38,25 +42,65 @@ pub enum Attributes<'a> {
     movement: Option<Displacement>,
     stroke: Color,
     thickness: f32,
+    effect: Effect,
+    cap: Cap,
+    join: Join,
+    double: bool,
+    shadow: Option<Shadow>,
   },
 }
 
 impl Attributes<'_> {
-  pub(crate) fn open_attributes<'a>(pair: &Pair<'a, Rule>, config: &Config, rule: Rule) -> (Attributes<'a>, Pair<'a, Rule>) {
+  pub(crate) fn shadow(&self) -> Option<&Shadow> {
+    match self {
+      Attributes::Closed { shadow, .. } | Attributes::Open { shadow, .. } => shadow.as_ref(),
+    }
+  }
+
+  pub(crate) fn open_attributes<'a>(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index, shape: &ShapeConfig, rule: Rule) -> (Attributes<'a>, Pair<'a, Rule>) {
     let attributes = Rules::get_rule(pair, rule);
-    let (stroke, _fill, _text) = Conversion::colors_from(&attributes, &Color::BLACK);
+    let classes = index.class_attributes(&attributes);
+    let styled = |rule: Rule| classes.iter().rev().find(|pair| pair.as_rule() == rule).cloned();
+
+    let stroke = styled(Rule::stroke).and_then(|pair| index.diagnostics.report(Conversion::color_from(pair))).unwrap_or(shape.stroke);
+    let (stroke, _fill, _text) = Conversion::colors_from(&attributes, &stroke, &mut index.diagnostics);
+    let opacity = Rules::find_rule(&attributes, Rule::opacity).or_else(|| styled(Rule::opacity)).map(Conversion::opacity_from);
+    let length = Conversion::length_into(&attributes, &config.unit, index)
+      .or_else(|| styled(Rule::length).map(|pair| Conversion::length_from(pair, &config.unit, index).pixels()));
 
     (Attributes::Open {
       id: Conversion::identified_in(pair),
-      caption: Conversion::caption(&attributes, config),
-      length: Conversion::length_into(&attributes, &config.unit).unwrap_or(config.line.pixels()),
-      endings: Conversion::endings(&attributes).unwrap_or_default(),
-      source: Conversion::fraction_edge_for(&attributes, Rule::source),
-      target: Conversion::fraction_edge_for(&attributes, Rule::target),
-      movement: Conversion::displacement_for(&attributes, Rule::rel_movement, &config.unit),
+      caption: Conversion::caption(&attributes, config, shape, index),
+      length: length.unwrap_or(shape.length),
+      endings: Conversion::endings(&attributes).or_else(|| styled(Rule::endings).map(Conversion::endings_from))
+        .or_else(|| shape.endings.clone())
+        .unwrap_or_default(),
+      source: Conversion::fraction_edge_for(&attributes, Rule::source, index),
+      target: Conversion::fraction_edge_for(&attributes, Rule::target, index),
+      movement: Conversion::displacement_for(&attributes, Rule::rel_movement, &config.unit, index),
       same: Rules::find_rule(&attributes, Rule::same).is_some(),
-      stroke,
-      thickness: Conversion::thickness_for(&attributes),
+      stroke: Colors::faded(stroke, opacity.unwrap_or(1.)),
+      thickness: Rules::find_rule(&attributes, Rule::thickness).or_else(|| styled(Rule::thickness))
+        .and_then(|pair| {
+          let thickness = Conversion::thickness_from(pair, &config.unit, index);
+          index.diagnostics.report(thickness)
+        })
+        .unwrap_or(shape.thickness),
+      effect: Rules::find_rule(&attributes, Rule::effect).or_else(|| styled(Rule::effect))
+        .and_then(|pair| {
+          let effect = Conversion::effect_from(pair, &config.unit, index);
+          index.diagnostics.report(effect)
+        })
+        .unwrap_or_else(|| shape.effect.clone()),
+      cap: Rules::find_rule(&attributes, Rule::cap).map(Conversion::cap_from).unwrap_or_default(),
+      join: Rules::find_rule(&attributes, Rule::join).map(Conversion::join_from).unwrap_or_default(),
+      double: Rules::find_rule(&attributes, Rule::double).is_some(),
+      shadow: Rules::find_rule(&attributes, Rule::shadow).or_else(|| styled(Rule::shadow))
+        .and_then(|pair| {
+          let shadow = Conversion::shadow_from(pair, &config.unit, index);
+          index.diagnostics.report(shadow)
+        })
+        .or_else(|| shape.shadow.clone()),
     }, attributes)
   }
 
@@ -131,18 +175,20 @@ pub struct ClosedAttributes<'a> {
   pub(crate) endings: Option<Endings>,
   pub(crate) stroke: Color,
   pub(crate) fill: Color,
+  pub(crate) shading: Option<Shading>,
   pub(crate) text: Color,
   pub(crate) thickness: f32,
   pub(crate) effect: Effect,
+  pub(crate) shadow: Option<Shadow>,
 }
 
 impl<'a> ClosedAttributes<'a> {
-  pub(crate) fn from(pair: &Pair<'a, Rule>, config: &Config, shape: &ShapeConfig) -> Self {
+  pub(crate) fn from(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index, shape: &ShapeConfig) -> Self {
     let mut attrs = ClosedAttributes::default();
     pair.clone().into_inner().for_each(|pair| {
       match pair.as_rule() {
         Rule::identified => attrs.id = Some(pair.into_inner().next().unwrap().as_str()),
-        Rule::closed_attributes => Self::attributes(&pair, config, shape, &mut attrs),
+        Rule::closed_attributes => Self::attributes(&pair, config, index, shape, &mut attrs),
         // _ => panic!("Unexpected {:?}", pair)
         _ => {}
       }
@@ -150,37 +196,69 @@ impl<'a> ClosedAttributes<'a> {
     attrs
   }
 
-  pub(crate) fn attributes(pair: &Pair<'a, Rule>, config: &Config, shape: &ShapeConfig, attrs: &mut ClosedAttributes<'a>) {
-    attrs.fill = Color::TRANSPARENT;
+  pub(crate) fn attributes(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index, shape: &ShapeConfig, attrs: &mut ClosedAttributes<'a>) {
+    attrs.fill = shape.fill;
+    attrs.shading = shape.shading.clone();
+    attrs.shadow = shape.shadow.clone();
     attrs.stroke = shape.stroke;
-    attrs.effect = shape.effect;
-    attrs.thickness = 1.0;
-    attrs.text = Color::BLACK;
+    attrs.effect = shape.effect.clone();
+    attrs.thickness = shape.thickness;
+    attrs.text = shape.text;
+    attrs.endings = shape.endings.clone();
     attrs.radius = shape.radius;
     attrs.space = shape.space;
     attrs.padding = shape.padding;
 
-    pair.clone().into_inner().for_each(|pair| {
+    // styles first, so the shape's own attributes win
+    let styled = index.class_attributes(pair).into_iter().filter(|pair| pair.as_rule() != Rule::length);
+    let mut opacity = 1.;
+    styled.chain(pair.clone().into_inner()).for_each(|pair| {
       match pair.as_rule() {
         Rule::string => {
-          attrs.strings.push(Conversion::string_from(pair));
+          attrs.strings.push(index.interpolate(&Conversion::string_from(pair)));
         }
         Rule::same => attrs.same = true,
-        Rule::height => attrs.height = Conversion::length_from_(pair, &config.unit, shape.height).pixels().into(),
-        Rule::width => attrs.width = Conversion::length_from_(pair, &config.unit, shape.width).pixels().into(),
-        Rule::padding => attrs.padding = Conversion::length_from(pair, &config.unit).pixels(),
-        Rule::location => attrs.location = Some(Conversion::location_from(pair, &config.unit)),
-        Rule::stroke => attrs.stroke = Conversion::color_from(pair).unwrap_or(attrs.stroke),
-        Rule::fill => attrs.fill = Conversion::color_from(pair).unwrap_or(attrs.fill),
-        Rule::thickness => attrs.thickness = Conversion::thickness_from(pair),
-        Rule::effect => attrs.effect = Conversion::effect_from(pair),
-        Rule::radius => attrs.radius = Conversion::length_from(pair, &config.unit).pixels(),
-        Rule::text_color => attrs.text = Conversion::color_from(pair).unwrap_or(attrs.text),
+        Rule::height => attrs.height = Conversion::length_from_(pair, &config.unit, shape.height, index).pixels().into(),
+        Rule::width => attrs.width = Conversion::length_from_(pair, &config.unit, shape.width, index).pixels().into(),
+        Rule::padding => attrs.padding = Conversion::length_from(pair, &config.unit, index).pixels(),
+        Rule::space => attrs.space = Conversion::length_from(pair, &config.unit, index).pixels(),
+        Rule::location => {
+          let location = Conversion::location_from(pair, &config.unit, index);
+          attrs.location = index.diagnostics.report(location)
+        }
+        Rule::stroke => attrs.stroke = index.diagnostics.report(Conversion::color_from(pair)).unwrap_or(attrs.stroke),
+        Rule::fill => match Conversion::shading_in(&pair, &config.unit, index) {
+          // a gradient or pattern keeps the fill color underneath
+          Some(shading) => attrs.shading = index.diagnostics.report(shading).or(attrs.shading.take()),
+          None => {
+            attrs.fill = index.diagnostics.report(Conversion::color_from(pair)).unwrap_or(attrs.fill);
+            attrs.shading = None;
+          }
+        },
+        Rule::thickness => {
+          let thickness = Conversion::thickness_from(pair, &config.unit, index);
+          attrs.thickness = index.diagnostics.report(thickness).unwrap_or(attrs.thickness)
+        }
+        Rule::effect => {
+          let effect = Conversion::effect_from(pair, &config.unit, index);
+          attrs.effect = index.diagnostics.report(effect).unwrap_or(attrs.effect.clone())
+        }
+        Rule::shadow => {
+          let shadow = Conversion::shadow_from(pair, &config.unit, index);
+          attrs.shadow = index.diagnostics.report(shadow).or(attrs.shadow.take())
+        }
+        Rule::radius => attrs.radius = Conversion::length_from(pair, &config.unit, index).pixels(),
+        Rule::text_color => attrs.text = index.diagnostics.report(Conversion::color_from(pair)).unwrap_or(attrs.text),
+        Rule::opacity => opacity = Conversion::opacity_from(pair),
         Rule::endings => attrs.endings = Conversion::endings_from(pair).into(),
-        Rule::continuation => {}
-        _ => panic!("Unexpected {:?}", pair)
+        Rule::continuation | Rule::class => {}
+        _ => index.diagnostics.warning(&pair, format!("ignored {:?}", pair.as_rule())),
       }
     });
+    attrs.stroke = Colors::faded(attrs.stroke, opacity);
+    attrs.fill = Colors::faded(attrs.fill, opacity);
+    attrs.text = Colors::faded(attrs.text, opacity);
+    attrs.shading = attrs.shading.take().map(|shading| shading.map_colors(|color| Colors::faded(color, opacity)));
     if !attrs.strings.is_empty() {
       attrs.title = Some(attrs.strings.join("\n"));
     }
@@ -191,7 +269,10 @@ impl<'a> ClosedAttributes<'a> {
 pub struct OpenAttributes<'a> {
   pub(crate) id: Option<&'a str>,
   pub(crate) same: bool,
-  pub(crate) route: bool,
+  /// routed around other shapes, keeping this far away
+  pub(crate) route: Option<f32>,
+  /// ends in the middle of shapes move out to their outline
+  pub(crate) chop: bool,
   pub(crate) caption: Option<Caption>,
   length: f32,
   pub(crate) endings: Endings,
@@ -201,40 +282,76 @@ pub struct OpenAttributes<'a> {
   pub(crate) movements: Vec<Movement>,
   stroke: Color,
   thickness: f32,
+  /// for arcs, which turn counter-clockwise unless `cw`
+  pub(crate) clockwise: bool,
+  pub(crate) radius: Option<f32>,
+  /// curves through the points of lines and paths
+  pub(crate) smooth: bool,
+  pub(crate) tension: Option<f32>,
+  /// where curves bend toward, relative to their start and then their end
+  pub(crate) controls: Vec<Movement>,
 }
 
 impl<'a> OpenAttributes<'a> {
-  pub(crate) fn from(pair: &Pair<'a, Rule>, config: &Config) -> OpenAttributes<'a> {
-    let mut attrs = OpenAttributes::default();
+  pub(crate) fn from(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index, shape: &ShapeConfig) -> OpenAttributes<'a> {
+    let mut attrs = OpenAttributes {
+      length: shape.length,
+      endings: shape.endings.clone().unwrap_or_default(),
+      stroke: shape.stroke,
+      thickness: shape.thickness,
+      ..OpenAttributes::default()
+    };
     pair.clone().into_inner().for_each(|pair| {
       match pair.as_rule() {
         Rule::identified => attrs.id = Some(pair.into_inner().next().unwrap().as_str()),
-        Rule::open_attributes => OpenAttributes::attributes(&pair, config, &mut attrs),
-        _ => panic!("Unexpected {:?}", pair)
+        Rule::open_attributes | Rule::arc_attributes | Rule::curve_attributes => OpenAttributes::attributes(&pair, config, index, shape, &mut attrs),
+        _ => index.diagnostics.warning(&pair, format!("ignored {:?}", pair.as_rule())),
       }
     });
     attrs
   }
 
-  pub(crate) fn attributes(pair: &Pair<'a, Rule>, config: &Config, attrs: &mut OpenAttributes<'a>) {
-    pair.clone().into_inner().for_each(|pair| {
+  pub(crate) fn attributes(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index, shape: &ShapeConfig, attrs: &mut OpenAttributes<'a>) {
+    let styled = index.class_attributes(pair).into_iter()
+      .filter(|pair| matches!(pair.as_rule(), Rule::endings | Rule::length | Rule::stroke | Rule::opacity | Rule::thickness));
+    let mut opacity = 1.;
+    styled.chain(pair.clone().into_inner()).for_each(|pair| {
       match pair.as_rule() {
         Rule::endings => attrs.endings = Conversion::endings_from(pair),
-        Rule::caption => attrs.caption = Some(Conversion::caption_from(pair, config)),
-        Rule::length => attrs.length = Conversion::length_from(pair, &config.unit).pixels(),
+        Rule::caption => attrs.caption = Some(Conversion::caption_from(pair, config, shape, index)),
+        Rule::length => attrs.length = Conversion::length_from(pair, &config.unit, index).pixels(),
         Rule::same => attrs.same = true,
-        Rule::route => attrs.route = true,
-        Rule::source => attrs.source = Some(Conversion::fraction_edge_from(pair)),
-        Rule::target => attrs.target = Some(Conversion::fraction_edge_from(pair)),
-        Rule::stroke => attrs.stroke = Conversion::color_from(pair).unwrap_or(attrs.stroke),
-        Rule::thickness => attrs.thickness = Conversion::thickness_from(pair),
+        Rule::route => attrs.route = Some(shape.space),
+        Rule::chop => attrs.chop = true,
+        Rule::turn => attrs.clockwise = pair.as_str() == "cw",
+        Rule::radius => attrs.radius = Some(Conversion::length_from(pair, &config.unit, index).pixels()),
+        Rule::smooth => attrs.smooth = true,
+        Rule::tension => attrs.tension = pair.into_inner().next().unwrap().as_str().parse().ok(),
+        Rule::control => {
+          let inner = pair.clone().into_inner().next().unwrap();
+          let control = match inner.as_rule() {
+            Rule::rel_movement => Conversion::rel_movement_from(inner, &config.unit, index),
+            _ => Movement::ObjectStart { object: Conversion::fraction_edge_from(pair, index) },
+          };
+          attrs.controls.push(control);
+        }
+        Rule::class | Rule::shadow | Rule::effect | Rule::cap | Rule::join | Rule::double => {}
+        Rule::source => attrs.source = Some(Conversion::fraction_edge_from(pair, index)),
+        Rule::target => attrs.target = Some(Conversion::fraction_edge_from(pair, index)),
+        Rule::stroke => attrs.stroke = index.diagnostics.report(Conversion::color_from(pair)).unwrap_or(attrs.stroke),
+        Rule::opacity => opacity = Conversion::opacity_from(pair),
+        Rule::thickness => {
+          let thickness = Conversion::thickness_from(pair, &config.unit, index);
+          attrs.thickness = index.diagnostics.report(thickness).unwrap_or(attrs.thickness)
+        }
         Rule::rel_movement | Rule::abs_movement => {
-          let movement = Conversion::movement_from(pair, &config.unit);
+          let movement = Conversion::movement_from(pair, &config.unit, index);
           attrs.movements.push(movement)
         }
-        _ => panic!("Unexpected {:?}", pair)
+        _ => index.diagnostics.warning(&pair, format!("ignored {:?}", pair.as_rule())),
       }
     });
+    attrs.stroke = Colors::faded(attrs.stroke, opacity);
   }
 }
 
@@ -242,6 +359,7 @@ impl<'a> OpenAttributes<'a> {
 mod tests {
   use crate::diagram::attributes::OpenAttributes;
   use crate::diagram::conversion::Conversion;
+  use crate::diagram::index::Index;
   use crate::diagram::parser::Rule;
   use crate::diagram::types::EdgeDirection::Vertical;
   use crate::diagram::types::{Caption, Config, Edge, Ending, Endings, ObjectEdge};
@@ -249,9 +367,9 @@ mod tests {
 
   fn attrs_from(string: &str, config: Option<Config>) -> OpenAttributes<'_> {
     let config = config.unwrap_or_default();
-    let mut top = Conversion::pairs_for(Rule::picture, string);
+    let mut top = Conversion::pairs_for(Rule::picture, string).unwrap();
     let next = top.next().unwrap();
-    OpenAttributes::from(&next, &config)
+    OpenAttributes::from(&next, &config, &mut Index::default(), &config.line)
   }
 
   #[test]
@@ -262,10 +380,10 @@ mod tests {
 
     let attrs = attrs_from(string, None);
     assert_eq!(Some("ui13"), attrs.id);
-    assert_eq!(Endings { start: Ending::Arrow, end: Ending::Arrow }, attrs.endings);
+    assert_eq!(Endings { start: Ending::Arrow, end: Ending::Arrow, middle: Ending::None }, attrs.endings);
     assert_eq!(78., attrs.length);
-    assert_eq!(Some(ObjectEdge { id: "id1".into(), edge: Edge { direction: Vertical, x: 0.0, y: -0.5 } }), attrs.source);
-    assert_eq!(Some(ObjectEdge { id: "id2".into(), edge: Edge { direction: Vertical, x: 0.0, y: 0.5 } }), attrs.target);
+    assert_eq!(Some(ObjectEdge::new("id1", Edge { direction: Vertical, x: 0.0, y: -0.5 })), attrs.source);
+    assert_eq!(Some(ObjectEdge::new("id2", Edge { direction: Vertical, x: 0.0, y: 0.5 })), attrs.target);
     assert_eq!(true, attrs.same);
     assert_eq!(Color::RED, attrs.stroke);
     assert_eq!(3.0, attrs.thickness);
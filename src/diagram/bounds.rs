@@ -1,5 +1,7 @@
 use skia_safe::{Point, Rect};
 
+use crate::diagram::types::{Arc, Curve, Node};
+
 pub(crate) struct Bounds;
 
 impl Bounds {
@@ -29,4 +31,37 @@ impl Bounds {
     bounds.right = bounds.right.max(point.x);
   }
 
+  /// the ends of an arc, and where it crosses the axes of its circle in between
+  pub(crate) fn bounds_from_arc(arc: &Arc) -> Rect {
+    let (from, to) = (arc.start.min(arc.start + arc.sweep), arc.start.max(arc.start + arc.sweep));
+    let mut points = vec![arc.point_at(from), arc.point_at(to)];
+    let mut axis = (from / 90.).ceil() * 90.;
+    while axis < to {
+      points.push(arc.point_at(axis));
+      axis += 90.;
+    }
+    Self::bounds_from_points(&points)
+  }
+
+  pub(crate) fn bounds_from_curve(curve: &Curve) -> Rect {
+    Self::bounds_from_points(&curve.flatten(16))
+  }
+
+  /// Adjust bounds so that shadows fit in it too, since they take no room in the layout
+  pub(crate) fn bounds_from_shadows(bounds: &mut Rect, nodes: &[Node]) {
+    for node in nodes {
+      let (attributes, used) = match node {
+        Node::Group(attributes, used, nodes) => {
+          Self::bounds_from_shadows(bounds, nodes);
+          (attributes, used)
+        }
+        Node::Closed(attributes, used, ..) | Node::Open(attributes, used, _) => (attributes, used),
+        _ => continue,
+      };
+      if let Some(shadow) = attributes.shadow() {
+        Self::bounds_from_rect(bounds, shadow.bounds(used));
+      }
+    }
+  }
+
 }
@@ -0,0 +1,194 @@
+use skia_safe::Color;
+
+#[cfg(test)]
+mod tests;
+
+/// Names picturs had before CSS, which win over the CSS ones: `green` stays `#00ff00` and `gray` `#888888`
+const PICTURS: &[(&str, u32)] = &[
+  ("green", 0xFF00FF00),
+  ("gray", 0xFF888888),
+  ("grey", 0xFF888888),
+  ("dgray", 0xFF444444),
+  ("dgrey", 0xFF444444),
+  ("lgray", 0xFFCCCCCC),
+];
+
+// https://www.w3.org/TR/css-color-4/#named-colors
+const CSS: &[(&str, u32)] = &[
+  ("aliceblue", 0xF0F8FF), ("antiquewhite", 0xFAEBD7), ("aqua", 0x00FFFF), ("aquamarine", 0x7FFFD4),
+  ("azure", 0xF0FFFF), ("beige", 0xF5F5DC), ("bisque", 0xFFE4C4), ("black", 0x000000),
+  ("blanchedalmond", 0xFFEBCD), ("blue", 0x0000FF), ("blueviolet", 0x8A2BE2), ("brown", 0xA52A2A),
+  ("burlywood", 0xDEB887), ("cadetblue", 0x5F9EA0), ("chartreuse", 0x7FFF00), ("chocolate", 0xD2691E),
+  ("coral", 0xFF7F50), ("cornflowerblue", 0x6495ED), ("cornsilk", 0xFFF8DC), ("crimson", 0xDC143C),
+  ("cyan", 0x00FFFF), ("darkblue", 0x00008B), ("darkcyan", 0x008B8B), ("darkgoldenrod", 0xB8860B),
+  ("darkgray", 0xA9A9A9), ("darkgreen", 0x006400), ("darkgrey", 0xA9A9A9), ("darkkhaki", 0xBDB76B),
+  ("darkmagenta", 0x8B008B), ("darkolivegreen", 0x556B2F), ("darkorange", 0xFF8C00), ("darkorchid", 0x9932CC),
+  ("darkred", 0x8B0000), ("darksalmon", 0xE9967A), ("darkseagreen", 0x8FBC8F), ("darkslateblue", 0x483D8B),
+  ("darkslategray", 0x2F4F4F), ("darkslategrey", 0x2F4F4F), ("darkturquoise", 0x00CED1), ("darkviolet", 0x9400D3),
+  ("deeppink", 0xFF1493), ("deepskyblue", 0x00BFFF), ("dimgray", 0x696969), ("dimgrey", 0x696969),
+  ("dodgerblue", 0x1E90FF), ("firebrick", 0xB22222), ("floralwhite", 0xFFFAF0), ("forestgreen", 0x228B22),
+  ("fuchsia", 0xFF00FF), ("gainsboro", 0xDCDCDC), ("ghostwhite", 0xF8F8FF), ("gold", 0xFFD700),
+  ("goldenrod", 0xDAA520), ("gray", 0x808080), ("green", 0x008000), ("greenyellow", 0xADFF2F),
+  ("grey", 0x808080), ("honeydew", 0xF0FFF0), ("hotpink", 0xFF69B4), ("indianred", 0xCD5C5C),
+  ("indigo", 0x4B0082), ("ivory", 0xFFFFF0), ("khaki", 0xF0E68C), ("lavender", 0xE6E6FA),
+  ("lavenderblush", 0xFFF0F5), ("lawngreen", 0x7CFC00), ("lemonchiffon", 0xFFFACD), ("lightblue", 0xADD8E6),
+  ("lightcoral", 0xF08080), ("lightcyan", 0xE0FFFF), ("lightgoldenrodyellow", 0xFAFAD2), ("lightgray", 0xD3D3D3),
+  ("lightgreen", 0x90EE90), ("lightgrey", 0xD3D3D3), ("lightpink", 0xFFB6C1), ("lightsalmon", 0xFFA07A),
+  ("lightseagreen", 0x20B2AA), ("lightskyblue", 0x87CEFA), ("lightslategray", 0x778899), ("lightslategrey", 0x778899),
+  ("lightsteelblue", 0xB0C4DE), ("lightyellow", 0xFFFFE0), ("lime", 0x00FF00), ("limegreen", 0x32CD32),
+  ("linen", 0xFAF0E6), ("magenta", 0xFF00FF), ("maroon", 0x800000), ("mediumaquamarine", 0x66CDAA),
+  ("mediumblue", 0x0000CD), ("mediumorchid", 0xBA55D3), ("mediumpurple", 0x9370DB), ("mediumseagreen", 0x3CB371),
+  ("mediumslateblue", 0x7B68EE), ("mediumspringgreen", 0x00FA9A), ("mediumturquoise", 0x48D1CC), ("mediumvioletred", 0xC71585),
+  ("midnightblue", 0x191970), ("mintcream", 0xF5FFFA), ("mistyrose", 0xFFE4E1), ("moccasin", 0xFFE4B5),
+  ("navajowhite", 0xFFDEAD), ("navy", 0x000080), ("oldlace", 0xFDF5E6), ("olive", 0x808000),
+  ("olivedrab", 0x6B8E23), ("orange", 0xFFA500), ("orangered", 0xFF4500), ("orchid", 0xDA70D6),
+  ("palegoldenrod", 0xEEE8AA), ("palegreen", 0x98FB98), ("paleturquoise", 0xAFEEEE), ("palevioletred", 0xDB7093),
+  ("papayawhip", 0xFFEFD5), ("peachpuff", 0xFFDAB9), ("peru", 0xCD853F), ("pink", 0xFFC0CB),
+  ("plum", 0xDDA0DD), ("powderblue", 0xB0E0E6), ("purple", 0x800080), ("rebeccapurple", 0x663399),
+  ("red", 0xFF0000), ("rosybrown", 0xBC8F8F), ("royalblue", 0x4169E1), ("saddlebrown", 0x8B4513),
+  ("salmon", 0xFA8072), ("sandybrown", 0xF4A460), ("seagreen", 0x2E8B57), ("seashell", 0xFFF5EE),
+  ("sienna", 0xA0522D), ("silver", 0xC0C0C0), ("skyblue", 0x87CEEB), ("slateblue", 0x6A5ACD),
+  ("slategray", 0x708090), ("slategrey", 0x708090), ("snow", 0xFFFAFA), ("springgreen", 0x00FF7F),
+  ("steelblue", 0x4682B4), ("tan", 0xD2B48C), ("teal", 0x008080), ("thistle", 0xD8BFD8),
+  ("tomato", 0xFF6347), ("turquoise", 0x40E0D0), ("violet", 0xEE82EE), ("wheat", 0xF5DEB3),
+  ("white", 0xFFFFFF), ("whitesmoke", 0xF5F5F5), ("yellow", 0xFFFF00), ("yellowgreen", 0x9ACD32),
+];
+
+/// What a color function is given
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ColorArgument {
+  Number(f32),
+  Percentage(f32),
+  Color(Color),
+}
+
+/// Named colors, hex colors and the color functions `rgb`, `rgba`, `hsl`, `hsla`, `lighten`, `darken` and `mix`
+pub(crate) struct Colors;
+
+impl Colors {
+  pub(crate) fn named(name: &str) -> Option<Color> {
+    if name == "transparent" {
+      return Some(Color::TRANSPARENT);
+    }
+    PICTURS.iter().find(|(known, _)| *known == name).map(|(_, argb)| Color::new(*argb))
+      .or_else(|| CSS.iter().find(|(known, _)| *known == name).map(|(_, rgb)| Color::new(0xFF000000 | rgb)))
+  }
+
+  /// every name `named` knows, for completion
+  pub(crate) fn names() -> impl Iterator<Item=&'static str> {
+    let css = CSS.iter().filter(|(name, _)| !PICTURS.iter().any(|(known, _)| known == name));
+    PICTURS.iter().chain(css).map(|(name, _)| *name).chain(["transparent"])
+  }
+
+  /// `rgb`, `rgba`, `rrggbb` or `rrggbbaa` without the `#`
+  pub(crate) fn hex(hex: &str) -> Option<Color> {
+    let digits = match hex.len() {
+      3 | 4 => hex.chars().flat_map(|digit| [digit, digit]).collect(),
+      6 | 8 => hex.to_string(),
+      _ => return None,
+    };
+    let value = u32::from_str_radix(&digits, 16).ok()?;
+    Some(match digits.len() {
+      8 => Color::new(value.rotate_right(8)),
+      _ => Color::new(0xFF000000 | value),
+    })
+  }
+
+  pub(crate) fn apply(function: &str, arguments: &[ColorArgument]) -> Result<Color, String> {
+    use ColorArgument::{Number, Percentage};
+    match (function, arguments) {
+      ("rgb" | "rgba", [r, g, b, alpha @ ..]) if alpha.len() <= 1 => {
+        let channel = |argument: &ColorArgument| match argument {
+          Number(value) => Ok(value.clamp(0., 255.).round() as u8),
+          Percentage(value) => Ok((value.clamp(0., 100.) * 2.55).round() as u8),
+          ColorArgument::Color(_) => Err(format!("`{}` takes numbers, not colors", function)),
+        };
+        let alpha = alpha.first().map(Self::alpha).transpose()?.unwrap_or(1.);
+        Ok(Color::from_rgb(channel(r)?, channel(g)?, channel(b)?).with_a(Self::byte(alpha)))
+      }
+      ("hsl" | "hsla", [Number(hue), Percentage(saturation), Percentage(lightness), alpha @ ..]) if alpha.len() <= 1 => {
+        let alpha = alpha.first().map(Self::alpha).transpose()?.unwrap_or(1.);
+        Ok(Self::from_hsl(*hue, saturation / 100., lightness / 100.).with_a(Self::byte(alpha)))
+      }
+      ("hsl" | "hsla", _) => Err(format!("`{}` takes a hue, two percentages and an optional alpha", function)),
+      ("lighten" | "darken", [ColorArgument::Color(color), Percentage(amount)]) => {
+        let amount = if function == "darken" { -amount } else { *amount };
+        let (hue, saturation, lightness) = Self::hsl(*color);
+        Ok(Self::from_hsl(hue, saturation, (lightness + amount / 100.).clamp(0., 1.)).with_a(color.a()))
+      }
+      ("lighten" | "darken", _) => Err(format!("`{}` takes a color and a percentage", function)),
+      ("mix", [ColorArgument::Color(first), ColorArgument::Color(second), weight @ ..]) if weight.len() <= 1 => {
+        let weight = match weight.first() {
+          Some(Percentage(weight)) => weight.clamp(0., 100.) / 100.,
+          None => 0.5,
+          _ => return Err("`mix` takes two colors and a percentage of the first".into()),
+        };
+        Ok(Self::mix(*first, *second, weight))
+      }
+      ("mix", _) => Err("`mix` takes two colors and a percentage of the first".into()),
+      _ => Err(format!("`{}` takes three numbers and an optional alpha", function)),
+    }
+  }
+
+  /// `weight` of the first color, the rest of the second
+  pub(crate) fn mix(first: Color, second: Color, weight: f32) -> Color {
+    let channel = |a: u8, b: u8| (a as f32 * weight + b as f32 * (1. - weight)).round() as u8;
+    Color::from_argb(channel(first.a(), second.a()), channel(first.r(), second.r()), channel(first.g(), second.g()), channel(first.b(), second.b()))
+  }
+
+  /// `color` with its alpha scaled by `opacity`
+  pub(crate) fn faded(color: Color, opacity: f32) -> Color {
+    color.with_a((color.a() as f32 * opacity.clamp(0., 1.)).round() as u8)
+  }
+
+  fn alpha(argument: &ColorArgument) -> Result<f32, String> {
+    match argument {
+      ColorArgument::Number(alpha) => Ok(alpha.clamp(0., 1.)),
+      ColorArgument::Percentage(alpha) => Ok(alpha.clamp(0., 100.) / 100.),
+      ColorArgument::Color(_) => Err("alpha should be a number or a percentage".into()),
+    }
+  }
+
+  fn byte(fraction: f32) -> u8 {
+    (fraction * 255.).round() as u8
+  }
+
+  /// hue in degrees, saturation and lightness from 0 to 1
+  fn hsl(color: Color) -> (f32, f32, f32) {
+    let [r, g, b] = [color.r(), color.g(), color.b()].map(|channel| channel as f32 / 255.);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.;
+    if max == min {
+      return (0., 0., lightness);
+    }
+    let delta = max - min;
+    let saturation = delta / (1. - (2. * lightness - 1.).abs());
+    let hue = if max == r {
+      ((g - b) / delta).rem_euclid(6.)
+    } else if max == g {
+      (b - r) / delta + 2.
+    } else {
+      (r - g) / delta + 4.
+    };
+    (hue * 60., saturation, lightness)
+  }
+
+  fn from_hsl(hue: f32, saturation: f32, lightness: f32) -> Color {
+    let saturation = saturation.clamp(0., 1.);
+    let lightness = lightness.clamp(0., 1.);
+    let chroma = (1. - (2. * lightness - 1.).abs()) * saturation;
+    let sector = hue.rem_euclid(360.) / 60.;
+    let x = chroma * (1. - (sector % 2. - 1.).abs());
+    let (r, g, b) = match sector as u32 {
+      0 => (chroma, x, 0.),
+      1 => (x, chroma, 0.),
+      2 => (0., chroma, x),
+      3 => (0., x, chroma),
+      4 => (x, 0., chroma),
+      _ => (chroma, 0., x),
+    };
+    let m = lightness - chroma / 2.;
+    Color::from_rgb(Self::byte(r + m), Self::byte(g + m), Self::byte(b + m))
+  }
+}
@@ -0,0 +1,54 @@
+use skia_safe::Color;
+
+use crate::diagram::attributes::Attributes;
+use crate::diagram::colors::{ColorArgument, Colors};
+use crate::diagram::create_diagram;
+use crate::diagram::types::Node;
+
+#[test]
+fn names() {
+  assert_eq!(Some(Color::new(0xFF6495ED)), Colors::named("cornflowerblue"));
+  assert_eq!(Some(Color::new(0xFF663399)), Colors::named("rebeccapurple"));
+  assert_eq!(Some(Color::GREEN), Colors::named("green"));
+  assert_eq!(Some(Color::new(0xFF006400)), Colors::named("darkgreen"));
+  assert_eq!(Some(Color::TRANSPARENT), Colors::named("transparent"));
+  assert_eq!(None, Colors::named("mauve"));
+  assert_eq!(1, Colors::names().filter(|name| *name == "green").count());
+}
+
+#[test]
+fn hex() {
+  assert_eq!(Some(Color::new(0xFFFFCC00)), Colors::hex("fc0"));
+  assert_eq!(Some(Color::new(0x88FFCC00)), Colors::hex("fc08"));
+  assert_eq!(Some(Color::new(0xFF645590)), Colors::hex("645590"));
+  assert_eq!(Some(Color::new(0x80645590)), Colors::hex("64559080"));
+  assert_eq!(None, Colors::hex("64559"));
+}
+
+#[test]
+fn functions() {
+  use ColorArgument::{Number, Percentage};
+  assert_eq!(Ok(Color::new(0xFF0A141E)), Colors::apply("rgb", &[Number(10.), Number(20.), Number(30.)]));
+  assert_eq!(Ok(Color::new(0x80FF0000)), Colors::apply("rgba", &[Percentage(100.), Number(0.), Number(0.), Number(0.5)]));
+  assert_eq!(Ok(Color::new(0xFF3366CC)), Colors::apply("hsl", &[Number(220.), Percentage(60.), Percentage(50.)]));
+  assert_eq!(Ok(Color::new(0xFF6666FF)), Colors::apply("lighten", &[ColorArgument::Color(Color::BLUE), Percentage(20.)]));
+  assert_eq!(Ok(Color::new(0xFF000099)), Colors::apply("darken", &[ColorArgument::Color(Color::BLUE), Percentage(20.)]));
+  assert_eq!(Ok(Color::new(0xFFFFB3B3)), Colors::apply("mix", &[ColorArgument::Color(Color::RED), ColorArgument::Color(Color::WHITE), Percentage(30.)]));
+}
+
+#[test]
+fn wrong_arguments() {
+  use ColorArgument::{Number, Percentage};
+  assert_eq!(Err("`rgb` takes three numbers and an optional alpha".into()), Colors::apply("rgb", &[Number(1.), Number(2.)]));
+  assert_eq!(Err("`lighten` takes a color and a percentage".into()), Colors::apply("lighten", &[Number(1.), Percentage(2.)]));
+  assert_eq!(Err("`mix` takes two colors and a percentage of the first".into()), Colors::apply("mix", &[ColorArgument::Color(Color::RED)]));
+}
+
+#[test]
+fn opacity() {
+  let diagram = create_diagram("box fill blue opacity 50%\narrow color #ff000080 opacity 0.5");
+  let Some(Node::Closed(Attributes::Closed { stroke, fill, text, .. }, ..)) = diagram.nodes.first() else { panic!("Expected box") };
+  assert_eq!((0x80, 0x80, 0x80), (stroke.a(), fill.a(), text.a()));
+  let Some(Node::Open(Attributes::Open { stroke, .. }, ..)) = diagram.nodes.last() else { panic!("Expected arrow") };
+  assert_eq!(0x40, stroke.a());
+}
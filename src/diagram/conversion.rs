@@ -2,14 +2,19 @@
 
 use log::{debug, warn};
 use pest::iterators::{Pair, Pairs};
-use pest::Parser;
-use skia_safe::{Color, Font, FontMgr, FontStyle, Size};
-
-use crate::diagram::index::ShapeName;
+use pest::{Parser, Span};
+use skia_safe::{Color, Font, FontMgr, FontStyle, Size, Vector};
+
+use crate::diagram::attributes::EdgeMovement;
+use crate::diagram::colors::{ColorArgument, Colors};
+use crate::diagram::diagnostics::{Diagnostic, Diagnostics};
+use crate::diagram::error::ParseError;
+use crate::diagram::expression::Expression;
+use crate::diagram::index::{Index, Ordinal, ShapeName};
 use crate::diagram::parser::{DiagramParser, Rule};
 use crate::diagram::rules::Rules;
-use crate::diagram::types::{Caption, Config, Displacement, Edge, EdgeDirection, Ending, Endings, Continuation, Length, Movement, ObjectEdge, Unit};
-use crate::skia::Effect;
+use crate::diagram::types::{Caption, Config, Displacement, Edge, EdgeDirection, Ending, Endings, Continuation, Length, Movement, ObjectEdge, ShapeConfig, Terminal, Unit};
+use crate::skia::{Cap, Effect, Join, Pattern, Shading, Shadow, Sketch};
 
 #[cfg(test)]
 mod tests;
@@ -19,11 +24,13 @@ pub(crate) struct Conversion;
 
 impl Conversion {
   pub(crate) fn pair_for(rule: Rule, string: &str) -> Pair<'_, Rule> {
-    Self::pairs_for(rule, string).next().unwrap()
+    Self::pairs_for(rule, string)
+      .unwrap_or_else(|error| panic!("{}", error))
+      .next().unwrap()
   }
 
-  pub(crate) fn pairs_for(rule: Rule, string: &str) -> Pairs<'_, Rule> {
-    DiagramParser::parse(rule, string).unwrap()
+  pub(crate) fn pairs_for(rule: Rule, string: &str) -> Result<Pairs<'_, Rule>, Box<ParseError>> {
+    DiagramParser::parse(rule, string).map_err(|error| Box::new(ParseError::from(error)))
   }
 
   fn next_to_f32(iter: &mut Pairs<Rule>) -> Option<f32> {
@@ -40,64 +47,152 @@ impl Conversion {
     pairs.next().map(|p| p.as_str())
   }
 
-  pub(crate) fn colors_from(pair: &Pair<Rule>, stroke: &Color) -> (Color, Color, Color) {
-    let stroke = Conversion::stroke_color_in(pair).unwrap_or(*stroke);
-    let fill = Conversion::fill_color_in(pair).unwrap_or(Color::TRANSPARENT);
-    let text_color = Conversion::text_color_in(pair).unwrap_or(Color::BLACK);
+  pub(crate) fn colors_from(pair: &Pair<Rule>, stroke: &Color, diagnostics: &mut Diagnostics) -> (Color, Color, Color) {
+    let stroke = Conversion::stroke_color_in(pair).and_then(|color| diagnostics.report(color)).unwrap_or(*stroke);
+    let fill = Conversion::fill_color_in(pair).and_then(|color| diagnostics.report(color)).unwrap_or(Color::TRANSPARENT);
+    let text_color = Conversion::text_color_in(pair).and_then(|color| diagnostics.report(color)).unwrap_or(Color::BLACK);
     (stroke, fill, text_color)
   }
 
-  pub(crate) fn stroke_color_in(pair: &Pair<Rule>) -> Option<Color> {
-    Rules::dig_rule(pair, Rule::stroke).and_then(Self::color_from)
+  pub(crate) fn stroke_color_in(pair: &Pair<Rule>) -> Option<Result<Color, Diagnostic>> {
+    Rules::dig_rule(pair, Rule::stroke).map(Self::color_from)
   }
 
-  pub(crate) fn fill_color_in(pair: &Pair<Rule>) -> Option<Color> {
-    Rules::dig_rule(pair, Rule::fill).and_then(Self::color_from)
+  pub(crate) fn fill_color_in(pair: &Pair<Rule>) -> Option<Result<Color, Diagnostic>> {
+    Rules::dig_rule(pair, Rule::fill).map(Self::color_from)
   }
 
-  pub(crate) fn text_color_in(pair: &Pair<Rule>) -> Option<Color> {
-    Rules::dig_rule(pair, Rule::text_color).and_then(Self::color_from)
+  pub(crate) fn text_color_in(pair: &Pair<Rule>) -> Option<Result<Color, Diagnostic>> {
+    Rules::dig_rule(pair, Rule::text_color).map(Self::color_from)
   }
 
   // https://www.rapidtables.com/web/color/RGB_Color.html
   // https://www.colordic.org/w (Japanese)
-  pub(crate) fn color_from(pair: Pair<Rule>) -> Option<Color> {
-    let mut pairs = pair.into_inner();
-    pairs.find_map(|pair| {
-      match pair.as_rule() {
-        Rule::id => {
-          let str = pair.as_str();
-          match str {
-            "black" => Color::BLACK,
-            "white" => Color::WHITE,
-            "red" => Color::RED,
-            "green" => Color::GREEN,
-            "yellow" => Color::YELLOW,
-            "blue" => Color::BLUE,
-            "cyan" => Color::CYAN,
-            "magenta" => Color::MAGENTA,
-            "brown" => Color::new(0xFFA52A2A),
-            "orange" => Color::new(0xFFFFA500),
-            "pink" => Color::new(0xFFFFC0CB),
-            "purple" => Color::new(0xFF800080),
-            "gray" | "grey" => Color::GRAY,
-            "dgray" | "dgrey" => Color::DARK_GRAY,
-            "lgray" => Color::LIGHT_GRAY,
-            _ => panic!("unknown color {:?}", pair)
-          }
-        }
-        Rule::rgb => {
-          let str = pair.as_str();
-          let hex = &str[1..];
-          let hex = u32::from_str_radix(hex, 16).unwrap();
-          let r = (hex >> 16) as u8;
-          let g = (hex >> 8) as u8;
-          let b = hex as u8;
-          Color::from_argb(0xFF, r, g, b)
-        }
-        _ => panic!("Unexpected rule for color {:?}", pair.as_rule())
-      }.into()
-    })
+  pub(crate) fn color_from(pair: Pair<Rule>) -> Result<Color, Diagnostic> {
+    let span = pair.as_span();
+    pair.into_inner()
+      .find(|pair| matches!(pair.as_rule(), Rule::id | Rule::rgb | Rule::mixed))
+      .map(Self::paint_from)
+      .unwrap_or_else(|| Err(Diagnostic::error(&span, "expected a color")))
+  }
+
+  /// a color name, `#rgb` to `#rrggbbaa`, or a color function like `mix(red, white, 30%)`
+  fn paint_from(pair: Pair<Rule>) -> Result<Color, Diagnostic> {
+    let span = pair.as_span();
+    match pair.as_rule() {
+      Rule::id => Self::named_color(pair.as_str())
+        .ok_or_else(|| Diagnostic::error(&span, format!("unknown color `{}`", pair.as_str()))),
+      Rule::rgb => Ok(Colors::hex(&pair.as_str()[1..]).unwrap()),
+      _ => {
+        let mut inner = pair.into_inner();
+        let function = inner.next().unwrap().as_str();
+        let arguments = inner.map(|argument| match argument.as_rule() {
+          Rule::number => Ok(ColorArgument::Number(argument.as_str().parse().unwrap())),
+          Rule::percentage => Ok(ColorArgument::Percentage(Self::percentage_from(argument))),
+          _ => Self::paint_from(argument).map(ColorArgument::Color),
+        }).collect::<Result<Vec<_>, _>>()?;
+        Colors::apply(function, &arguments).map_err(|message| Diagnostic::error(&span, message))
+      }
+    }
+  }
+
+  pub(crate) fn shading_in(pair: &Pair<Rule>, unit: &Unit, index: &mut Index) -> Option<Result<Shading, Diagnostic>> {
+    Rules::find_rule(pair, Rule::shading)
+      .map(|pair| Self::shading_from(pair, unit, index))
+  }
+
+  /// `gradient(to right, white, gray)`, `radial(white, gray)`, or `hatch`, `crosshatch` and `dots` with optional spacing, angle and color
+  pub(crate) fn shading_from(pair: Pair<Rule>, unit: &Unit, index: &mut Index) -> Result<Shading, Diagnostic> {
+    let span = pair.as_span();
+    let mut inner = pair.into_inner();
+    let kind = inner.next().unwrap().as_str();
+    let (mut angle, mut spacing, mut colors) = (None, None, vec![]);
+    for argument in inner {
+      match argument.as_rule() {
+        Rule::toward => angle = Some(Self::toward_from(argument)),
+        Rule::angle => angle = Some(argument.into_inner().next().unwrap().as_str().parse().unwrap()),
+        Rule::spacing => spacing = Some(Self::length_from(argument, unit, index).pixels()),
+        _ => colors.push(Self::paint_from(argument)?),
+      }
+    }
+    let pattern = match kind {
+      "hatch" => Pattern::Hatch,
+      "crosshatch" => Pattern::CrossHatch,
+      "dots" => Pattern::Dots,
+      _ if colors.len() < 2 || spacing.is_some() => return Err(Diagnostic::error(&span, format!("`{}` takes two or more colors", kind))),
+      "radial" if angle.is_some() => return Err(Diagnostic::error(&span, "`radial` takes no direction")),
+      "radial" => return Ok(Shading::Radial(colors)),
+      _ => return Ok(Shading::Linear(angle.unwrap_or(180.), colors)),
+    };
+    if colors.len() > 1 {
+      return Err(Diagnostic::error(&span, format!("`{}` takes one color", kind)));
+    }
+    if spacing.is_some_and(|spacing| spacing <= 0.) {
+      return Err(Diagnostic::error(&span, format!("`{}` takes a spacing of more than 0", kind)));
+    }
+    let angle = angle.unwrap_or(if pattern == Pattern::Dots { 0. } else { 45. });
+    Ok(Shading::Pattern { pattern, spacing: spacing.unwrap_or(6.), angle, color: colors.pop() })
+  }
+
+  /// `shadow` with an offset, one length or two, a blur and a color, or `glow` with a blur and a color, in the stroke color unless given
+  pub(crate) fn shadow_from(pair: Pair<Rule>, unit: &Unit, index: &mut Index) -> Result<Shadow, Diagnostic> {
+    let span = pair.as_span();
+    let mut inner = pair.into_inner();
+    let kind = inner.next().unwrap().as_str();
+    let (mut lengths, mut colors) = (vec![], vec![]);
+    for argument in inner {
+      match argument.as_rule() {
+        Rule::size => lengths.push(Self::length_from(argument, unit, index).pixels()),
+        _ => colors.push(Self::paint_from(argument)?),
+      }
+    }
+    if colors.len() > 1 {
+      return Err(Diagnostic::error(&span, format!("`{}` takes one color", kind)));
+    }
+    let color = colors.pop();
+    if kind == "glow" {
+      return match lengths.as_slice() {
+        [] => Ok(Shadow { offset: Vector::default(), blur: 6., color }),
+        [blur] => Ok(Shadow { offset: Vector::default(), blur: *blur, color }),
+        _ => Err(Diagnostic::error(&span, "`glow` takes a blur and a color")),
+      };
+    }
+    let offset = match lengths.as_slice() {
+      [] => Vector::new(4., 4.),
+      [length] => Vector::new(*length, *length),
+      [x, y] | [x, y, _] => Vector::new(*x, *y),
+      _ => return Err(Diagnostic::error(&span, "`shadow` takes an offset, a blur and a color")),
+    };
+    let blur = lengths.get(2).copied().unwrap_or(4.);
+    Ok(Shadow { offset, blur, color: color.or(Some(Color::from_argb(0x66, 0, 0, 0))) })
+  }
+
+  /// `to top` is 0 degrees, `to right` 90, and `to bottom right` in between
+  fn toward_from(pair: Pair<Rule>) -> f32 {
+    let (x, y) = pair.into_inner().fold((0., 0.), |(x, y), side| match side.as_str() {
+      "top" => (x, y - 1.),
+      "bottom" => (x, y + 1.),
+      "left" => (x - 1., y),
+      _ => (x + 1., y),
+    });
+    (f32::atan2(x, -y).to_degrees() + 360.) % 360.
+  }
+
+  fn percentage_from(pair: Pair<Rule>) -> f32 {
+    pair.as_str().trim_end_matches('%').parse().unwrap()
+  }
+
+  /// `opacity 50%` or `opacity 0.5`
+  pub(crate) fn opacity_from(pair: Pair<Rule>) -> f32 {
+    let value = pair.into_inner().next().unwrap();
+    match value.as_rule() {
+      Rule::percentage => Self::percentage_from(value) / 100.,
+      _ => value.as_str().parse().unwrap(),
+    }
+  }
+
+  pub(crate) fn named_color(name: &str) -> Option<Color> {
+    Colors::named(name)
   }
 
   pub(crate) fn str_for<'a>(pair: &Pair<'a, Rule>, rule: Rule) -> Option<&'a str> {
@@ -145,51 +240,69 @@ impl Conversion {
   }
 
   #[allow(clippy::unwrap_or_default)]
-  pub(crate) fn caption(pair: &Pair<Rule>, config: &Config) -> Option<Caption> {
+  pub(crate) fn caption(pair: &Pair<Rule>, config: &Config, shape: &ShapeConfig, index: &Index) -> Option<Caption> {
     Rules::find_rule(pair, Rule::caption)
-      .map(|caption| { Self::caption_from(caption, config) })
+      .map(|caption| { Self::caption_from(caption, config, shape, index) })
   }
 
-  pub(crate) fn font_from(pair: Pair<Rule>, unit: &Unit) -> Font {
+  pub(crate) fn font_from(pair: Pair<Rule>, unit: &Unit, index: &mut Index) -> Result<Font, Diagnostic> {
+    let span = pair.as_span();
     let mut family = "Helvetica".to_owned();
     let mut size = 17.;
 
     let pairs = pair.into_inner();
     pairs.for_each(|pair| match pair.as_rule() {
       Rule::string => {
-        family = Self::string_from(pair);
+        family = index.interpolate(&Self::string_from(pair));
       }
       Rule::size => {
-        let length = Conversion::length_from(pair, unit);
+        let length = Conversion::length_from(pair, unit, index);
         size = length.points();
       }
       _ => warn!("Unexpected rule for font {:?}", pair.as_rule())
     });
 
-    let typeface = FontMgr::default().match_family_style(family, FontStyle::default()).unwrap();
-    Font::from_typeface(typeface, size)
+    Self::font_for(&family, size, &span)
+  }
+
+  fn font_for(family: &str, size: f32, span: &Span) -> Result<Font, Diagnostic> {
+    let typeface = FontMgr::default().match_family_style(family, FontStyle::default())
+      .ok_or_else(|| Diagnostic::error(span, format!("unknown font `{}`", family)))?;
+    Ok(Font::from_typeface(typeface, size))
   }
 
-  pub(crate) fn caption_from(pair: Pair<Rule>, config: &Config) -> Caption {
+  /// `set style sketch seed 7 hachure "Comic Neue"`, with the handwriting font at the size of `font`
+  pub(crate) fn sketch_from(pair: Pair<Rule>, font: &Font, index: &mut Index) -> (Sketch, Option<Result<Font, Diagnostic>>) {
+    let mut sketch = Sketch { seed: 1, hachure: false };
+    let mut handwriting = None;
+    for pair in pair.into_inner() {
+      match pair.as_rule() {
+        Rule::seed => match pair.clone().into_inner().as_str().parse::<u32>() {
+          Ok(seed) => sketch.seed = seed,
+          Err(_) => index.diagnostics.error(&pair, format!("seed can't be more than {}", u32::MAX)),
+        },
+        Rule::hachure => sketch.hachure = true,
+        _ => {
+          let span = pair.as_span();
+          let family = index.interpolate(&Self::string_from(pair));
+          handwriting = Some(Self::font_for(&family, font.size(), &span));
+        }
+      }
+    }
+    (sketch, handwriting)
+  }
+
+  pub(crate) fn caption_from(pair: Pair<Rule>, config: &Config, shape: &ShapeConfig, index: &Index) -> Caption {
     let mut text: Option<String> = None;
-    let mut alignment: Option<(Edge, Edge)> = None;
-    let mut opaque = false;
+    let mut alignment = shape.caption.clone();
+    let mut opaque = shape.opaque;
 
     let pairs = pair.into_inner();
     pairs.for_each(|pair| match pair.as_rule() {
       Rule::string => {
-        text = Self::string_from(pair).into();
-      }
-      Rule::alignment => {
-        let string = pair.as_str();
-        alignment = match string {
-          "ljust" => (Edge::left(), Edge::left()),
-          "top" => (Edge::above(), Edge::above()),
-          "center" => (Edge::center(), Edge::center()),
-          "bottom" => (Edge::below(), Edge::below()),
-          _ => (string.into(), Edge::from(string).mirror())
-        }.into();
+        text = index.interpolate(&Self::string_from(pair)).into();
       }
+      Rule::alignment => alignment = Self::alignment_from(pair).into(),
       Rule::opaque => { opaque = true }
       _ => panic!("Unexpected rule for caption {:?}", pair.as_rule())
     });
@@ -200,15 +313,27 @@ impl Conversion {
     Caption { text, rect_edge, caption_edge, bounds, opaque }
   }
 
+  /// the edge of the shape and the edge of the caption that meet
+  pub(crate) fn alignment_from(pair: Pair<Rule>) -> (Edge, Edge) {
+    let string = pair.as_str();
+    match string {
+      "ljust" => (Edge::left(), Edge::left()),
+      "top" => (Edge::above(), Edge::above()),
+      "center" => (Edge::center(), Edge::center()),
+      "bottom" => (Edge::below(), Edge::below()),
+      _ => (string.into(), Edge::from(string).mirror())
+    }
+  }
+
   pub(crate) fn endings(pair: &Pair<Rule>) -> Option<Endings> {
     Rules::find_rule(pair, Rule::endings)
       .map(Self::endings_from)
   }
 
-  pub(crate) fn movement_from(pair: Pair<Rule>, unit: &Unit) -> Movement {
+  pub(crate) fn movement_from(pair: Pair<Rule>, unit: &Unit, index: &mut Index) -> Movement {
     match pair.as_rule() {
       Rule::rel_movement => {
-        Self::rel_movement_from(pair, unit)
+        Self::rel_movement_from(pair, unit, index)
       }
       Rule::abs_movement => {
         Self::abs_movement_from(pair)
@@ -217,8 +342,8 @@ impl Conversion {
     }
   }
 
-  pub(crate) fn rel_movement_from(pair: Pair<Rule>, unit: &Unit) -> Movement {
-    let displacement = Self::displacement_from(pair, unit);
+  pub(crate) fn rel_movement_from(pair: Pair<Rule>, unit: &Unit, index: &mut Index) -> Movement {
+    let displacement = Self::displacement_from(pair, unit, index);
     Movement::Relative { displacement }
   }
 
@@ -230,52 +355,52 @@ impl Conversion {
     Movement::ObjectStart { object }
   }
 
-  pub(crate) fn length_in(pair: &Pair<Rule>, rule: Rule, unit: &Unit) -> Option<Length> {
+  pub(crate) fn length_in(pair: &Pair<Rule>, rule: Rule, unit: &Unit, index: &mut Index) -> Option<Length> {
     Rules::dig_rule(pair, rule)
-      .map(|pair| Self::length_from(pair, unit))
+      .map(|pair| Self::length_from(pair, unit, index))
   }
 
-  pub(crate) fn length_from(pair: Pair<Rule>, unit: &Unit) -> Length {
-    let mut width = pair.into_inner();
-    let length = Self::next_to_f32(&mut width).unwrap();
-    let unit = Self::next_to_string(&mut width).map(|str| str.into()).unwrap_or(*unit);
-    Length::new(length, unit)
+  /// evaluate the expression in a length rule
+  pub(crate) fn length_from(pair: Pair<Rule>, unit: &Unit, index: &mut Index) -> Length {
+    let expression = pair.into_inner().next().unwrap();
+    Expression::new(unit, index).length_from(expression)
   }
 
-  pub(crate) fn sized_from(pair: Pair<Rule>, unit: &Unit) -> Size {
+  /// `width x height`, where the unit of a plain height also applies to the width, as in `0.75x0.5in`
+  pub(crate) fn sized_from(pair: Pair<Rule>, unit: &Unit, index: &mut Index) -> Size {
     let mut sized = pair.into_inner();
-    let width = Self::next_to_f32(&mut sized).expect("width as f32");
-    let height = Self::next_to_f32(&mut sized).expect("height as f32");
-    let unit = Self::next_to_string(&mut sized).map(|str| str.into()).unwrap_or(*unit);
-    Size::new(Length::pixels_for(width, unit), Length::pixels_for(height, unit))
+    let (width, height) = (sized.next().unwrap(), sized.next().unwrap());
+    let (height, named) = match height.as_rule() {
+      Rule::lone_quantity => {
+        let quantity = height.into_inner().next().unwrap();
+        let unit = quantity.clone().into_inner().nth(1);
+        (quantity, unit)
+      }
+      _ => (height, sized.next()),
+    };
+    let unit = named.and_then(|unit| Unit::try_from(unit.as_str()).ok()).unwrap_or(*unit);
+    let mut expression = Expression::new(&unit, index);
+    Size::new(expression.length_from(width).pixels(), expression.length_from(height).pixels())
   }
 
-  pub(crate) fn radius_into(attributes: &Pair<Rule>, unit: &Unit) -> Option<f32> {
-    Conversion::length_in(attributes, Rule::radius, unit)
+  pub(crate) fn radius_into(attributes: &Pair<Rule>, unit: &Unit, index: &mut Index) -> Option<f32> {
+    Conversion::length_in(attributes, Rule::radius, unit, index)
       .map(|length| length.pixels())
   }
 
-  pub(crate) fn width_into(attributes: &Pair<Rule>, unit: &Unit) -> Option<f32> {
-    Conversion::length_in(attributes, Rule::width, unit)
+  pub(crate) fn width_into(attributes: &Pair<Rule>, unit: &Unit, index: &mut Index) -> Option<f32> {
+    Conversion::length_in(attributes, Rule::width, unit, index)
       .map(|length| length.pixels())
   }
 
-  pub(crate) fn length_from_(pair: Pair<Rule>, unit: &Unit, size: f32) -> Length {
-    let mut pairs = pair.into_inner();
-    let factor = Self::next_to_f32(&mut pairs).unwrap();
-    match Self::next_to_string(&mut pairs).map(|str| str.into()) {
-      Some(Unit::Unit) => {
-        Length::new(factor * size, Unit::Px)
-      }
-      Some(unit) => {
-        Length::new(factor, unit)
-      }
-      None => Length::new(factor, *unit)
-    }
+  /// like `length_from`, with `u` standing for `size`
+  pub(crate) fn length_from_(pair: Pair<Rule>, unit: &Unit, size: f32, index: &mut Index) -> Length {
+    let expression = pair.into_inner().next().unwrap();
+    Expression::sized(unit, size, index).length_from(expression)
   }
 
-  pub(crate) fn length_into(attributes: &Pair<Rule>, unit: &Unit) -> Option<f32> {
-    Conversion::length_in(attributes, Rule::length, unit)
+  pub(crate) fn length_into(attributes: &Pair<Rule>, unit: &Unit, index: &mut Index) -> Option<f32> {
+    Conversion::length_in(attributes, Rule::length, unit, index)
       .map(|length| length.pixels())
   }
 
@@ -286,18 +411,32 @@ impl Conversion {
   fn object_edge_from_default(pair: Pair<Rule>, default: &Edge) -> ObjectEdge {
     let mut inner = pair.into_inner();
     let id = Self::next_to_string(&mut inner).unwrap();
-    let edge = Self::next_to_string(&mut inner);
-    let edge = edge.map(Edge::from).unwrap_or(default.clone());
-    ObjectEdge::new(id, edge)
+    let (edge, terminal) = match inner.next() {
+      Some(pair) if pair.as_rule() == Rule::terminal => (default.clone(), Some(Self::terminal_from(pair))),
+      Some(pair) => (Edge::from(pair.as_str()), None),
+      None => (default.clone(), None),
+    };
+    ObjectEdge { terminal, ..ObjectEdge::new(id, edge) }
   }
 
   fn object_edge_from_degrees(pair: Pair<Rule>) -> ObjectEdge {
     let mut inner = pair.into_inner();
 
     let id = Self::next_to_string(&mut inner).unwrap();
-    let edge = inner.next().map(Self::edge_from).unwrap_or(Edge::center());
+    let (edge, terminal) = match inner.next() {
+      Some(pair) if pair.as_rule() == Rule::terminal => (Edge::center(), Some(Self::terminal_from(pair))),
+      Some(pair) => (Self::edge_from(pair), None),
+      None => (Edge::center(), None),
+    };
 
-    ObjectEdge::new(id, edge)
+    ObjectEdge { terminal, ..ObjectEdge::new(id, edge) }
+  }
+
+  fn terminal_from(pair: Pair<Rule>) -> Terminal {
+    match pair.as_str() {
+      ".start" => Terminal::Start,
+      _ => Terminal::End,
+    }
   }
 
   pub(crate) fn continuation_in(pair: &Pair<Rule>) -> Option<Continuation> {
@@ -305,20 +444,21 @@ impl Conversion {
       .map(|pair| Continuation::new(pair.as_str()))
   }
 
-  pub(crate) fn fraction_edge_for(pair: &Pair<Rule>, rule: Rule) -> Option<ObjectEdge> {
+  pub(crate) fn fraction_edge_for(pair: &Pair<Rule>, rule: Rule, index: &mut Index) -> Option<ObjectEdge> {
     Rules::find_rule(pair, rule)
-      .map(Self::fraction_edge_from)
+      .map(|pair| Self::fraction_edge_from(pair, index))
   }
 
-  pub(crate) fn fraction_edge_from(pair: Pair<Rule>) -> ObjectEdge {
+  pub(crate) fn fraction_edge_from(pair: Pair<Rule>, index: &mut Index) -> ObjectEdge {
     let mut fraction: Option<f32> = None;
     let mut object: Option<ObjectEdge> = None;
 
     pair.into_inner().for_each(|pair| match pair.as_rule() {
       Rule::fraction => {
-        let mut inner = pair.into_inner();
-        let x = Self::next_to_f32(&mut inner).unwrap();
-        let y = Self::next_to_f32(&mut inner).unwrap();
+        let mut inner = pair.into_inner()
+          .map(|part| Expression::amount(&Expression::new(&Unit::Px, index).value_from(part)));
+        let x = inner.next().unwrap();
+        let y = inner.next().unwrap();
         fraction = Some(x / y);
       }
       Rule::object_edge => {
@@ -337,23 +477,23 @@ impl Conversion {
     object
   }
 
-  pub(crate) fn displacement_for(pair: &Pair<Rule>, rule: Rule, unit: &Unit) -> Option<Displacement> {
+  pub(crate) fn displacement_for(pair: &Pair<Rule>, rule: Rule, unit: &Unit, index: &mut Index) -> Option<Displacement> {
     Rules::find_rule(pair, rule)
-      .map(|pair| Self::displacement_from(pair, unit))
+      .map(|pair| Self::displacement_from(pair, unit, index))
   }
 
-  pub(crate) fn displacement_from(pair: Pair<Rule>, unit: &Unit) -> Displacement {
+  pub(crate) fn displacement_from(pair: Pair<Rule>, unit: &Unit, index: &mut Index) -> Displacement {
     let length = Rules::find_rule(&pair, Rule::offset)
-      .map(|pair| Self::length_from(pair, unit)).unwrap();
+      .map(|pair| Self::length_from(pair, unit, index)).unwrap();
     let direction = Self::str_for(&pair, Rule::direction).unwrap();
     Displacement { length, edge: direction.into() }
   }
 
-  pub(crate) fn displacements_from(pair: &Pair<Rule>, unit: &Unit) -> Option<Vec<Displacement>> {
+  pub(crate) fn displacements_from(pair: &Pair<Rule>, unit: &Unit, index: &mut Index) -> Option<Vec<Displacement>> {
     Rules::find_rule(pair, Rule::movements)
       .map(|pair| {
         pair.into_inner()
-          .map(|inner| Self::displacement_from(inner, unit))
+          .map(|inner| Self::displacement_from(inner, unit, index))
           .collect::<Vec<_>>()
       })
   }
@@ -396,12 +536,13 @@ impl Conversion {
     hour * 30 + minutes as i32
   }
 
-  pub(crate) fn location_for(pair: &Pair<Rule>, unit: &Unit) -> Option<(Edge, Vec<Displacement>, ObjectEdge)> {
+  pub(crate) fn location_for(pair: &Pair<Rule>, unit: &Unit, index: &mut Index) -> Option<Result<EdgeMovement, Diagnostic>> {
     Rules::find_rule(pair, Rule::location)
-      .map(|p| Self::location_from(p, unit))
+      .map(|p| Self::location_from(p, unit, index))
   }
 
-  pub(crate) fn location_from(pair: Pair<Rule>, unit: &Unit) -> (Edge, Vec<Displacement>, ObjectEdge) {
+  pub(crate) fn location_from(pair: Pair<Rule>, unit: &Unit, index: &mut Index) -> Result<EdgeMovement, Diagnostic> {
+    let span = pair.as_span();
     let mut object: Option<ObjectEdge> = None;
     let mut directions: Vec<Displacement> = vec![];
     let mut edge: Option<Edge> = None;
@@ -410,18 +551,25 @@ impl Conversion {
       match pair.as_rule() {
         Rule::edge_point => { edge = Some(Self::edge_from(pair)); }
         Rule::rel_movement => {
-          let movement = Self::displacement_from(pair, unit);
+          let movement = Self::displacement_from(pair, unit, index);
           directions.push(movement);
         }
-        Rule::last_object => { object = Some(Self::fraction_edge_from(pair)); }
-        Rule::from_object => { object = Some(Self::fraction_edge_from(pair)); }
+        Rule::last_object => {
+          // `last box` counts within the group, like the ordinal it is
+          let mut last = Self::fraction_edge_from(pair, index);
+          if ShapeName::some(&last.id).is_some() {
+            last.id = format!("last {}", last.id);
+          }
+          object = Some(last);
+        }
+        Rule::from_object => { object = Some(Self::fraction_edge_from(pair, index)); }
         _ => {}
       }
     };
 
     if let Some(movement) = directions.first() {
       if let Some(object) = object.as_mut() {
-        if ShapeName::some(object.id.as_str()).is_some() {
+        if Ordinal::parse(&object.id).is_some() && object.edge == Edge::center() {
           object.edge = movement.edge.clone()
         }
       }
@@ -433,51 +581,98 @@ impl Conversion {
       }
     }
 
-    (edge.unwrap_or_default(), directions, object.unwrap())
+    let object = object.ok_or_else(|| Diagnostic::error(&span, "expected an object to place at"))?;
+    Ok((edge.unwrap_or_default(), directions, object))
   }
 
   pub(crate) fn endings_from(pair: Pair<Rule>) -> Endings {
     let mut start = Ending::None;
     let mut end = Ending::None;
+    let mut middle = Ending::None;
 
     pair.into_inner().for_each(|pair| match pair.as_rule() {
       Rule::left_end => start = Ending::from(pair.as_str()),
       Rule::right_end => end = Ending::from(pair.as_str()),
+      Rule::middle => middle = Ending::from(pair.as_str()),
       _ => panic!(" {:?}", pair.as_rule())
     });
 
-    Endings { start, end }
+    Endings { start, end, middle }
   }
 
-  pub(crate) fn thickness_for(pair: &Pair<Rule>) -> f32 {
+  pub(crate) fn thickness_for(pair: &Pair<Rule>, unit: &Unit, index: &mut Index) -> f32 {
     Rules::find_rule(pair, Rule::thickness)
-      .map(Self::thickness_from)
+      .and_then(|pair| {
+        let thickness = Self::thickness_from(pair, unit, index);
+        index.diagnostics.report(thickness)
+      })
       .unwrap_or(1.0)
   }
 
-  pub(crate) fn thickness_from(pair: Pair<Rule>) -> f32 {
-    match pair.as_str() {
+  /// a name like `thick`, or `thickness 2.5px`
+  pub(crate) fn thickness_from(pair: Pair<Rule>, unit: &Unit, index: &mut Index) -> Result<f32, Diagnostic> {
+    if let Some(thickness) = Self::named_thickness(pair.as_str()) {
+      return Ok(thickness);
+    }
+    let span = pair.as_span();
+    let thickness = Self::length_from(pair.into_inner().next().unwrap(), unit, index).pixels();
+    if thickness < 0. {
+      return Err(Diagnostic::error(&span, "thickness can't be negative"));
+    }
+    Ok(thickness)
+  }
+
+  pub(crate) fn named_thickness(name: &str) -> Option<f32> {
+    Some(match name {
       "invisible" | "invis" | "nostroke" => 0.0,
       "thin" => 1.0,
       "normal" => 2.0,
       "thick" => 3.0,
       "thicker" => 4.0,
       "thickest" => 6.0,
-      _ => panic!("unknown thickness {:?}", pair.as_str()),
-    }
+      _ => return None,
+    })
   }
 
-  pub(crate) fn effect_for(pair: &Pair<Rule>) -> Effect {
+  pub(crate) fn effect_for(pair: &Pair<Rule>, unit: &Unit, index: &mut Index) -> Effect {
     Rules::find_rule(pair, Rule::effect)
-      .map(Self::effect_from)
+      .and_then(|pair| {
+        let effect = Self::effect_from(pair, unit, index);
+        index.diagnostics.report(effect)
+      })
       .unwrap_or(Effect::Solid)
   }
 
-  pub(crate) fn effect_from(pair: Pair<Rule>) -> Effect {
+  pub(crate) fn effect_from(pair: Pair<Rule>, unit: &Unit, index: &mut Index) -> Result<Effect, Diagnostic> {
     match pair.as_str() {
-      "dashed" => Effect::Dashed,
-      "dotted" => Effect::Dotted,
-      _ => panic!("Unknown effect {:?}", pair.as_str()),
+      "dashed" => Ok(Effect::Dashed),
+      "dotted" => Ok(Effect::Dotted),
+      "solid" => Ok(Effect::Solid),
+      _ => {
+        let span = pair.as_span();
+        let dash = pair.into_inner().next().unwrap();
+        let intervals = dash.into_inner().map(|size| Self::length_from(size, unit, index).pixels()).collect::<Vec<_>>();
+        if intervals.iter().any(|interval| *interval < 0.) || intervals.iter().sum::<f32>() <= 0. {
+          return Err(Diagnostic::error(&span, "dashes should be lengths of 0 or more, and not all 0"));
+        }
+        Ok(Effect::Dash(intervals))
+      }
+    }
+  }
+
+  pub(crate) fn cap_from(pair: Pair<Rule>) -> Cap {
+    match pair.into_inner().next().unwrap().as_str() {
+      "round" => Cap::Round,
+      "square" => Cap::Square,
+      _ => Cap::Butt,
+    }
+  }
+
+  pub(crate) fn join_from(pair: Pair<Rule>) -> Join {
+    match pair.into_inner().next().unwrap().as_str() {
+      "round" => Join::Round,
+      "bevel" => Join::Bevel,
+      _ => Join::Miter,
     }
   }
 }
@@ -41,6 +41,9 @@ mod string {
 }
 
 mod thickness {
+  use crate::diagram::index::Index;
+  use crate::diagram::types::Unit;
+
   use super::*;
 
   #[test]
@@ -49,9 +52,55 @@ mod thickness {
     assert_eq!(thickness, 3.);
   }
 
+  #[test]
+  fn numeric() {
+    assert_eq!(2.5, subject("thickness 2.5px"));
+    assert_eq!(8., subject("thickness 6pt"));
+    let negative = Conversion::thickness_from(Conversion::pair_for(Rule::thickness, "thickness -2px"), &Unit::Px, &mut Index::default());
+    assert_eq!("thickness can't be negative", negative.unwrap_err().message);
+  }
+
   fn subject(string: &str) -> f32 {
     let pair = Conversion::pair_for(Rule::thickness, string);
-    Conversion::thickness_from(pair)
+    Conversion::thickness_from(pair, &Unit::Px, &mut Index::default()).unwrap()
+  }
+}
+
+mod effects {
+  use crate::diagram::index::Index;
+  use crate::diagram::types::Unit;
+  use crate::skia::{Cap, Effect, Join};
+
+  use super::*;
+
+  #[test]
+  fn named() {
+    assert_eq!(Effect::Dashed, subject("dashed"));
+    assert_eq!(Effect::Solid, subject("solid"));
+  }
+
+  #[test]
+  fn dash_array() {
+    assert_eq!(Effect::Dash(vec![6., 3.]), subject("dash 6px 3px"));
+    assert_eq!(Effect::Dash(vec![8., 2., 1., 2.]), subject("dash 6pt 2 1 2"));
+  }
+
+  #[test]
+  fn caps_and_joins() {
+    assert_eq!(Cap::Round, Conversion::cap_from(Conversion::pair_for(Rule::cap, "cap round")));
+    assert_eq!(Join::Bevel, Conversion::join_from(Conversion::pair_for(Rule::join, "join bevel")));
+  }
+
+  #[test]
+  fn nothing_to_dash() {
+    let message = |string: &str| Conversion::effect_from(Conversion::pair_for(Rule::effect, string), &Unit::Px, &mut Index::default()).unwrap_err().message;
+    assert_eq!("dashes should be lengths of 0 or more, and not all 0", message("dash 0 0"));
+    assert_eq!("dashes should be lengths of 0 or more, and not all 0", message("dash -6px 3px"));
+  }
+
+  fn subject(string: &str) -> Effect {
+    let pair = Conversion::pair_for(Rule::effect, string);
+    Conversion::effect_from(pair, &Unit::Px, &mut Index::default()).unwrap()
   }
 }
 
@@ -63,7 +112,20 @@ mod endings {
   #[test]
   fn mixed_endings() {
     let endings = subject("*->");
-    assert_eq!(endings, Endings { start: Ending::Dot, end: Ending::Arrow });
+    assert_eq!(endings, Endings { start: Ending::Dot, end: Ending::Arrow, middle: Ending::None });
+  }
+
+  #[test]
+  fn uml_and_crows_feet() {
+    assert_eq!(Endings { start: Ending::Diamond, end: Ending::Open, middle: Ending::None }, subject("<*>->>"));
+    assert_eq!(Endings { start: Ending::One, end: Ending::Many, middle: Ending::None }, subject("||-{"));
+    assert_eq!(Endings { start: Ending::Half, end: Ending::Bar, middle: Ending::None }, subject("\\-|"));
+  }
+
+  #[test]
+  fn in_the_middle() {
+    assert_eq!(Endings { start: Ending::None, end: Ending::Arrow, middle: Ending::Hollow }, subject("-|>->"));
+    assert_eq!(Endings { start: Ending::None, end: Ending::None, middle: Ending::Arrow }, subject("->-"));
   }
 
   fn subject(string: &str) -> Endings {
@@ -76,26 +138,131 @@ mod colors {
   use skia_safe::Color;
 
   use crate::diagram::conversion::Conversion;
+  use crate::diagram::diagnostics::Diagnostic;
   use crate::diagram::parser::Rule;
 
   #[test]
   fn named_color() {
     let color = subject("color red");
-    assert_eq!(color, Some(Color::RED));
+    assert_eq!(color, Ok(Color::RED));
   }
 
   #[test]
   fn rgb_color() {
     let color = subject("color #645590");
-    assert_eq!(color, Some(Color::from(0xFF645590)));
+    assert_eq!(color, Ok(Color::from(0xFF645590)));
   }
 
-  fn subject(string: &str) -> Option<Color> {
+  #[test]
+  fn short_and_transparent_hex() {
+    assert_eq!(Ok(Color::from(0xFFFFCC00)), subject("color #fc0"));
+    assert_eq!(Ok(Color::from(0x80645590)), subject("color #64559080"));
+  }
+
+  #[test]
+  fn functions() {
+    assert_eq!(Ok(Color::from(0x800A141E)), subject("color rgba(10, 20, 30, 50%)"));
+    assert_eq!(Ok(Color::from(0xFFFFB3B3)), subject("color mix(red, white, 30%)"));
+    assert_eq!(Ok(Color::from(0xFF9999FF)), subject("color lighten(mix(blue, white, 100%), 30%)"));
+    assert_eq!("`hsl` takes a hue, two percentages and an optional alpha", subject("color hsl(red)").unwrap_err().message);
+    assert_eq!("unknown color `mauve`", subject("color mix(mauve, red)").unwrap_err().message);
+  }
+
+  #[test]
+  fn unknown_color() {
+    let error = subject("color mauve").unwrap_err();
+    assert_eq!(error.message, "unknown color `mauve`");
+    assert_eq!((error.line, error.column), (1, 7));
+  }
+
+  fn subject(string: &str) -> Result<Color, Diagnostic> {
     let pair = Conversion::pair_for(Rule::stroke, string);
     Conversion::color_from(pair)
   }
 }
 
+mod shadings {
+  use skia_safe::Color;
+
+  use crate::diagram::conversion::Conversion;
+  use crate::diagram::diagnostics::Diagnostic;
+  use crate::diagram::index::Index;
+  use crate::diagram::parser::Rule;
+  use crate::diagram::types::Unit;
+  use crate::skia::{Pattern, Shading};
+
+  #[test]
+  fn gradients() {
+    assert_eq!(Ok(Shading::Linear(180., vec![Color::WHITE, Color::from(0xFFCCCCCC)])), subject("fill gradient(#fff, #ccc)"));
+    assert_eq!(Ok(Shading::Linear(90., vec![Color::RED, Color::BLUE])), subject("fill gradient(to right, red, blue)"));
+    assert_eq!(Ok(Shading::Linear(315., vec![Color::RED, Color::BLUE])), subject("fill gradient(to top left, red, blue)"));
+    assert_eq!(Ok(Shading::Linear(30., vec![Color::RED, Color::BLUE])), subject("fill gradient(30deg, red, blue)"));
+    assert_eq!(Ok(Shading::Radial(vec![Color::WHITE, Color::RED, Color::BLACK])), subject("fill radial(white, red, black)"));
+  }
+
+  #[test]
+  fn patterns() {
+    let hatch = Shading::Pattern { pattern: Pattern::Hatch, spacing: 6., angle: 45., color: None };
+    assert_eq!(Ok(hatch), subject("fill hatch()"));
+    let crosshatch = Shading::Pattern { pattern: Pattern::CrossHatch, spacing: 4., angle: 0., color: Some(Color::RED) };
+    assert_eq!(Ok(crosshatch), subject("fill crosshatch(4px, 0deg, red)"));
+    let dots = Shading::Pattern { pattern: Pattern::Dots, spacing: 48., angle: 0., color: None };
+    assert_eq!(Ok(dots), subject("fill dots(0.5in)"));
+  }
+
+  #[test]
+  fn wrong_arguments() {
+    assert_eq!("`gradient` takes two or more colors", subject("fill gradient(to right, red)").unwrap_err().message);
+    assert_eq!("`radial` takes no direction", subject("fill radial(to top, red, blue)").unwrap_err().message);
+    assert_eq!("`hatch` takes one color", subject("fill hatch(red, blue)").unwrap_err().message);
+    assert_eq!("unknown color `mauve`", subject("fill dots(mauve)").unwrap_err().message);
+    assert_eq!("`dots` takes a spacing of more than 0", subject("fill dots(0px)").unwrap_err().message);
+    assert_eq!("`hatch` takes a spacing of more than 0", subject("fill hatch(-4px, red)").unwrap_err().message);
+  }
+
+  fn subject(string: &str) -> Result<Shading, Diagnostic> {
+    let pair = Conversion::pair_for(Rule::fill, string);
+    Conversion::shading_in(&pair, &Unit::Px, &mut Index::default()).unwrap()
+  }
+}
+
+mod shadows {
+  use skia_safe::{Color, Vector};
+
+  use crate::diagram::conversion::Conversion;
+  use crate::diagram::diagnostics::Diagnostic;
+  use crate::diagram::index::Index;
+  use crate::diagram::parser::Rule;
+  use crate::diagram::types::Unit;
+  use crate::skia::Shadow;
+
+  #[test]
+  fn shadow() {
+    let gray = Some(Color::from_argb(0x66, 0, 0, 0));
+    assert_eq!(Ok(Shadow { offset: Vector::new(4., 4.), blur: 4., color: gray }), subject("shadow"));
+    assert_eq!(Ok(Shadow { offset: Vector::new(2., 2.), blur: 4., color: gray }), subject("shadow(2px)"));
+    assert_eq!(Ok(Shadow { offset: Vector::new(3., -1.), blur: 8., color: Some(Color::BLUE) }), subject("shadow(3px, -1px, 8px, blue)"));
+  }
+
+  #[test]
+  fn glow() {
+    assert_eq!(Ok(Shadow { offset: Vector::default(), blur: 6., color: None }), subject("glow"));
+    assert_eq!(Ok(Shadow { offset: Vector::default(), blur: 10., color: Some(Color::YELLOW) }), subject("glow(yellow, 10px)"));
+  }
+
+  #[test]
+  fn wrong_arguments() {
+    assert_eq!("`glow` takes a blur and a color", subject("glow(1px, 2px)").unwrap_err().message);
+    assert_eq!("`shadow` takes an offset, a blur and a color", subject("shadow(1px, 2px, 3px, 4px)").unwrap_err().message);
+    assert_eq!("`shadow` takes one color", subject("shadow(red, blue)").unwrap_err().message);
+  }
+
+  fn subject(string: &str) -> Result<Shadow, Diagnostic> {
+    let pair = Conversion::pair_for(Rule::shadow, string);
+    Conversion::shadow_from(pair, &Unit::Px, &mut Index::default())
+  }
+}
+
 mod object_edge_degrees {
   use super::*;
 
@@ -136,6 +303,9 @@ mod object_edge {
 }
 
 mod fractions {
+  use crate::diagram::expression::Value;
+  use crate::diagram::index::Index;
+
   use super::*;
 
   #[test]
@@ -154,8 +324,19 @@ mod fractions {
     assert_eq!(object, ObjectEdge::new("a", edge));
   }
 
+  #[test]
+  fn with_variable() {
+    let mut index = Index::default();
+    index.assign("i", Value::Number(3.));
+    let pair = Conversion::pair_for(Rule::open_attributes, "from $i/4 a.w");
+    let object = Conversion::fraction_edge_for(&pair, Rule::source, &mut index).unwrap();
+    let mut edge = Edge::left();
+    edge.y = 0.25;
+    assert_eq!(object, ObjectEdge::new("a", edge));
+  }
+
   fn subject(string: &str) -> Option<ObjectEdge> {
     let pair = Conversion::pair_for(Rule::open_attributes, string);
-    Conversion::fraction_edge_for(&pair, Rule::source)
+    Conversion::fraction_edge_for(&pair, Rule::source, &mut Index::default())
   }
 }
\ No newline at end of file
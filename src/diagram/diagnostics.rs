@@ -0,0 +1,156 @@
+use std::fmt::{Display, Formatter};
+
+use pest::iterators::Pair;
+use pest::Span;
+
+use crate::diagram::error::{write_location, write_snippet};
+use crate::diagram::parser::Rule;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+  Error,
+  Warning,
+}
+
+/// A problem found while laying out a statement that parsed fine
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+  pub severity: Severity,
+  pub message: String,
+  pub span: (usize, usize),
+  pub line: usize,
+  pub column: usize,
+  pub snippet: String,
+  /// the included file the problem is in, when there is one
+  pub file: Option<String>,
+}
+
+impl Diagnostic {
+  pub fn error(span: &Span, message: impl Into<String>) -> Self {
+    Self::new(Severity::Error, span, message)
+  }
+
+  pub fn warning(span: &Span, message: impl Into<String>) -> Self {
+    Self::new(Severity::Warning, span, message)
+  }
+
+  fn new(severity: Severity, span: &Span, message: impl Into<String>) -> Self {
+    let start = span.start_pos();
+    let (line, column) = start.line_col();
+    Self {
+      severity,
+      message: message.into(),
+      span: (span.start(), span.end()),
+      line,
+      column,
+      snippet: start.line_of().trim_end_matches(['\r', '\n']).to_string(),
+      file: None,
+    }
+  }
+}
+
+impl Display for Diagnostic {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let severity = match self.severity {
+      Severity::Error => "error",
+      Severity::Warning => "warning",
+    };
+    write!(f, "{}: {}", severity, self.message)?;
+    write_location(f, &self.file, self.line, self.column)?;
+    write_snippet(f, self.line, self.column, self.span, &self.snippet)
+  }
+}
+
+/// Collects problems during layout, so one run reports all of them instead of stopping at the first
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Diagnostics {
+  /// warnings count as errors
+  pub strict: bool,
+  items: Vec<Diagnostic>,
+  statement: Option<Diagnostic>,
+}
+
+impl Diagnostics {
+  /// Remember the statement being laid out, for problems found without a pair at hand
+  pub(crate) fn statement(&mut self, pair: &Pair<Rule>) {
+    self.statement = Some(Diagnostic::error(&pair.as_span(), ""));
+  }
+
+  pub(crate) fn error(&mut self, pair: &Pair<Rule>, message: impl Into<String>) {
+    self.push(Diagnostic::error(&pair.as_span(), message));
+  }
+
+  pub(crate) fn warning(&mut self, pair: &Pair<Rule>, message: impl Into<String>) {
+    self.push(Diagnostic::warning(&pair.as_span(), message));
+  }
+
+  /// Remember the text being laid out, for problems found outside of any statement
+  pub(crate) fn source(&mut self, text: &str) {
+    self.statement = Span::new(text, 0, text.len()).map(|span| Diagnostic::error(&span, ""));
+  }
+
+  pub(crate) fn error_in_statement(&mut self, message: impl Into<String>) {
+    self.in_statement(Severity::Error, message);
+  }
+
+  pub(crate) fn warning_in_statement(&mut self, message: impl Into<String>) {
+    self.in_statement(Severity::Warning, message);
+  }
+
+  fn in_statement(&mut self, severity: Severity, message: impl Into<String>) {
+    let statement = self.statement.clone().unwrap_or_else(|| Diagnostic::error(&Span::new("", 0, 0).unwrap(), ""));
+    self.push(Diagnostic { severity, message: message.into(), ..statement });
+  }
+
+  pub(crate) fn push(&mut self, diagnostic: Diagnostic) {
+    if !self.items.contains(&diagnostic) {
+      self.items.push(diagnostic);
+    }
+  }
+
+  /// Keep the value, or record why there is none
+  pub(crate) fn report<T>(&mut self, result: Result<T, Diagnostic>) -> Option<T> {
+    result.map_err(|diagnostic| self.push(diagnostic)).ok()
+  }
+
+  pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item=&mut Diagnostic> {
+    self.items.iter_mut()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item=&Diagnostic> {
+    self.items.iter()
+  }
+
+  pub fn errors(&self) -> impl Iterator<Item=&Diagnostic> {
+    self.items.iter().filter(|diagnostic| diagnostic.severity == Severity::Error)
+  }
+
+  pub fn warnings(&self) -> impl Iterator<Item=&Diagnostic> {
+    self.items.iter().filter(|diagnostic| diagnostic.severity == Severity::Warning)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.items.is_empty()
+  }
+
+  pub fn has_errors(&self) -> bool {
+    self.errors().next().is_some() || (self.strict && self.warnings().next().is_some())
+  }
+
+  pub(crate) fn clear(&mut self) {
+    self.items.clear();
+    self.statement = None;
+  }
+}
+
+impl Display for Diagnostics {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    for diagnostic in self.items.iter() {
+      writeln!(f, "{}", diagnostic)?;
+    }
+    Ok(())
+  }
+}
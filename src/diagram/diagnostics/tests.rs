@@ -0,0 +1,94 @@
+use crate::diagram::diagnostics::{Diagnostic, Diagnostics, Severity};
+use crate::diagram::parser::Diagram;
+use crate::skia::A5;
+
+fn diagnostics_for(string: &str) -> Diagnostics {
+  let mut diagram = Diagram::inset(A5, (0., 0.));
+  diagram.parse_string(string).unwrap();
+  diagram.diagnostics
+}
+
+#[test]
+fn unknown_color() {
+  let diagnostics = diagnostics_for("box \"one\" color mauve");
+  let errors = diagnostics.errors().collect::<Vec<_>>();
+  assert_eq!(1, errors.len());
+  assert_eq!("unknown color `mauve`", errors[0].message);
+  assert_eq!((1, 17), (errors[0].line, errors[0].column));
+}
+
+#[test]
+fn unknown_object() {
+  let diagnostics = diagnostics_for("box.a\narrow from a.e end b.w");
+  let errors = diagnostics.errors().collect::<Vec<_>>();
+  assert_eq!(1, errors.len());
+  assert_eq!("unknown object `b`", errors[0].message);
+  assert_eq!(2, errors[0].line);
+  assert_eq!("arrow from a.e end b.w", errors[0].snippet);
+}
+
+#[test]
+fn keeps_going_after_errors() {
+  let diagnostics = diagnostics_for("box color mauve\ncircle fill tealish\nline from x.e end y.w");
+  let messages = diagnostics.errors().map(|error| error.message.as_str()).collect::<Vec<_>>();
+  assert_eq!(vec!["unknown color `mauve`", "unknown color `tealish`", "unknown object `x`", "unknown object `y`"], messages);
+}
+
+#[test]
+fn millimeters() {
+  let diagnostics = diagnostics_for("set unit mm\nbox wd 20mm");
+  assert!(diagnostics.is_empty());
+}
+
+#[test]
+fn no_previous_object() {
+  let diagnostics = diagnostics_for("box 1cm right last circle");
+  let error = diagnostics.errors().next().unwrap();
+  assert_eq!("no previous circle", error.message);
+}
+
+#[test]
+fn seed_out_of_range() {
+  let diagnostics = diagnostics_for("set style sketch seed 4294967296\nbox");
+  let errors = diagnostics.errors().collect::<Vec<_>>();
+  assert_eq!(1, errors.len());
+  assert_eq!("seed can't be more than 4294967295", errors[0].message);
+  assert_eq!((1, 18), (errors[0].line, errors[0].column));
+}
+
+#[test]
+fn outside_of_statements() {
+  let mut diagnostics = Diagnostics::default();
+  diagnostics.error_in_statement("no previous object");
+  let errors = diagnostics.errors().collect::<Vec<_>>();
+  assert_eq!(1, errors.len());
+  assert_eq!(("no previous object", 1, 1), (errors[0].message.as_str(), errors[0].line, errors[0].column));
+}
+
+#[test]
+fn strict_counts_warnings() {
+  let mut diagnostics = Diagnostics::default();
+  diagnostics.push(Diagnostic {
+    severity: Severity::Warning,
+    message: "ignored".into(),
+    span: (0, 3),
+    line: 1,
+    column: 1,
+    snippet: "box".into(),
+    file: None,
+  });
+  assert!(!diagnostics.has_errors());
+
+  diagnostics.strict = true;
+  assert!(diagnostics.has_errors());
+}
+
+#[test]
+fn rendered_with_severity() {
+  let diagnostics = diagnostics_for("box color mauve");
+  let rendered = diagnostics.to_string();
+  let lines = rendered.lines().collect::<Vec<_>>();
+  assert_eq!("error: unknown color `mauve` at line 1, column 11", lines[0]);
+  assert_eq!("1 | box color mauve", lines[2]);
+  assert_eq!("  |           ^^^^^", lines[3]);
+}
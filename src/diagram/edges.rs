@@ -1,4 +1,4 @@
-use std::ops::{Add, Sub};
+use std::ops::{Add, Mul, Sub};
 use skia_safe::{Point, Rect, scalar};
 use crate::trig::angle_at;
 
@@ -56,6 +56,19 @@ impl Edge {
     None
   }
 
+  /// like `intersect_factor`, but also where `with` is only touched at an end
+  fn crossing(&self, with: &Edge) -> Option<scalar> {
+    let (a, b, c, d) = (self.from, self.to, with.from, with.to);
+    let bottom = (d.x - c.x) * (b.y - a.y) - (d.y - c.y) * (b.x - a.x);
+    if bottom == 0. {
+      return None;
+    }
+    let alpha = ((d.x - c.x) * (c.y - a.y) - (d.y - c.y) * (c.x - a.x)) / bottom;
+    let beta = ((b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)) / bottom;
+    let touch = 1e-4;
+    (alpha > 0. && (-touch..=1. + touch).contains(&beta)).then_some(alpha)
+  }
+
   fn lerp(a: scalar, b: scalar, t: f32) -> scalar {
     a + (b - a) * t
   }
@@ -139,6 +152,107 @@ impl EdgeFinder {
     EdgeFinder { edges, ellipses, bounds }
   }
 
+  /// how far the slanted sides of a hexagon, parallelogram or trapezoid lean in
+  pub fn slant(width: f32, height: f32) -> f32 {
+    (width / 4.).min(height / 2.)
+  }
+
+  pub fn hexagon(x: f32, y: f32, width: f32, height: f32) -> Self {
+    let slant = Self::slant(width, height);
+    Self::closed(Rect::from_xywh(x, y, width, height), vec![
+      Point::new(x + slant, y),
+      Point::new(x + width - slant, y),
+      Point::new(x + width, y + height / 2.),
+      Point::new(x + width - slant, y + height),
+      Point::new(x + slant, y + height),
+      Point::new(x, y + height / 2.),
+    ])
+  }
+
+  pub fn parallelogram(x: f32, y: f32, width: f32, height: f32) -> Self {
+    let slant = Self::slant(width, height);
+    Self::closed(Rect::from_xywh(x, y, width, height), vec![
+      Point::new(x + slant, y),
+      Point::new(x + width, y),
+      Point::new(x + width - slant, y + height),
+      Point::new(x, y + height),
+    ])
+  }
+
+  pub fn trapezoid(x: f32, y: f32, width: f32, height: f32) -> Self {
+    let slant = Self::slant(width, height);
+    Self::closed(Rect::from_xywh(x, y, width, height), vec![
+      Point::new(x + slant, y),
+      Point::new(x + width - slant, y),
+      Point::new(x + width, y + height),
+      Point::new(x, y + height),
+    ])
+  }
+
+  /// five points, the first one up, stretched to fill the bounds
+  pub fn star(x: f32, y: f32, width: f32, height: f32) -> Self {
+    let inner = 18f32.to_radians().sin() / 54f32.to_radians().sin();
+    let (left, top) = (18f32.to_radians().cos(), 1.);
+    let (right, bottom) = (left, 36f32.to_radians().cos());
+    let corners = (0..10).map(|n| {
+      let radius = if n % 2 == 0 { 1. } else { inner };
+      let (sin, cos) = (n as f32 * 36. - 90.).to_radians().sin_cos();
+      Point::new(
+        x + (radius * cos + left) / (left + right) * width,
+        y + (radius * sin + top) / (top + bottom) * height,
+      )
+    }).collect();
+    Self::closed(Rect::from_xywh(x, y, width, height), corners)
+  }
+
+  fn closed(bounds: Rect, corners: Vec<Point>) -> Self {
+    let edges = corners.iter().zip(corners.iter().cycle().skip(1))
+      .map(|(from, to)| Edge::new(*from, *to))
+      .collect();
+    EdgeFinder { edges, ellipses: vec![], bounds }
+  }
+
+  /// where the straight edges start, in order around the outline
+  pub fn corners(&self) -> Vec<Point> {
+    self.edges.iter().map(|edge| edge.from).collect()
+  }
+
+  /// the first point of the outline from the center toward `point`, corners included
+  pub fn toward(&self, point: Point) -> Option<Point> {
+    let from = self.bounds.center();
+    let direction = point.sub(from);
+    if direction.length() == 0. {
+      return Some(from);
+    }
+    let reach = (self.bounds.width() + self.bounds.height()) / direction.length();
+    let ray = Edge::new(from, from.add(direction.mul(reach)));
+    let edges = self.edges.iter().filter_map(|edge| Edge::crossing(&ray, edge));
+    if self.ellipses.is_empty() {
+      return edges.min_by(|a, b| a.total_cmp(b)).map(|factor| ray.interpolate(factor));
+    }
+    // outlines with curves are convex, and their inner curves are crossed first
+    edges.chain(self.ellipses.iter().filter_map(|rect| EdgeFinder::intersect_ellipse(&ray, rect)))
+      .max_by(|a, b| a.total_cmp(b))
+      .map(|factor| ray.interpolate(factor))
+  }
+
+  pub fn ellipse(x: f32, y: f32, width: f32, height: f32) -> Self {
+    let bounds = Rect::from_xywh(x, y, width, height);
+    EdgeFinder { edges: vec![], ellipses: vec![bounds], bounds }
+  }
+
+  /// round ends as high as the oval, joined at the top and bottom
+  pub fn oval(x: f32, y: f32, width: f32, height: f32) -> Self {
+    let bounds = Rect::from_xywh(x, y, width, height);
+    let left = Rect::from_xywh(x, y, height, height);
+    let right = Rect::from_xywh(x + width - height, y, height, height);
+    let edges = vec![
+      Edge::new((left.center_x(), y), (right.center_x(), y)),
+      Edge::new((right.center_x(), y + height), (left.center_x(), y + height)),
+    ];
+    EdgeFinder { edges, ellipses: vec![left, right], bounds }
+  }
+
   pub fn file(x: f32, y: f32, width: f32, height: f32, radius: f32) -> Self {
     let bounds = Rect::from_xywh(x, y, width, height);
     let tl = Point::new(x, y);
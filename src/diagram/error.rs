@@ -0,0 +1,103 @@
+use std::fmt::{Display, Formatter};
+
+use pest::error::{Error, ErrorVariant, InputLocation, LineColLocation};
+use pest::RuleType;
+
+use crate::diagram::parser::Rule;
+
+#[cfg(test)]
+mod tests;
+
+/// A syntax error in the diagram source, with enough context to point at the offending text.
+/// Results carry it boxed, as it is large.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+  /// byte offsets into the source
+  pub span: (usize, usize),
+  /// 1-based, like editors
+  pub line: usize,
+  pub column: usize,
+  /// the source line that contains the error
+  pub snippet: String,
+  /// rules the parser would have accepted at this position
+  pub expected: Vec<String>,
+  pub message: String,
+  /// the included file the error is in, when there is one
+  pub file: Option<String>,
+}
+
+impl ParseError {
+  fn rule_name(rule: &Rule) -> String {
+    match rule {
+      Rule::EOI => "end of input".into(),
+      Rule::rgb => "color".into(),
+      Rule::object_edge => "object".into(),
+      rule => format!("{:?}", rule).replace('_', " ")
+    }
+  }
+
+  /// Convert an error from any of the grammars, naming rules with `rule_name`
+  pub(crate) fn from_pest<R: RuleType>(error: Error<R>, rule_name: fn(&R) -> String) -> Self {
+    let span = match error.location {
+      InputLocation::Pos(pos) => (pos, pos),
+      InputLocation::Span(span) => span,
+    };
+    let (line, column) = match error.line_col {
+      LineColLocation::Pos(pos) => pos,
+      LineColLocation::Span(start, _) => start,
+    };
+    let (expected, message) = match &error.variant {
+      ErrorVariant::ParsingError { positives, .. } => {
+        let mut expected = positives.iter().map(rule_name).collect::<Vec<_>>();
+        expected.dedup();
+        let message = Self::describe(&expected);
+        (expected, message)
+      }
+      ErrorVariant::CustomError { message } => (vec![], message.clone()),
+    };
+    let snippet = error.line().trim_end_matches(['\r', '\n']).to_string();
+    Self { span, line, column, snippet, expected, message, file: None }
+  }
+
+  fn describe(expected: &[String]) -> String {
+    match expected {
+      [] => "unexpected input".into(),
+      [one] => format!("expected {}", one),
+      [init @ .., last] => format!("expected {} or {}", init.join(", "), last),
+    }
+  }
+}
+
+/// Where a problem is, naming the file once includes are involved
+pub(crate) fn write_location(f: &mut Formatter<'_>, file: &Option<String>, line: usize, column: usize) -> std::fmt::Result {
+  match file {
+    Some(file) => writeln!(f, " in {} at line {}, column {}", file, line, column),
+    None => writeln!(f, " at line {}, column {}", line, column),
+  }
+}
+
+/// Render the source line with a marker under the offending span, like `rustc`
+pub(crate) fn write_snippet(f: &mut Formatter<'_>, line: usize, column: usize, span: (usize, usize), snippet: &str) -> std::fmt::Result {
+  let gutter = " ".repeat(line.to_string().len());
+  let width = (span.1 - span.0).max(1);
+  let width = width.min(snippet.chars().count().saturating_sub(column - 1).max(1));
+  writeln!(f, "{} |", gutter)?;
+  writeln!(f, "{} | {}", line, snippet)?;
+  write!(f, "{} | {}{}", gutter, " ".repeat(column - 1), "^".repeat(width))
+}
+
+impl From<Error<Rule>> for ParseError {
+  fn from(error: Error<Rule>) -> Self {
+    Self::from_pest(error, Self::rule_name)
+  }
+}
+
+impl Display for ParseError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.message)?;
+    write_location(f, &self.file, self.line, self.column)?;
+    write_snippet(f, self.line, self.column, self.span, &self.snippet)
+  }
+}
+
+impl std::error::Error for ParseError {}
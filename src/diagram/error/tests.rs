@@ -0,0 +1,48 @@
+use crate::diagram::create_diagram;
+use crate::diagram::error::ParseError;
+use crate::diagram::parser::Diagram;
+use crate::skia::A5;
+
+fn subject(string: &str) -> ParseError {
+  let mut diagram = Diagram::inset(A5, (0., 0.));
+  *diagram.parse_string(string).unwrap_err()
+}
+
+#[test]
+fn line_and_column() {
+  let error = subject("box \"one\"\nbox wd red");
+  assert_eq!(error.line, 2);
+  assert_eq!(error.column, 8);
+  assert_eq!(error.span, (17, 17));
+  assert_eq!(error.snippet, "box wd red");
+}
+
+#[test]
+fn expected_rules() {
+  let error = subject("box color #12");
+  assert_eq!(error.line, 1);
+  assert!(error.expected.contains(&"color".to_string()), "{:?}", error.expected);
+  assert!(error.message.starts_with("expected "), "{}", error.message);
+}
+
+#[test]
+fn rendered_with_marker() {
+  let error = subject("box \"one\"\nbox wd red");
+  let rendered = error.to_string();
+  let lines = rendered.lines().collect::<Vec<_>>();
+  assert_eq!(lines[1], "  |");
+  assert_eq!(lines[2], "2 | box wd red");
+  assert_eq!(lines[3], "  |        ^");
+}
+
+#[test]
+fn valid_input() {
+  let mut diagram = Diagram::inset(A5, (0., 0.));
+  assert!(diagram.parse_string("box \"one\"").is_ok());
+}
+
+#[test]
+#[should_panic(expected = "at line 1, column")]
+fn create_diagram_panics_with_location() {
+  create_diagram("box color #12");
+}
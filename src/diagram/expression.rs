@@ -0,0 +1,243 @@
+use std::fmt::{Display, Formatter};
+
+use pest::iterators::Pair;
+use skia_safe::Rect;
+
+use crate::diagram::conversion::Conversion;
+use crate::diagram::index::Index;
+use crate::diagram::parser::Rule;
+use crate::diagram::types::{Edge, Length, Unit};
+
+#[cfg(test)]
+mod tests;
+
+/// Result of an expression: a plain number, or a length already in pixels.
+/// Macro arguments can also be text, for use in strings and ids.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+  Number(f32),
+  Pixels(f32),
+  Text(String),
+}
+
+impl Display for Value {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Value::Number(amount) | Value::Pixels(amount) => write!(f, "{}", amount),
+      Value::Text(text) => write!(f, "{}", text),
+    }
+  }
+}
+
+/// Evaluates `Rule::expression` pairs, reading variables and placed objects from the index
+pub(crate) struct Expression<'e, 'i> {
+  unit: Unit,
+  /// what `1u` stands for
+  size: f32,
+  index: &'e mut Index<'i>,
+}
+
+impl<'e, 'i> Expression<'e, 'i> {
+  pub(crate) fn new(unit: &Unit, index: &'e mut Index<'i>) -> Self {
+    Self { unit: *unit, size: 1., index }
+  }
+
+  pub(crate) fn sized(unit: &Unit, size: f32, index: &'e mut Index<'i>) -> Self {
+    Self { unit: *unit, size, index }
+  }
+
+  /// Plain numbers are taken in the current unit, a single quantity keeps its own
+  pub(crate) fn length_from(&mut self, pair: Pair<Rule>) -> Length {
+    if let Some(length) = self.literal_from(&pair) {
+      return length;
+    }
+    match self.value_from(pair) {
+      Value::Pixels(pixels) => Length::new(pixels, Unit::Px),
+      value => Length::new(Self::amount(&value), self.unit),
+    }
+  }
+
+  fn literal_from(&self, pair: &Pair<Rule>) -> Option<Length> {
+    let quantity = Self::only(pair.clone()).and_then(Self::only)
+      .filter(|pair| pair.as_rule() == Rule::quantity)?;
+    Some(match Self::number_unit(quantity) {
+      (number, Some(Unit::Unit)) => Length::new(number * self.size, Unit::Px),
+      (number, Some(unit)) => Length::new(number, unit),
+      (number, None) => Length::new(number, self.unit),
+    })
+  }
+
+  fn only(pair: Pair<Rule>) -> Option<Pair<Rule>> {
+    let mut inner = pair.into_inner();
+    let first = inner.next();
+    inner.next().is_none().then_some(first).flatten()
+  }
+
+  pub(crate) fn value_from(&mut self, pair: Pair<Rule>) -> Value {
+    match pair.as_rule() {
+      Rule::expression => self.fold(pair, Self::add),
+      Rule::term => self.fold(pair, Self::multiply),
+      Rule::negation => Self::map(self.value_from(pair.into_inner().next().unwrap()), |amount| -amount),
+      Rule::quantity => self.quantity_from(pair),
+      Rule::number => Value::Number(pair.as_str().parse().unwrap()),
+      Rule::variable => self.variable_from(pair),
+      Rule::property => self.property_from(pair),
+      Rule::function => self.function_from(pair),
+      _ => panic!("Unexpected rule for expression {:?}", pair.as_rule())
+    }
+  }
+
+  fn fold(&mut self, pair: Pair<Rule>, apply: fn(&mut Self, &Pair<Rule>, &str, Value, Value) -> Value) -> Value {
+    let mut inner = pair.into_inner();
+    let mut value = self.value_from(inner.next().unwrap());
+    while let (Some(operator), Some(operand)) = (inner.next(), inner.next()) {
+      let right = self.value_from(operand.clone());
+      value = apply(self, &operand, operator.as_str(), value, right);
+    }
+    value
+  }
+
+  fn add(&mut self, _pair: &Pair<Rule>, operator: &str, left: Value, right: Value) -> Value {
+    let sign = if operator == "-" { -1. } else { 1. };
+    match (left, right) {
+      (Value::Number(left), Value::Number(right)) => Value::Number(left + sign * right),
+      (left, right) => Value::Pixels(self.pixels(&left) + sign * self.pixels(&right)),
+    }
+  }
+
+  fn multiply(&mut self, pair: &Pair<Rule>, operator: &str, left: Value, right: Value) -> Value {
+    let (left_amount, right_amount) = (Self::amount(&left), Self::amount(&right));
+    let amount = match operator {
+      "*" => left_amount * right_amount,
+      _ if right_amount == 0. => {
+        self.index.diagnostics.error(pair, "division by zero");
+        0.
+      }
+      _ => left_amount / right_amount,
+    };
+    match (left, right, operator) {
+      (Value::Number(_), Value::Number(_), _) => Value::Number(amount),
+      (Value::Pixels(_), Value::Pixels(_), "/") => Value::Number(amount),
+      _ => Value::Pixels(amount),
+    }
+  }
+
+  fn quantity_from(&mut self, pair: Pair<Rule>) -> Value {
+    match Self::number_unit(pair) {
+      (number, Some(Unit::Unit)) => Value::Pixels(number * self.size),
+      (number, Some(unit)) => Value::Pixels(Length::new(number, unit).pixels()),
+      (number, None) => Value::Number(number),
+    }
+  }
+
+  fn number_unit(pair: Pair<Rule>) -> (f32, Option<Unit>) {
+    let mut inner = pair.into_inner();
+    let number = inner.next().unwrap().as_str().parse::<f32>().unwrap();
+    (number, inner.next().and_then(|unit| Unit::try_from(unit.as_str()).ok()))
+  }
+
+  fn variable_from(&mut self, pair: Pair<Rule>) -> Value {
+    let name = pair.clone().into_inner().next().unwrap().as_str();
+    match self.index.variable(name) {
+      Some(Value::Text(text)) => text.parse().map(Value::Number).unwrap_or_else(|_| {
+        self.index.diagnostics.error(&pair, format!("`${}` is not a number", name));
+        Value::Number(0.)
+      }),
+      Some(value) => value,
+      None => {
+        self.index.diagnostics.error(&pair, format!("unknown variable `${}`", name));
+        Value::Number(0.)
+      }
+    }
+  }
+
+  fn property_from(&mut self, pair: Pair<Rule>) -> Value {
+    let mut edge: Option<Edge> = None;
+    let mut rect: Option<Rect> = None;
+    let mut name = "";
+
+    for inner in pair.clone().into_inner() {
+      match inner.as_rule() {
+        Rule::object_ref => rect = self.object_from(inner),
+        Rule::edge_point => edge = Some(Conversion::edge_from(inner)),
+        Rule::property_name => name = inner.as_str(),
+        _ => {}
+      }
+    }
+
+    let Some(rect) = rect else {
+      return Value::Pixels(0.);
+    };
+    let point = edge.unwrap_or(Edge::center()).edge_point(&rect);
+    Value::Pixels(match name {
+      "wd" | "width" => rect.width(),
+      "ht" | "height" => rect.height(),
+      "rad" | "radius" => rect.width() / 2.,
+      "x" => point.x,
+      _ => point.y,
+    })
+  }
+
+  fn object_from(&mut self, pair: Pair<Rule>) -> Option<Rect> {
+    let inner = pair.clone().into_inner().next().unwrap();
+    let rect = self.index.rect_for(inner.as_str());
+    if rect.is_none() {
+      self.index.diagnostics.error(&pair, format!("unknown object `{}`", pair.as_str()));
+    }
+    rect
+  }
+
+  fn function_from(&mut self, pair: Pair<Rule>) -> Value {
+    let mut inner = pair.clone().into_inner();
+    let name = inner.next().unwrap().as_str();
+    let arguments = inner.map(|argument| self.value_from(argument)).collect::<Vec<_>>();
+
+    if !matches!(name, "min" | "max") && arguments.len() > 1 {
+      self.index.diagnostics.error(&pair, format!("`{}` takes a single argument", name));
+    }
+
+    let first = arguments[0].clone();
+    let fold = |f: fn(f32, f32) -> f32| arguments.iter().skip(1).fold(first.clone(), |left, right| {
+      match (&left, right) {
+        (Value::Number(left), Value::Number(right)) => Value::Number(f(*left, *right)),
+        (left, right) => Value::Pixels(f(self.pixels(left), self.pixels(right))),
+      }
+    });
+
+    match name {
+      "min" => fold(f32::min),
+      "max" => fold(f32::max),
+      "sqrt" if Self::amount(&first) < 0. => {
+        self.index.diagnostics.error(&pair, "square root of a negative number");
+        Self::map(first, |_| 0.)
+      }
+      "sqrt" => Self::map(first, f32::sqrt),
+      "abs" => Self::map(first, f32::abs),
+      "sin" => Value::Number(Self::amount(&first).to_radians().sin()),
+      "cos" => Value::Number(Self::amount(&first).to_radians().cos()),
+      _ => Value::Number(Self::amount(&first).to_radians().tan()),
+    }
+  }
+
+  fn map(value: Value, f: fn(f32) -> f32) -> Value {
+    match value {
+      Value::Pixels(pixels) => Value::Pixels(f(pixels)),
+      value => Value::Number(f(Self::amount(&value))),
+    }
+  }
+
+  /// text only gets here when it is not a number, which has been reported
+  pub(crate) fn amount(value: &Value) -> f32 {
+    match value {
+      Value::Number(amount) | Value::Pixels(amount) => *amount,
+      Value::Text(_) => 0.,
+    }
+  }
+
+  fn pixels(&self, value: &Value) -> f32 {
+    match value {
+      Value::Pixels(pixels) => *pixels,
+      value => Length::new(Self::amount(value), self.unit).pixels(),
+    }
+  }
+}
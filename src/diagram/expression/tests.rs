@@ -0,0 +1,105 @@
+use skia_safe::Rect;
+
+use crate::diagram::conversion::Conversion;
+use crate::diagram::create_diagram;
+use crate::diagram::expression::Value;
+use crate::diagram::index::{Index, ShapeName};
+use crate::diagram::parser::Rule;
+use crate::diagram::types::{Length, Node, Unit};
+
+fn pixels(string: &str, index: &mut Index) -> f32 {
+  let pair = Conversion::pair_for(Rule::width, string);
+  Conversion::length_from(pair, &Unit::Px, index).pixels()
+}
+
+fn subject(string: &str) -> f32 {
+  pixels(string, &mut Index::default())
+}
+
+#[test]
+fn quantity() {
+  assert_eq!(Length::new(2., Unit::Cm).pixels(), subject("wd 2cm"));
+  assert_eq!(2., subject("wd 2"));
+}
+
+#[test]
+fn current_unit() {
+  let pair = Conversion::pair_for(Rule::width, "wd 2");
+  let length = Conversion::length_from(pair, &Unit::In, &mut Index::default());
+  assert_eq!(Length::new(2., Unit::In), length);
+}
+
+#[test]
+fn precedence() {
+  assert_eq!(288., subject("wd 1in + 2 * 1in"));
+  assert_eq!(96., subject("wd (1in + 1in) / 2"));
+  assert_eq!(-96., subject("wd -1in"));
+  assert_eq!(48., subject("wd 1in - 0.5in"));
+}
+
+#[test]
+fn variables() {
+  let mut index = Index::default();
+  index.assign("gap", Value::Pixels(10.));
+  let expected = 20. + Length::new(3., Unit::Mm).pixels();
+  assert_eq!(expected, pixels("wd $gap * 2 + 3mm", &mut index));
+}
+
+#[test]
+fn functions() {
+  assert_eq!(96., subject("wd max(1in, 2cm)"));
+  assert_eq!(Length::new(2., Unit::Cm).pixels(), subject("wd min(1in, 2cm)"));
+  assert_eq!(3., subject("wd sqrt(9)"));
+  assert_eq!(5., subject("wd 10 * cos(60)").round());
+}
+
+#[test]
+fn function_arguments() {
+  let mut index = Index::default();
+  assert_eq!(3., pixels("wd sqrt(9, 16)", &mut index));
+  assert_eq!(0., pixels("wd sqrt(-4)", &mut index));
+  let messages = index.diagnostics.errors().map(|error| error.message.clone()).collect::<Vec<_>>();
+  assert_eq!(vec!["`sqrt` takes a single argument", "square root of a negative number"], messages);
+}
+
+#[test]
+fn properties() {
+  let mut index = Index::default();
+  index.insert_shape(ShapeName::Box, Some("pic1"), Rect::from_xywh(10., 20., 100., 50.));
+
+  assert_eq!(100., pixels("wd pic1.wd", &mut index));
+  assert_eq!(25., pixels("wd pic1.ht / 2", &mut index));
+  assert_eq!(20., pixels("wd pic1.n.y", &mut index));
+  assert_eq!(10., pixels("wd pic1.w.x", &mut index));
+  assert_eq!(60., pixels("wd last box.x", &mut index));
+  assert!(index.diagnostics.is_empty());
+}
+
+#[test]
+fn unknown_names() {
+  let mut index = Index::default();
+  assert_eq!(0., pixels("wd $gap + pic2.wd", &mut index));
+  let messages = index.diagnostics.errors().map(|error| error.message.clone()).collect::<Vec<_>>();
+  assert_eq!(vec!["unknown variable `$gap`", "unknown object `pic2`"], messages);
+}
+
+#[test]
+fn assignment() {
+  let diagram = create_diagram("$gap = 0.5in\nbox wd $gap * 2 ht $gap");
+  let Some(Node::Closed(_, used, _, _)) = diagram.nodes.first() else {
+    panic!("Expected box")
+  };
+  assert_eq!((96., 48.), (used.width(), used.height()));
+}
+
+#[test]
+fn canvas_size() {
+  let sized = |string: &str| match create_diagram(string).nodes.first() {
+    Some(Node::Canvas(size)) => (size.width, size.height),
+    _ => panic!("Expected canvas"),
+  };
+  assert_eq!((72., 48.), sized("canvas 0.75x0.5in"));
+  assert_eq!((96., 48.), sized("canvas 0.5in * 2 x (1in - 0.5in)"));
+  assert_eq!((96., 20.), sized("canvas 1in x max(10, 20)"));
+  assert_eq!((2., 192.), sized("canvas 2 x 1in * 2"));
+}
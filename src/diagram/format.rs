@@ -0,0 +1,327 @@
+use pest::iterators::Pair;
+
+use crate::diagram::conversion::Conversion;
+use crate::diagram::error::ParseError;
+use crate::diagram::parser::Rule;
+
+#[cfg(test)]
+mod tests;
+
+const INDENT: &str = "  ";
+
+/// Writes diagram text back in canonical form: a statement per line, bodies indented,
+/// attributes in a fixed order with one spelling each, comments and blank lines kept
+pub struct Formatter<'i> {
+  text: &'i str,
+  lines: Vec<String>,
+}
+
+impl<'i> Formatter<'i> {
+  pub fn format(text: &'i str) -> Result<String, Box<ParseError>> {
+    let pairs = Conversion::pairs_for(Rule::picture, text)?;
+    let mut formatter = Formatter { text, lines: vec![] };
+    formatter.statements(pairs, 0, 0);
+    let mut formatted = formatter.lines.join("\n");
+    formatted.push('\n');
+    Ok(formatted)
+  }
+
+  /// statements of the picture or of a body that starts at `end`
+  fn statements(&mut self, pairs: impl IntoIterator<Item=Pair<'i, Rule>>, depth: usize, mut end: usize) {
+    let indent = INDENT.repeat(depth);
+    let mut first = true;
+    for pair in pairs.into_iter().filter(|pair| pair.as_rule() != Rule::EOI) {
+      // comments end with their newline, rules with a trailing repetition with the whitespace after them
+      let before = &self.text[..end];
+      let gap = &before[before.trim_end().len()..];
+      let breaks = gap.matches('\n').count() + self.text[end..pair.as_span().start()].matches('\n').count();
+
+      if pair.as_rule() == Rule::comment && breaks == 0 && !self.lines.is_empty() {
+        let line = self.lines.last_mut().unwrap();
+        line.push(' ');
+        line.push_str(pair.as_str().trim_end());
+      } else {
+        if breaks > 1 && !first {
+          self.lines.push(String::new());
+        }
+        match pair.as_rule() {
+          Rule::comment => self.lines.push(format!("{}{}", indent, pair.as_str().trim_end())),
+          Rule::group | Rule::definition | Rule::for_loop => self.block(pair.clone(), depth),
+          _ => self.lines.push(format!("{}{}", indent, Self::line(pair.clone()))),
+        }
+      }
+      end = pair.as_span().end();
+      first = false;
+    }
+  }
+
+  fn block(&mut self, pair: Pair<'i, Rule>, depth: usize) {
+    let (header, body): (Vec<_>, Vec<_>) = pair.clone().into_inner()
+      .partition(|child| Self::is_header(child.as_rule()));
+    let sorted = Self::header(&pair, &header, true);
+    let line = match Self::same_parse(&pair, &format!("{}\n}}", sorted)) {
+      true => sorted,
+      false => Self::header(&pair, &header, false),
+    };
+    self.lines.push(format!("{}{}", INDENT.repeat(depth), line));
+
+    let after = header.last().map(|child| child.as_span().end()).unwrap_or(pair.as_span().start());
+    let open = after + self.text[after..].find('{').map(|brace| brace + 1).unwrap_or(0);
+    self.statements(body, depth + 1, open);
+    self.lines.push(format!("{}}}", INDENT.repeat(depth)));
+  }
+
+  /// the opening line of a `group`, `define` or `for`
+  fn header(pair: &Pair<Rule>, header: &[Pair<Rule>], sorted: bool) -> String {
+    let tokens = |rule: Rule| header.iter().find(|child| child.as_rule() == rule).map(|child| Self::tokens(child.clone()));
+    match pair.as_rule() {
+      Rule::group => {
+        let mut line = format!("group{}", tokens(Rule::identified).unwrap_or_default());
+        let attributes = header.iter().find(|child| child.as_rule() == Rule::closed_attributes)
+          .map(|attributes| Self::attributes(attributes.clone().into_inner(), sorted))
+          .unwrap_or_default();
+        if !attributes.is_empty() {
+          line.push(' ');
+          line.push_str(&attributes);
+        }
+        line + " {"
+      }
+      Rule::definition => {
+        let parameters = header.iter().find(|child| child.as_rule() == Rule::parameters)
+          .map(|parameters| Self::list(parameters.clone()))
+          .unwrap_or_default();
+        format!("define {}({}) {{", tokens(Rule::id).unwrap_or_default(), parameters)
+      }
+      _ => {
+        let values = tokens(Rule::range).or(tokens(Rule::items)).unwrap_or_default();
+        format!("for {} {} {{", tokens(Rule::id).unwrap_or_default(), values)
+      }
+    }
+  }
+
+  fn is_header(rule: Rule) -> bool {
+    matches!(rule, Rule::identified | Rule::closed_attributes | Rule::id | Rule::parameters | Rule::range | Rule::items)
+  }
+
+  /// a statement on one line, with its attributes in order unless that would change what it means
+  fn line(pair: Pair<Rule>) -> String {
+    let sorted = Self::statement(pair.clone(), true);
+    match Self::same_parse(&pair, &sorted) {
+      true => sorted,
+      false => Self::statement(pair, false),
+    }
+  }
+
+  fn statement(pair: Pair<Rule>, sorted: bool) -> String {
+    let rule = pair.as_rule();
+    let mut children = pair.clone().into_inner();
+    match rule {
+      Rule::rectangle | Rule::file | Rule::circle | Rule::ellipse | Rule::cylinder | Rule::oval | Rule::diamond | Rule::triangle
+      | Rule::hexagon | Rule::parallelogram | Rule::trapezoid | Rule::star | Rule::text | Rule::dot
+      | Rule::arrow | Rule::line | Rule::sline | Rule::arc | Rule::curve | Rule::path => {
+        let mut line = match rule {
+          Rule::rectangle => "box".to_string(),
+          Rule::curve => pair.as_str().chars().take_while(char::is_ascii_alphabetic).collect(),
+          rule => format!("{:?}", rule),
+        };
+        for child in children {
+          match child.as_rule() {
+            Rule::identified => line.push_str(child.as_str()),
+            _ => Self::append(&mut line, Self::attributes(child.into_inner(), sorted)),
+          }
+        }
+        line
+      }
+      Rule::expansion => {
+        let mut line = String::new();
+        for child in children {
+          match child.as_rule() {
+            Rule::id | Rule::identified => line.push_str(child.as_str()),
+            Rule::arguments => line.push_str(&format!("({})", Self::list(child))),
+            _ => Self::append(&mut line, Self::attributes(child.into_inner(), sorted)),
+          }
+        }
+        line
+      }
+      Rule::shape_config => {
+        let shapes = children.next().unwrap().as_str();
+        let mut line = format!("set {}", shapes);
+        Self::append(&mut line, Self::attributes(children, sorted));
+        line
+      }
+      Rule::style => {
+        let mut line = format!("style {} {{", children.next().unwrap().as_str());
+        Self::append(&mut line, Self::attributes(children, sorted));
+        line + " }"
+      }
+      Rule::assignment => {
+        let variable = children.next().unwrap().as_str();
+        format!("{} = {}", variable, Self::tokens(children.next().unwrap()))
+      }
+      _ => Self::tokens(pair),
+    }
+  }
+
+  fn append(line: &mut String, attributes: String) {
+    if !attributes.is_empty() {
+      line.push(' ');
+      line.push_str(&attributes);
+    }
+  }
+
+  fn attributes<'p>(pairs: impl Iterator<Item=Pair<'p, Rule>>, sorted: bool) -> String {
+    let mut attributes = pairs.collect::<Vec<_>>();
+    if sorted {
+      attributes.sort_by_key(|attribute| Self::rank(attribute.as_rule()));
+    }
+    attributes.into_iter().map(Self::tokens).collect::<Vec<_>>().join(" ")
+  }
+
+  /// titles first, then styles, where lines go and what they say, size, place, looks and endings;
+  /// the steps of a path keep their order
+  fn rank(rule: Rule) -> usize {
+    [
+      &[Rule::string][..], &[Rule::same], &[Rule::class], &[Rule::source, Rule::rel_movement, Rule::abs_movement, Rule::target], &[Rule::control], &[Rule::caption],
+      &[Rule::width], &[Rule::height], &[Rule::length], &[Rule::radius], &[Rule::turn], &[Rule::padding], &[Rule::space], &[Rule::fit],
+      &[Rule::location], &[Rule::continuation], &[Rule::route], &[Rule::chop], &[Rule::smooth], &[Rule::tension],
+      &[Rule::stroke], &[Rule::fill], &[Rule::text_color], &[Rule::opacity], &[Rule::thickness], &[Rule::effect], &[Rule::cap], &[Rule::join], &[Rule::double], &[Rule::shadow],
+    ].iter().position(|ranked| ranked.contains(&rule)).unwrap_or(usize::MAX)
+  }
+
+  /// arguments and parameters, separated by commas
+  fn list(pair: Pair<Rule>) -> String {
+    pair.into_inner().map(Self::tokens).collect::<Vec<_>>().join(", ")
+  }
+
+  /// the text of a pair with whitespace collapsed, expressions spaced and keywords spelled one way
+  fn tokens(pair: Pair<Rule>) -> String {
+    let rule = pair.as_rule();
+    match rule {
+      Rule::expression => return Self::expression(pair),
+      Rule::string => return pair.as_str().to_string(),
+      Rule::thickness if pair.clone().into_inner().next().is_none() => return Self::thickness(pair.as_str()).to_string(),
+      Rule::range => {
+        let values = pair.into_inner().map(Self::expression).collect::<Vec<_>>();
+        let step = values.get(2).map(|step| format!(" by {}", step)).unwrap_or_default();
+        return format!("= {} to {}{}", values[0], values[1], step);
+      }
+      Rule::items => return format!("in [{}]", Self::list(pair)),
+      Rule::shadow => {
+        let mut inner = pair.into_inner();
+        let kind = inner.next().unwrap().as_str();
+        let arguments = inner.map(Self::tokens).collect::<Vec<_>>();
+        return if arguments.is_empty() { kind.to_string() } else { format!("{}({})", kind, arguments.join(", ")) };
+      }
+      Rule::mixed | Rule::shading => {
+        let mut inner = pair.into_inner();
+        let function = inner.next().unwrap().as_str();
+        return format!("{}({})", function, inner.map(Self::tokens).collect::<Vec<_>>().join(", "));
+      }
+      _ => {}
+    }
+
+    let keyword = match rule {
+      Rule::width => Some("wd"),
+      Rule::height => Some("ht"),
+      Rule::padding => Some("pd"),
+      Rule::radius => Some("rad"),
+      Rule::space => Some("sp"),
+      Rule::length => Some("ln"),
+      Rule::stroke => Some("color"),
+      _ => None,
+    };
+    if let Some(keyword) = keyword {
+      let value = pair.into_inner().map(Self::tokens).collect::<Vec<_>>().join(" ");
+      return format!("{} {}", keyword, value);
+    }
+
+    let text = pair.as_str();
+    let start = pair.as_span().start();
+    let mut tokens = String::new();
+    let mut position = 0;
+    for child in pair.into_inner() {
+      tokens.push_str(&Self::collapse(&text[position..child.as_span().start() - start]));
+      position = child.as_span().end() - start;
+      // rules with a trailing repetition keep the whitespace after them
+      let spaced = child.as_str().ends_with(char::is_whitespace);
+      tokens.push_str(&Self::tokens(child));
+      if spaced {
+        tokens.push(' ');
+      }
+    }
+    tokens.push_str(&Self::collapse(&text[position..]));
+    tokens.trim().to_string()
+  }
+
+  fn collapse(text: &str) -> String {
+    let mut collapsed = String::new();
+    for (index, word) in text.split(char::is_whitespace).enumerate() {
+      if index > 0 && !collapsed.ends_with(' ') {
+        collapsed.push(' ');
+      }
+      collapsed.push_str(word);
+    }
+    collapsed
+  }
+
+  fn thickness(name: &str) -> &str {
+    match name {
+      "invis" => "invisible",
+      name => name,
+    }
+  }
+
+  /// operators between spaces, `-` and units against what they belong to
+  fn expression(pair: Pair<Rule>) -> String {
+    let parenthesized = |pair: Pair<Rule>| match pair.as_rule() {
+      Rule::expression => format!("({})", Self::expression(pair)),
+      _ => Self::expression(pair),
+    };
+    match pair.as_rule() {
+      Rule::expression | Rule::term => pair.into_inner().map(parenthesized).collect::<Vec<_>>().join(" "),
+      Rule::negation => format!("-{}", parenthesized(pair.into_inner().next().unwrap())),
+      Rule::function => {
+        let mut inner = pair.into_inner();
+        let name = inner.next().unwrap().as_str();
+        format!("{}({})", name, inner.map(Self::expression).collect::<Vec<_>>().join(", "))
+      }
+      Rule::quantity => pair.into_inner().map(|part| part.as_str()).collect(),
+      _ => pair.as_str().to_string(),
+    }
+  }
+
+  /// formatting may only move attributes around and respell them
+  fn same_parse(original: &Pair<Rule>, formatted: &str) -> bool {
+    let Ok(mut pairs) = Conversion::pairs_for(Rule::picture, formatted) else {
+      return false;
+    };
+    let Some(reparsed) = pairs.next() else {
+      return false;
+    };
+    let (mut expected, mut actual) = (vec![], vec![]);
+    Self::fingerprint(original.clone(), &mut expected);
+    Self::fingerprint(reparsed, &mut actual);
+    expected == actual
+  }
+
+  /// rules and tokens, attributes in order, bodies left out
+  fn fingerprint(pair: Pair<Rule>, prints: &mut Vec<(Rule, String)>) {
+    let rule = pair.as_rule();
+    let mut children = pair.clone().into_inner()
+      .filter(|child| !matches!(rule, Rule::group | Rule::definition | Rule::for_loop) || Self::is_header(child.as_rule()))
+      .collect::<Vec<_>>();
+    if children.is_empty() {
+      let token = match rule {
+        Rule::thickness => Self::thickness(pair.as_str()),
+        _ => pair.as_str(),
+      };
+      prints.push((rule, token.to_string()));
+      return;
+    }
+    if matches!(rule, Rule::closed_attributes | Rule::open_attributes | Rule::arc_attributes | Rule::curve_attributes | Rule::text_attributes | Rule::dot_attributes | Rule::shape_config | Rule::style) {
+      children.sort_by_key(|child| Self::rank(child.as_rule()));
+    }
+    prints.push((rule, String::new()));
+    children.into_iter().for_each(|child| Self::fingerprint(child, prints));
+  }
+}
@@ -0,0 +1,146 @@
+use crate::diagram::format::Formatter;
+use crate::diagram::parser::Diagram;
+use crate::skia::A5;
+
+fn format(text: &str) -> String {
+  Formatter::format(text).unwrap()
+}
+
+/// the same nodes, laid out in the same places
+fn assert_same_layout(text: &str) {
+  let formatted = format(text);
+  let mut before = Diagram::inset(A5, (0., 0.));
+  before.parse_string(text).unwrap();
+  let mut after = Diagram::inset(A5, (0., 0.));
+  after.parse_string(&formatted).unwrap();
+  assert_eq!(format!("{:?}", before.nodes), format!("{:?}", after.nodes), "{}", formatted);
+}
+
+#[test]
+fn spacing_and_aliases() {
+  assert_eq!("box.a \"x\" wd 1 + 2 color red\n", format("box.a   wid 1+2\tstroke red \"x\""));
+  assert_eq!("$gap = 2 * (1cm + 3mm)\n", format("$gap=2*(1cm+3mm)"));
+  assert_eq!("line \"a\" invisible\n", format("line invis \"a\""));
+}
+
+#[test]
+fn attribute_order() {
+  assert_eq!("box \"title\" wd 2cm ht 1cm fill blue\n", format("box fill blue ht 1cm \"title\" wd 2cm"));
+  assert_eq!("box \"title\" wd 2cm ht 1cm fill blue\n", format("box ht 1cm wd 2cm fill blue \"title\""));
+}
+
+#[test]
+fn path_steps_keep_their_order() {
+  assert_eq!("path.fix 1in right to a.se 1in up \"fix\" color red\n", format("path.fix color red 1in right to a.se 1in up \"fix\""));
+}
+
+#[test]
+fn comments_stay() {
+  let text = "# a picture\nbox.a # the first box\n\n\n\n// more\ncircle\n";
+  assert_eq!("# a picture\nbox.a # the first box\n\n// more\ncircle\n", format(text));
+}
+
+#[test]
+fn bodies_are_indented() {
+  let text = "group.g {\n\nbox # one\n    group { circle }\n}\nfor i = 1 to 3 { box \"{i}\" }\n";
+  let expected = "group.g {\n  box # one\n  group {\n    circle\n  }\n}\nfor i = 1 to 3 {\n  box \"{i}\"\n}\n";
+  assert_eq!(expected, format(text));
+}
+
+#[test]
+fn macros() {
+  let text = "define service(name,size){\nbox.api \"{name} API\" wid $size\n}\nservice.orders(\"Orders\",2in)\n";
+  let expected = "define service(name, size) {\n  box.api \"{name} API\" wd $size\n}\nservice.orders(\"Orders\", 2in)\n";
+  assert_eq!(expected, format(text));
+}
+
+#[test]
+fn idempotent() {
+  let train = std::fs::read_to_string("tests/train.pic").unwrap();
+  let styles = std::fs::read_to_string("tests/styles.pic").unwrap();
+  for text in [train.as_str(), styles.as_str(), "box.pic1 ht 2in wd 1in \"Primary Interrupt Controller\"\nfor i = 1 to 8 {\n  line.irq{i} from $i/9 pic1.w 1in left\n}\n"] {
+    let once = format(text);
+    assert_eq!(once, format(&once));
+  }
+}
+
+#[test]
+fn layout_is_kept() {
+  assert_same_layout("box.a fill blue ht 1cm \"title\" wd 2cm\ncircle \"c\" 1 right\narrow from a.e end last circle.w");
+  assert_same_layout(&std::fs::read_to_string("tests/train.pic").unwrap());
+  assert_same_layout(&std::fs::read_to_string("tests/styles.pic").unwrap());
+}
+
+#[test]
+fn check_finds_unformatted() {
+  let formatted = format("box   \"x\"");
+  assert_eq!(formatted, format(&formatted));
+  assert_ne!("box   \"x\"\n", formatted);
+}
+
+#[test]
+fn errors() {
+  assert!(Formatter::format("box wd (1").is_err());
+}
+
+#[test]
+fn styles() {
+  assert_eq!("style loud { class base color red fill yellow }\nbox \"x\" class loud\n", format("style loud{fill yellow stroke red class base}\nbox class loud \"x\""));
+}
+
+#[test]
+fn defaults() {
+  assert_eq!("set arrow color red thick caption above\npush\npop\n", format("set arrow   stroke red thick caption  above\npush\npop"));
+}
+
+#[test]
+fn colors() {
+  assert_eq!("box fill mix(red, lighten(blue, 20%), 30%) opacity 50%\n", format("box opacity 50% fill mix( red,lighten(blue,20%) ,30% )"));
+}
+
+#[test]
+fn sketch() {
+  assert_eq!("set style sketch seed 3 hachure\nbox\n", format("set style sketch  seed 3\n  hachure\nbox"));
+}
+
+#[test]
+fn shadows() {
+  assert_eq!("box thick shadow(2px, 3px, gray)\narrow glow\n", format("box shadow( 2px,3px,gray ) thick\narrow glow"));
+}
+
+#[test]
+fn polygons() {
+  assert_eq!("diamond.ok \"ok?\" wd 1in\nstar fill gold\n", format("diamond.ok  wd 1in \"ok?\"\nstar   fill gold"));
+}
+
+#[test]
+fn arcs() {
+  assert_eq!("arc from a.n to b.n rad 1in cw ->\n", format("arc -> cw from a.n  to b.n rad 1in"));
+}
+
+#[test]
+fn chop() {
+  assert_eq!("arrow from a end b chop\n", format("arrow chop from a end  b"));
+}
+
+#[test]
+fn endings() {
+  assert_eq!("line 1in right }|-o|\narrow <*>->-|>\n", format("line }|-o|  1in right\narrow  <*>->-|>"));
+}
+
+#[test]
+fn strokes() {
+  assert_eq!("arrow 1in right thickness 2.5px dash 6px 3px cap round double ->\n", format("arrow -> double cap  round dash 6px  3px thickness 2.5px 1in right"));
+}
+
+#[test]
+fn curves() {
+  assert_eq!("spline 1in right 1in down control 1cm up ->\n", format("spline -> control 1cm up 1in right 1in down"));
+  assert_eq!("line 1in right 1in down smooth tension 0.5\n", format("line tension 0.5 smooth 1in right 1in down"));
+}
+
+#[test]
+fn shadings() {
+  assert_eq!("box fill gradient(to bottom right, #fff, gray)\n", format("box fill gradient( to bottom  right,#fff ,gray)"));
+  assert_eq!("cylinder fill crosshatch(4px, 30deg)\n", format("cylinder fill crosshatch(4 px,30deg)"));
+}
@@ -1,11 +1,20 @@
 use std::collections::HashMap;
-use std::ops::Add;
+use std::ops::{Add, Sub};
+use std::path::PathBuf;
 
-use log::error;
-use skia_safe::{Point, Rect};
+use pest::iterators::Pair;
+use skia_safe::{Point, Rect, Vector};
 
 use crate::diagram::attributes::{Attributes, OpenAttributes};
-use crate::diagram::types::{Displacement, Edge, Movement, ObjectEdge};
+use crate::diagram::diagnostics::Diagnostics;
+use crate::diagram::edges::EdgeFinder;
+use crate::diagram::expression::Value;
+use crate::diagram::parser::Rule;
+use crate::diagram::router::Router;
+use crate::diagram::source::Source;
+use crate::diagram::theme::Theme;
+use crate::diagram::types::{Config, Displacement, Edge, Movement, ObjectEdge, Polygon, Terminal};
+use crate::skia::{Sketch, FOLD};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ShapeName {
@@ -18,12 +27,16 @@ pub enum ShapeName {
   Text,
   Oval,
   File,
+  Polygon(Polygon),
   Arrow,
   Line,
+  Arc,
+  Curve,
   Path,
 }
 
 impl ShapeName {
+  /// the kind of shape after `last` or an ordinal, as in `2nd path`
   pub fn some(name: &str) -> Option<Self> {
     match name {
       "box" => Some(ShapeName::Box),
@@ -33,23 +46,124 @@ impl ShapeName {
       "circle" => Some(ShapeName::Circle),
       "text" => Some(ShapeName::Text),
       "oval" => Some(ShapeName::Oval),
-      _ => None
+      "file" => Some(ShapeName::File),
+      "dot" => Some(ShapeName::Dot),
+      "group" => Some(ShapeName::Container),
+      "arrow" => Some(ShapeName::Arrow),
+      "line" | "sline" => Some(ShapeName::Line),
+      "arc" => Some(ShapeName::Arc),
+      "curve" | "spline" => Some(ShapeName::Curve),
+      "path" => Some(ShapeName::Path),
+      name => Polygon::some(name).map(ShapeName::Polygon),
     }
   }
+
+  /// the drawn outline of shapes that are not rectangles
+  pub(crate) fn outline(&self, rect: &Rect) -> Option<EdgeFinder> {
+    let (x, y, width, height) = (rect.left, rect.top, rect.width(), rect.height());
+    match self {
+      ShapeName::Circle | ShapeName::Ellipse => Some(EdgeFinder::ellipse(x, y, width, height)),
+      ShapeName::Cylinder => Some(EdgeFinder::cylinder(x, y, width, height)),
+      ShapeName::Oval => Some(EdgeFinder::oval(x, y, width, height)),
+      ShapeName::File => Some(EdgeFinder::file(x, y, width, height, FOLD)),
+      ShapeName::Polygon(polygon) => Some(polygon.outline(rect)),
+      _ => None,
+    }
+  }
+}
+
+impl TryFrom<&str> for ShapeName {
+  type Error = String;
+
+  fn try_from(name: &str) -> Result<Self, Self::Error> {
+    Self::some(name).ok_or_else(|| format!("unknown shape `{}`", name))
+  }
+}
+
+/// `2nd box`, `3rd last circle`, `last arrow` or `previous`, counted within the enclosing group
+#[derive(Debug, PartialEq)]
+pub(crate) struct Ordinal {
+  /// 1 for the first, -1 for the last
+  nth: isize,
+  shape: Option<ShapeName>,
+}
+
+impl Ordinal {
+  /// the words of a reference, as written in an `object_edge`
+  pub(crate) fn parse(reference: &str) -> Option<Self> {
+    let words = reference.split_whitespace().collect::<Vec<_>>();
+    let nth = |word: &str| match word {
+      "first" => Some(1),
+      word => word.strip_suffix(['t', 'd', 'h']).and_then(|word| word.strip_suffix(['s', 'n', 'r', 't']))
+        .and_then(|number| number.parse::<isize>().ok())
+        .filter(|nth| *nth > 0),
+    };
+    let (nth, shape) = match words[..] {
+      ["previous"] | ["last"] => (-1, None),
+      ["last", shape] => (-1, Some(shape)),
+      [nth_word, "last", shape] => (-nth(nth_word)?, Some(shape)),
+      [nth_word, shape] => (nth(nth_word)?, Some(shape)),
+      _ => return None,
+    };
+    let shape = match shape {
+      Some(shape) => Some(ShapeName::some(shape)?),
+      None => None,
+    };
+    Some(Self { nth, shape })
+  }
+}
+
+/// A laid out shape, with the group it is in and where lines start and end
+#[derive(Debug)]
+struct Placed {
+  name: ShapeName,
+  rect: Rect,
+  group: usize,
+  ends: Option<(Point, Point)>,
+}
+
+/// Bindings of a macro expansion, with its own id namespace, or of a loop, without one
+#[derive(Debug, Default)]
+pub(crate) struct Scope {
+  namespace: Option<String>,
+  variables: HashMap<String, Value>,
 }
 
-impl From<&str> for ShapeName {
-  fn from(name: &str) -> Self {
-    Self::some(name).unwrap_or_else(|| panic!("unknown shape {}", name))
+impl Scope {
+  pub(crate) fn new(namespace: impl Into<String>, variables: HashMap<String, Value>) -> Self {
+    Self { namespace: Some(namespace.into()), variables }
+  }
+
+  pub(crate) fn unnamed(variables: HashMap<String, Value>) -> Self {
+    Self { namespace: None, variables }
   }
 }
 
 #[derive(Debug, Default)]
 pub struct Index<'i> {
-  ids: HashMap<String, Rect>,
-  shapes: Vec<(ShapeName, Rect)>,
+  ids: HashMap<String, usize>,
+  shapes: Vec<Placed>,
+  groups: Vec<usize>,
+  group_count: usize,
   open: Vec<(ShapeName, Attributes<'i>)>,
   closed: Vec<(ShapeName, Attributes<'i>)>,
+  variables: HashMap<String, Value>,
+  scopes: Vec<Scope>,
+  macros: HashMap<String, Pair<'i, Rule>>,
+  styles: HashMap<String, Pair<'i, Rule>>,
+  /// the last `theme` statement, which also decides how the picture is painted
+  pub(crate) theme: Option<Theme>,
+  /// the last `set style sketch`, for the whole picture
+  pub(crate) sketch: Option<Sketch>,
+  /// settings saved by `push`, with the group they were pushed in
+  configs: Vec<(usize, Config)>,
+  expansions: usize,
+  placed: Vec<((usize, usize), Rect)>,
+  /// segments of routed lines, for later routes to keep apart from
+  routes: Vec<[Point; 2]>,
+  /// where the text came from, to find the files it names
+  pub(crate) source: Option<&'i Source>,
+  pub(crate) diagnostics: Diagnostics,
 }
 
 impl<'a> Index<'a> {
@@ -74,9 +188,128 @@ impl<'a> Index<'a> {
 
   pub fn insert_shape(&mut self, name: ShapeName, id: Option<&str>, rect: Rect) {
     if let Some(id) = id {
-      self.ids.insert(id.into(), rect);
+      let id = self.interpolate(id);
+      let key = self.namespaces().next().map(|namespace| format!("{}/{}", namespace, id)).unwrap_or(id);
+      self.ids.insert(key, self.shapes.len());
     }
-    self.shapes.push((name, rect));
+    let group = self.groups.last().copied().unwrap_or_default();
+    self.shapes.push(Placed { name, rect, group, ends: None });
+  }
+
+  /// Where the line that was added last starts and ends, for `.start` and `.end`
+  pub(crate) fn insert_ends(&mut self, points: &[Point]) {
+    if let (Some(shape), Some(start), Some(end)) = (self.shapes.last_mut(), points.first(), points.last()) {
+      shape.ends = Some((*start, *end));
+    }
+  }
+
+  pub(crate) fn ids(&self) -> HashMap<String, Rect> {
+    self.ids.iter().map(|(id, shape)| (id.clone(), self.shapes[*shape].rect)).collect()
+  }
+
+  /// Ordinals count the shapes of the group that is laid out from here on
+  pub(crate) fn open_group(&mut self) {
+    self.group_count += 1;
+    self.groups.push(self.group_count);
+  }
+
+  /// what the group pushed and didn't pop is dropped with it
+  pub(crate) fn close_group(&mut self) {
+    if let Some(group) = self.groups.pop() {
+      self.configs.retain(|(pushed, _)| *pushed != group);
+    }
+  }
+
+  /// Remember where the statement at `span` ended up, for editors
+  pub(crate) fn place(&mut self, span: (usize, usize), rect: Rect) {
+    self.placed.push((span, rect));
+  }
+
+  /// Move what was placed since `from` along with the group it is in
+  pub(crate) fn shift_placed(&mut self, from: usize, offset: Point) {
+    self.placed[from..].iter_mut().for_each(|(_, rect)| rect.offset(offset));
+  }
+
+  pub(crate) fn placed(&self) -> &[((usize, usize), Rect)] {
+    &self.placed
+  }
+
+  /// innermost namespace first, each including its parents
+  fn namespaces(&self) -> impl Iterator<Item=String> + '_ {
+    (1..=self.scopes.len()).rev()
+      .filter(|depth| self.scopes[depth - 1].namespace.is_some())
+      .map(|depth| {
+        self.scopes[..depth].iter().filter_map(|scope| scope.namespace.as_deref()).collect::<Vec<_>>().join("/")
+      })
+  }
+
+  /// find an id in the current namespace, then in the ones around it
+  fn id_shape(&self, id: &str) -> Option<&Placed> {
+    let id = self.interpolate(id);
+    self.namespaces()
+      .find_map(|namespace| self.ids.get(&format!("{}/{}", namespace, id)))
+      .or_else(|| self.ids.get(&id))
+      .map(|shape| &self.shapes[*shape])
+  }
+
+  fn ordinal_shape(&self, ordinal: &Ordinal) -> Option<&Placed> {
+    let group = self.groups.last().copied().unwrap_or_default();
+    let mut shapes = self.shapes.iter()
+      .filter(|shape| shape.group == group && ordinal.shape.as_ref().is_none_or(|name| *name == shape.name));
+    match ordinal.nth {
+      nth if nth > 0 => shapes.nth(nth as usize - 1),
+      nth => shapes.rev().nth(nth.unsigned_abs() - 1),
+    }
+  }
+
+  /// the shape an `object_edge` names: the last one, an ordinal, or an id
+  fn shape_for(&self, id: &str) -> Option<&Placed> {
+    match id {
+      "#last" => self.shapes.last(),
+      id if id.contains(' ') || id == "previous" || id == "last" => self.ordinal_shape(&Ordinal::parse(id)?),
+      id => self.id_shape(id),
+    }
+  }
+
+  /// the point on an object, on the outline of shapes that are not rectangles, its start or end for lines
+  fn object_point(&self, object: &ObjectEdge) -> Option<(Rect, Point)> {
+    let shape = self.shape_for(&object.id)?;
+    let point = match (object.terminal, shape.ends) {
+      (Some(Terminal::Start), Some((start, _))) => start,
+      (Some(Terminal::End), Some((_, end))) => end,
+      _ => {
+        let point = object.edge.edge_point(&shape.rect);
+        shape.name.outline(&shape.rect).and_then(|outline| outline.toward(point)).unwrap_or(point)
+      }
+    };
+    Some((shape.rect, point))
+  }
+
+  /// move ends in the middle of a shape out to its outline, along the line
+  pub(crate) fn chop(&self, points: &mut [Point], source: &Option<ObjectEdge>, target: &Option<ObjectEdge>) {
+    let last = points.len().saturating_sub(1);
+    if last == 0 {
+      return;
+    }
+    if let Some(point) = source.as_ref().and_then(|object| self.chopped(object, points[1])) {
+      points[0] = point;
+    }
+    if let Some(point) = target.as_ref().and_then(|object| self.chopped(object, points[last - 1])) {
+      points[last] = point;
+    }
+  }
+
+  fn chopped(&self, object: &ObjectEdge, toward: Point) -> Option<Point> {
+    if object.edge != Edge::center() || object.terminal.is_some() {
+      return None;
+    }
+    let shape = self.shape_for(&object.id)?;
+    let rect = shape.rect;
+    let outline = match &shape.name {
+      ShapeName::Arrow | ShapeName::Line | ShapeName::Arc | ShapeName::Curve | ShapeName::Path => return None,
+      name => name.outline(&rect).unwrap_or_else(|| EdgeFinder::rectangle(rect.left, rect.top, rect.width(), rect.height())),
+    };
+    outline.toward(toward)
   }
 
   pub(crate) fn last_open(&self, shape: ShapeName) -> Option<&(ShapeName, Attributes<'_>)> {
@@ -94,29 +327,162 @@ impl<'a> Index<'a> {
   }
 
   /// modify a rectangle by any edge and displacements
-  pub fn position_rect(&self, location: &Option<(Edge, Vec<Displacement>, ObjectEdge)>, used: &mut Rect) {
+  pub fn position_rect(&mut self, location: &Option<(Edge, Vec<Displacement>, ObjectEdge)>, used: &mut Rect) {
     if let Some((edge, movements, object)) = &location {
-      if let Some(rect) = self.offset_index(object, movements) {
-        *used = Rect::from_xywh(rect.left, rect.top, used.width(), used.height());
-        edge.offset(used);
+      match self.offset_index(object, movements) {
+        Some(rect) => {
+          *used = Rect::from_xywh(rect.left, rect.top, used.width(), used.height());
+          edge.offset(used);
+        }
+        None => self.unknown_object(object)
       }
     }
   }
 
+  fn unknown_object(&mut self, object: &ObjectEdge) {
+    let message = match &*object.id {
+      "#last" => "no previous object".to_string(),
+      id if id.starts_with("last ") && Ordinal::parse(id).is_some() => format!("no previous {}", id.split_whitespace().last().unwrap()),
+      id if Ordinal::parse(id).is_some() => format!("there is no {}", id.split_whitespace().collect::<Vec<_>>().join(" ")),
+      id => format!("unknown object `{}`", id),
+    };
+    self.diagnostics.error_in_statement(message);
+  }
+
   fn offset_index(&self, object: &ObjectEdge, movements: &[Displacement]) -> Option<Rect> {
-    match &*object.id {
-      "#last" => self.shapes.last().map(|(_shape, rect)| rect),
-      id if ShapeName::some(id).is_some() => self.last(ShapeName::some(id).unwrap()).map(|(_shape, rect)| rect),
-      id => self.ids.get(id)
-    }.map(|rect| {
-      Self::offset_from_rect(rect, &object.edge, movements)
+    self.object_point(object).map(|(rect, point)| {
+      let mut rect = Rect::from_xywh(point.x, point.y, rect.width(), rect.height());
+      Self::offset_rect(&mut rect, movements);
+      rect
     })
   }
 
-  fn last(&self, shape: ShapeName) -> Option<&(ShapeName, Rect)> {
-    self.shapes.iter().filter(|(name, _)| {
-      shape == *name
-    }).next_back()
+  /// the rect of an id or an ordinal
+  pub(crate) fn rect_for(&self, id: &str) -> Option<Rect> {
+    self.shape_for(id).map(|shape| shape.rect)
+  }
+
+  /// loops don't keep assignments to themselves, except to their own variable
+  pub(crate) fn assign(&mut self, name: &str, value: Value) {
+    let scope = self.scopes.iter_mut().rev()
+      .find(|scope| scope.namespace.is_some() || scope.variables.contains_key(name));
+    let variables = match scope {
+      Some(scope) => &mut scope.variables,
+      None => &mut self.variables,
+    };
+    variables.insert(name.into(), value);
+  }
+
+  pub(crate) fn variable(&self, name: &str) -> Option<Value> {
+    self.scopes.iter().rev()
+      .find_map(|scope| scope.variables.get(name))
+      .or_else(|| self.variables.get(name))
+      .cloned()
+  }
+
+  /// replace `{name}` with the value of a variable in a macro or loop body, leaving unknown names alone
+  pub(crate) fn interpolate(&self, text: &str) -> String {
+    if self.scopes.is_empty() {
+      return text.into();
+    }
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+      let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+        break;
+      };
+      result.push_str(&rest[..start]);
+      match self.variable(&rest[start + 1..end]) {
+        Some(value) => result.push_str(&value.to_string()),
+        None => result.push_str(&rest[start..=end]),
+      }
+      rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    result
+  }
+
+  /// where a file named at `position` is looked for, like an include; the working directory for text without a source
+  pub(crate) fn dirs(&self, position: usize) -> Vec<PathBuf> {
+    match self.source {
+      Some(source) => source.dirs(position),
+      None => vec![PathBuf::new()],
+    }
+  }
+
+  pub(crate) fn define(&mut self, name: &str, definition: Pair<'a, Rule>) {
+    self.macros.insert(name.into(), definition);
+  }
+
+  pub(crate) fn definition(&self, name: &str) -> Option<Pair<'a, Rule>> {
+    self.macros.get(name).cloned()
+  }
+
+  pub(crate) fn define_style(&mut self, name: &str, style: Pair<'a, Rule>) {
+    self.styles.insert(name.into(), style);
+  }
+
+  /// attributes of the classes named in `attributes`, inherited ones first so later ones win
+  pub(crate) fn class_attributes(&mut self, attributes: &Pair<Rule>) -> Vec<Pair<'a, Rule>> {
+    let mut styled = vec![];
+    for class in attributes.clone().into_inner().filter(|pair| pair.as_rule() == Rule::class) {
+      self.expand_class(class, &mut vec![], &mut styled);
+    }
+    styled
+  }
+
+  fn expand_class(&mut self, class: Pair<Rule>, expanding: &mut Vec<String>, styled: &mut Vec<Pair<'a, Rule>>) {
+    for name in class.clone().into_inner() {
+      let name = name.as_str();
+      let Some(style) = self.styles.get(name).cloned() else {
+        self.diagnostics.error(&class, format!("unknown style `{}`", name));
+        continue;
+      };
+      if expanding.iter().any(|outer| outer == name) {
+        self.diagnostics.error(&class, format!("style `{}` inherits from itself", name));
+        continue;
+      }
+      expanding.push(name.into());
+      for pair in style.into_inner().skip(1) {
+        match pair.as_rule() {
+          Rule::class => self.expand_class(pair, expanding, styled),
+          _ => styled.push(pair),
+        }
+      }
+      expanding.pop();
+    }
+  }
+
+  pub(crate) fn push_config(&mut self, config: Config) {
+    let group = self.groups.last().copied().unwrap_or_default();
+    self.configs.push((group, config));
+  }
+
+  /// the settings pushed last in the same group
+  pub(crate) fn pop_config(&mut self) -> Option<Config> {
+    let group = self.groups.last().copied().unwrap_or_default();
+    match self.configs.last() {
+      Some((pushed, _)) if *pushed == group => self.configs.pop().map(|(_, config)| config),
+      _ => None,
+    }
+  }
+
+  pub(crate) fn enter(&mut self, scope: Scope) {
+    self.scopes.push(scope);
+  }
+
+  pub(crate) fn leave(&mut self) {
+    self.scopes.pop();
+  }
+
+  pub(crate) fn depth(&self) -> usize {
+    self.scopes.len()
+  }
+
+  /// namespace for an expansion without an id of its own
+  pub(crate) fn anonymous(&mut self) -> String {
+    self.expansions += 1;
+    format!("#{}", self.expansions)
   }
 
   pub fn offset_from_rect(rect: &Rect, edge: &Edge, movements: &[Displacement]) -> Rect {
@@ -132,18 +498,24 @@ impl<'a> Index<'a> {
     }
   }
 
-  pub fn point_index(&self, edge: Option<&ObjectEdge>, movements: &[Displacement]) -> Option<Point> {
+  pub fn point_index(&mut self, edge: Option<&ObjectEdge>, movements: &[Displacement]) -> Option<Point> {
     edge.and_then(|edge| {
-      self.ids.get(&edge.id).map(|rect| {
-        Self::point_from_rect(rect, &edge.edge, movements)
-      }).or_else(|| {
-        error!("{} edge id not found", edge.id);
-        None
-      })
+      let point = self.object_point(edge).map(|(_, point)| {
+        Self::offset_point(point, movements)
+      });
+      if point.is_none() {
+        self.unknown_object(edge);
+      }
+      point
     })
   }
 
-  pub fn points_from(&self, start: &Point, source: &Option<ObjectEdge>, movement: &Option<Displacement>, target: &Option<ObjectEdge>, route: bool) -> Vec<Point> {
+  pub fn points_from(&mut self, start: &Point, source: &Option<ObjectEdge>, movement: &Option<Displacement>, target: &Option<ObjectEdge>, route: Option<f32>) -> Vec<Point> {
+    if let (Some(clearance), Some(source), None, Some(target)) = (route, source, movement, target) {
+      if let Some(points) = self.routed(source, target, clearance) {
+        return points;
+      }
+    }
     let mut movements = vec!();
     let mut points = vec!();
     if let Some(object) = source {
@@ -157,17 +529,64 @@ impl<'a> Index<'a> {
     if let Some(object) = target {
       movements.push(Movement::ObjectEnd { object: object.clone() })
     }
-    self.add_movements_as_points(start, &movements, route, &mut points);
+    self.add_movements_as_points(start, &movements, route.is_some(), &mut points);
     points
   }
 
+  /// a route around the shapes in the way, square to the edges it leaves and arrives at,
+  /// or none when there is no way around
+  fn routed(&mut self, source: &ObjectEdge, target: &ObjectEdge, clearance: f32) -> Option<Vec<Point>> {
+    if source.terminal.is_some() || target.terminal.is_some() {
+      return None;
+    }
+    let from = self.shape_for(&source.id)?.rect;
+    let to = self.shape_for(&target.id)?.rect;
+    let (source, leaving) = Self::exit(source, &from, to.center());
+    let (target, entering) = Self::exit(target, &to, from.center());
+    let start = self.point_from(&source)?;
+    let end = self.point_from(&target)?;
+
+    let obstacles = self.shapes.iter()
+      .filter(|shape| !matches!(shape.name, ShapeName::Container | ShapeName::Dot | ShapeName::Arrow | ShapeName::Line
+        | ShapeName::Arc | ShapeName::Curve | ShapeName::Path))
+      .map(|shape| shape.rect)
+      .collect::<Vec<_>>();
+    let Some(points) = Router::new(&obstacles, clearance).route(start, leaving, end, Point::default().sub(entering), &self.routes) else {
+      self.diagnostics.warning_in_statement("no route around the shapes in the way, bending once instead");
+      return None;
+    };
+    self.routes.extend(points.windows(2).map(|pair| [pair[0], pair[1]]));
+    Some(points)
+  }
+
+  /// the edge a route leaves by, the side facing `toward` for a whole object, and the way out of it
+  fn exit(object: &ObjectEdge, rect: &Rect, toward: Point) -> (ObjectEdge, Vector) {
+    let edge = match object.edge == Edge::center() {
+      true => {
+        let delta = toward.sub(rect.center());
+        let side = match delta.x.abs() >= delta.y.abs() {
+          true => if delta.x < 0. { "w" } else { "e" },
+          false => if delta.y < 0. { "n" } else { "s" },
+        };
+        Edge::from(side)
+      }
+      false => object.edge.clone(),
+    };
+    let heading = match edge.x.abs() >= edge.y.abs() {
+      true => Vector::new(edge.x.signum(), 0.),
+      false => Vector::new(0., edge.y.signum()),
+    };
+    (ObjectEdge { edge, ..object.clone() }, heading)
+  }
+
   /// add points from movements to a vector
-  pub fn add_movements_as_points(&self, start: &Point, movements: &[Movement], route: bool, points: &mut Vec<Point>) {
+  /// unknown objects are reported and leave the point where it was
+  pub fn add_movements_as_points(&mut self, start: &Point, movements: &[Movement], route: bool, points: &mut Vec<Point>) {
     let mut last = *start;
     for movement in movements.iter() {
       match movement {
         Movement::ObjectEnd { object } => {
-          let terminal = self.point_from(object).unwrap_or_else(|| panic!("Index to have {:?}", object));
+          let terminal = self.point_index(Some(object), &[]).unwrap_or(last);
           if route {
             if let Some(point) = Self::straighten_point(last, terminal, object.edge.vertical()) {
               points.push(point);
@@ -176,7 +595,7 @@ impl<'a> Index<'a> {
           last = terminal;
         }
         Movement::ObjectStart { object } => {
-          last = self.point_from(object).unwrap_or_else(|| panic!("Index to have {:?}", object));
+          last = self.point_index(Some(object), &[]).unwrap_or(last);
         }
         Movement::Relative { displacement: movement } => {
           last = last.add(movement.offset());
@@ -197,7 +616,7 @@ impl<'a> Index<'a> {
   }
 
   /// return points from movements relative to a start point
-  pub fn points_from_movements(&self, start: &Point, movements: &[Movement]) -> Vec<Point> {
+  pub fn points_from_movements(&mut self, start: &Point, movements: &[Movement]) -> Vec<Point> {
     let mut point = *start;
     let points = movements.iter().map(|movement| {
       match movement {
@@ -206,11 +625,11 @@ impl<'a> Index<'a> {
           point
         }
         Movement::ObjectStart { object } => {
-          point = self.point_from(object).unwrap_or_else(|| panic!("Index to have {:?}", object));
+          point = self.point_index(Some(object), &[]).unwrap_or(point);
           point
         }
         Movement::ObjectEnd { object } => {
-          point = self.point_from(object).unwrap_or_else(|| panic!("Index to have {:?}", object));
+          point = self.point_index(Some(object), &[]).unwrap_or(point);
           point
         }
       }
@@ -219,14 +638,14 @@ impl<'a> Index<'a> {
   }
 
   pub fn point_from(&self, edge: &ObjectEdge) -> Option<Point> {
-    let rect = self.ids.get(&edge.id);
-    rect.map(|rect| {
-      Self::point_from_rect(rect, &edge.edge, &[])
-    })
+    self.object_point(edge).map(|(_, point)| point)
   }
 
   pub fn point_from_rect(rect: &Rect, edge: &Edge, displacements: &[Displacement]) -> Point {
-    let point = edge.edge_point(rect);
+    Self::offset_point(edge.edge_point(rect), displacements)
+  }
+
+  fn offset_point(point: Point, displacements: &[Displacement]) -> Point {
     for displacement in displacements.iter() {
       let _ = point.add(displacement.offset());
     }
@@ -7,16 +7,27 @@ pub mod types;
 pub mod parser;
 pub mod rules;
 pub mod conversion;
+pub(crate) mod colors;
 pub mod renderer;
 pub mod index;
 pub mod edges;
-mod attributes;
+pub mod router;
+pub(crate) mod attributes;
 pub mod bounds;
+pub mod error;
+pub mod diagnostics;
+pub mod expression;
+pub mod source;
+pub mod format;
+pub mod theme;
 
 pub fn create_diagram(string: &str) -> Diagram<'_> {
   init_logging();
   let pad = Length::new(1., Unit::Pc).pixels();
   let mut diagram = Diagram::inset(A5, (pad, pad));
-  diagram.parse_string(string);
+  diagram.parse_string(string).unwrap_or_else(|error| panic!("{}", error));
+  if diagram.diagnostics.has_errors() {
+    panic!("{}", diagram.diagnostics);
+  }
   diagram
 }
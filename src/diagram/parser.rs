@@ -1,18 +1,28 @@
-use log::{debug, warn};
+use log::debug;
 use pest::iterators::{Pair, Pairs};
 use pest_derive::Parser;
 use skia_safe::{Color, ISize, Point, Rect, Size, Vector};
+use std::collections::HashMap;
 use std::ops::Add;
 use std::path::Path;
 
 use crate::diagram::attributes::{Attributes, ClosedAttributes, OpenAttributes};
 use crate::diagram::bounds::Bounds;
+use crate::diagram::colors::Colors;
 use crate::diagram::conversion::Conversion;
-use crate::diagram::index::{Index, ShapeName};
+use crate::diagram::diagnostics::Diagnostics;
+use crate::diagram::error::ParseError;
+use crate::diagram::expression::{Expression, Value};
+use crate::diagram::index::{Index, Scope, ShapeName};
 use crate::diagram::renderer::Renderer;
 use crate::diagram::rules::Rules;
-use crate::diagram::types::{Caption, CommonAttributes, Config, Continuation, Displacement, Edge, EdgeDirection, Ending, Endings, Movement, Node, ObjectEdge, Paragraph, Shape, ShapeConfig, Unit, BLOCK_PADDING, HEIGHT};
-use crate::skia::Canvas;
+use crate::diagram::source::Source;
+use crate::diagram::theme::Theme;
+use crate::diagram::types::{Arc, Caption, Curve, CommonAttributes, Config, Continuation, Displacement, Edge, EdgeDirection, Ending, Endings, Movement, Node, ObjectEdge, Paragraph, Polygon, Shape, ShapeConfig, Unit, BLOCK_PADDING, HEIGHT};
+use crate::pic;
+use crate::pic::lower::Lowering;
+use crate::pic::Dialect;
+use crate::skia::{Canvas, Sketch};
 
 #[cfg(test)]
 mod tests;
@@ -27,6 +37,16 @@ pub struct Diagram<'a> {
   size: ISize,
   inset: Point,
   bounds: Rect,
+  pub diagnostics: Diagnostics,
+  /// what the picture starts with, until a `theme` statement picks another
+  pub theme: Theme,
+  picked: Option<Theme>,
+  /// drawn by hand from the start, until a `set style sketch` statement picks another seed
+  pub sketch: Option<Sketch>,
+  sketched: Option<Sketch>,
+  /// ids and statement spans of the last layout, with where they ended up
+  pub(crate) ids: HashMap<String, Rect>,
+  pub(crate) placed: Vec<((usize, usize), Rect)>,
 }
 
 impl<'i> Diagram<'i> {
@@ -36,44 +56,157 @@ impl<'i> Diagram<'i> {
       size: size.into(),
       inset: inset.into(),
       bounds: Default::default(),
+      diagnostics: Default::default(),
+      theme: Default::default(),
+      picked: None,
+      sketch: None,
+      sketched: None,
+      ids: Default::default(),
+      placed: vec![],
     }
   }
 
-  pub fn parse_string(&mut self, string: &'i str) -> Pairs<'i, Rule> {
-    let top = Conversion::pairs_for(Rule::picture, string);
-    let config = Config::default();
+  pub fn parse_string(&mut self, string: &'i str) -> Result<Pairs<'i, Rule>, Box<ParseError>> {
+    self.parse_text(string, None)
+  }
+
+  fn parse_text(&mut self, string: &'i str, source: Option<&'i Source>) -> Result<Pairs<'i, Rule>, Box<ParseError>> {
+    let top = Conversion::pairs_for(Rule::picture, string)?;
+    let config = Config::themed(Continuation::new("right"), &self.theme);
     let mut index = Index::default();
+    index.source = source;
+    index.diagnostics = std::mem::take(&mut self.diagnostics);
+    index.diagnostics.clear();
+    index.diagnostics.source(string);
 
     let cursor = Point::new(0.5, 0.5);
     let node = Node::Font(config.font.clone());
     let _ast = vec![node];
-    let (ast, bounds) = Self::nodes_from(top.clone(), vec![], &cursor, config, &mut index);
+    let (ast, mut bounds) = Self::nodes_from(top.clone(), vec![], &cursor, config, &mut index);
+    Bounds::bounds_from_shadows(&mut bounds, &ast);
     self.nodes = ast;
     self.bounds = bounds;
-    top
+    self.ids = index.ids();
+    self.placed = index.placed().to_vec();
+    self.picked = index.theme.take();
+    self.sketched = index.sketch.take();
+    self.diagnostics = index.diagnostics;
+    Ok(top)
   }
 
-  pub fn nodes_from<'a>(pairs: Pairs<'a, Rule>, mut ast: Vec<Node<'a>>, offset: &Point, mut config: Config, index: &mut Index<'a>)
+  /// Like `parse_string`, with problems pointing into the file they came from
+  pub fn parse_source(&mut self, source: &'i Source) -> Result<Pairs<'i, Rule>, Box<ParseError>> {
+    let result = self.parse_text(source.text(), Some(source)).map_err(|error| source.relocate_error(error));
+    if result.is_err() {
+      self.diagnostics.clear();
+    }
+    for diagnostic in self.diagnostics.iter_mut() {
+      source.relocate(diagnostic);
+    }
+    for diagnostic in &source.diagnostics {
+      self.diagnostics.push(diagnostic.clone());
+    }
+    result
+  }
+
+  /// Lay out a GNU PIC or pikchr picture, one of those `pic::pictures` finds
+  pub fn parse_pic(&mut self, statements: Pair<'i, pic::Rule>, dialect: Dialect) {
+    let mut diagnostics = std::mem::take(&mut self.diagnostics);
+    diagnostics.clear();
+    let (nodes, bounds, diagnostics) = Lowering::nodes_from(statements, dialect, diagnostics);
+    self.nodes = nodes;
+    self.bounds = bounds;
+    self.diagnostics = diagnostics;
+    self.ids.clear();
+    self.placed.clear();
+    self.picked = None;
+    self.sketched = None;
+  }
+
+  pub fn nodes_from<'a>(pairs: impl IntoIterator<Item=Pair<'a, Rule>>, mut ast: Vec<Node<'a>>, offset: &Point, mut config: Config, index: &mut Index<'a>)
                         -> (Vec<Node<'a>>, Rect) {
     let mut bounds = Rect::from_xywh(offset.x, offset.y, 0., 0.);
     let mut cursor = Point::new(offset.x, offset.y);
+    Self::nodes_into(pairs, &mut ast, &mut bounds, &mut cursor, &mut config, index);
+    (ast, bounds)
+  }
 
+  /// lay out statements after the ones already in `ast`, continuing from the cursor
+  fn nodes_into<'a>(pairs: impl IntoIterator<Item=Pair<'a, Rule>>, ast: &mut Vec<Node<'a>>, bounds: &mut Rect, cursor: &mut Point, config: &mut Config, index: &mut Index<'a>) {
     for pair in pairs.into_iter() {
-      let result = Self::node_from(pair, &mut config, index, &mut cursor);
+      if pair.as_rule() == Rule::for_loop {
+        Self::loop_into(pair, ast, bounds, cursor, config, index);
+        continue;
+      }
+      let span = (pair.as_span().start(), pair.as_span().end());
+      let result = Self::node_from(pair, config, index, cursor);
 
       if let Some((rect, node)) = result {
+        index.place(span, rect);
         match node {
-          Node::Open(_, rect, _) => cursor = config.continuation.end.edge_point(&rect),
-          _ => cursor = config.continuation.end.edge_point(&rect)
+          Node::Open(_, rect, _) => *cursor = config.continuation.end.edge_point(&rect),
+          _ => *cursor = config.continuation.end.edge_point(&rect)
         }
         ast.push(node);
-        Bounds::bounds_from_rect(&mut bounds, rect);
+        Bounds::bounds_from_rect(bounds, rect);
       }
     }
-    (ast, bounds)
   }
 
-  fn shift_nodes(nodes: &mut Vec<Node>, offset: impl Into<Vector>) {
+  /// lay out the body once for every value, as if it was written out that many times
+  fn loop_into<'a>(pair: Pair<'a, Rule>, ast: &mut Vec<Node<'a>>, bounds: &mut Rect, cursor: &mut Point, config: &mut Config, index: &mut Index<'a>) {
+    index.diagnostics.statement(&pair);
+    let name = Conversion::str_for(&pair, Rule::id).unwrap();
+    let values = match Rules::find_rule(&pair, Rule::range) {
+      Some(range) => Self::range_from(range, config, index),
+      None => Rules::get_rule(&pair, Rule::items).into_inner()
+        .map(|item| Self::argument_from(item, config, index))
+        .collect(),
+    };
+    let body = pair.into_inner().filter(|pair| !matches!(pair.as_rule(), Rule::id | Rule::range | Rule::items)).collect::<Vec<_>>();
+
+    for value in values {
+      index.enter(Scope::unnamed(HashMap::from([(name.to_string(), value)])));
+      Self::nodes_into(body.clone(), ast, bounds, cursor, config, index);
+      index.leave();
+    }
+  }
+
+  fn range_from(pair: Pair<Rule>, config: &Config, index: &mut Index) -> Vec<Value> {
+    let span = pair.clone();
+    let mut values = pair.into_inner().map(|expression| Expression::new(&config.unit, index).value_from(expression));
+    let (from, to) = (values.next().unwrap(), values.next().unwrap());
+    let by = values.next().unwrap_or(Value::Number(1.));
+    let (start, end, step) = (Expression::amount(&from), Expression::amount(&to), Expression::amount(&by));
+
+    if step == 0. {
+      index.diagnostics.error(&span, "loop step cannot be zero");
+      return vec![];
+    }
+    let count = ((end - start) / step + 1e-4).floor() + 1.;
+    if count > MAX_ITERATIONS as f32 {
+      index.diagnostics.error(&span, format!("loop runs more than {} times", MAX_ITERATIONS));
+      return vec![];
+    }
+    (0..count.max(0.) as usize).map(|i| {
+      let amount = start + i as f32 * step;
+      match (&from, &to, &by) {
+        (Value::Pixels(_), _, _) | (_, Value::Pixels(_), _) | (_, _, Value::Pixels(_)) => Value::Pixels(amount),
+        _ => Value::Number(amount),
+      }
+    }).collect()
+  }
+
+  /// value of a macro argument or loop item
+  fn argument_from(pair: Pair<Rule>, config: &Config, index: &mut Index) -> Value {
+    match pair.as_rule() {
+      Rule::string => Value::Text(index.interpolate(&Conversion::string_from(pair))),
+      Rule::expression => Expression::new(&config.unit, index).value_from(pair),
+      _ => Value::Text(index.interpolate(pair.as_str())),
+    }
+  }
+
+  pub(crate) fn shift_nodes(nodes: &mut Vec<Node>, offset: impl Into<Vector>) {
     let offset: Point = offset.into();
     for node in nodes {
       match node {
@@ -101,47 +234,121 @@ impl<'i> Diagram<'i> {
   }
 
   fn node_from<'a>(pair: Pair<'a, Rule>, config: &mut Config, index: &mut Index<'a>, cursor: &mut Point) -> Option<(Rect, Node<'a>)> {
+    index.diagnostics.statement(&pair);
     let result = match pair.as_rule() {
       Rule::grid => Some((Rect::new_empty(), Node::Grid)),
-      Rule::canvas => Self::canvas_from(&pair, config),
-      Rule::group => Self::group_from(&pair, config, index, cursor, &config.group),
+      Rule::canvas => Self::canvas_from(&pair, config, index),
+      Rule::group => {
+        let body = Self::group_body(&pair);
+        Self::group_from(&pair, body, None, config, index, cursor, &config.group)
+      }
+      Rule::definition => {
+        let name = Conversion::str_for(&pair, Rule::id).unwrap();
+        index.define(name, pair);
+        None
+      }
+      Rule::style => {
+        let name = Conversion::str_for(&pair, Rule::id).unwrap();
+        index.define_style(name, pair);
+        None
+      }
+      Rule::expansion => Self::expansion_from(&pair, config, index, cursor),
+      Rule::theme => {
+        let name = Conversion::string_for(&pair, Rule::string).unwrap();
+        match Theme::find(&name, &index.dirs(pair.as_span().start())) {
+          Ok(theme) => {
+            let mut themed = Config::themed(config.continuation.clone(), &theme);
+            themed.unit = config.unit;
+            *config = themed;
+            index.theme = Some(theme);
+            let rect = Rect::from_xywh(cursor.x, cursor.y, 0., 0.);
+            Some((rect, Node::Font(config.font.clone())))
+          }
+          Err(message) => {
+            index.diagnostics.error(&pair, message);
+            None
+          }
+        }
+      }
+      Rule::include => {
+        let file = Conversion::string_for(&pair, Rule::string).unwrap();
+        index.diagnostics.error(&pair, format!("cannot include `{}` here, it needs a line of its own", file));
+        None
+      }
       Rule::circle => Self::circle_from(&pair, config, index, cursor),
       Rule::cylinder => Self::cylinder_from(&pair, config, index, cursor),
       Rule::ellipse => Self::ellipse_from(&pair, config, index, cursor),
       Rule::file => Self::file_from(&pair, config, index, cursor),
       Rule::oval => Self::oval_from(&pair, config, index, cursor),
+      Rule::diamond => Self::polygon_from(&pair, Polygon::Diamond, config, index, cursor),
+      Rule::triangle => Self::polygon_from(&pair, Polygon::Triangle, config, index, cursor),
+      Rule::hexagon => Self::polygon_from(&pair, Polygon::Hexagon, config, index, cursor),
+      Rule::parallelogram => Self::polygon_from(&pair, Polygon::Parallelogram, config, index, cursor),
+      Rule::trapezoid => Self::polygon_from(&pair, Polygon::Trapezoid, config, index, cursor),
+      Rule::star => Self::polygon_from(&pair, Polygon::Star, config, index, cursor),
       Rule::rectangle => Self::box_from(&pair, config, index, cursor),
       Rule::arrow => Self::arrow_from(pair, config, index, cursor),
       Rule::line => Self::line_from(pair, config, index, cursor),
       Rule::sline => Self::sline_from(pair, config, index, cursor),
+      Rule::arc => Self::arc_from(pair, config, index, cursor),
+      Rule::curve => Self::curve_from(pair, config, index, cursor),
       Rule::path => Self::path_from(pair, config, index, cursor),
       Rule::text => Self::text_from(&pair, config, index, cursor),
       Rule::dot => Self::dot_from(&pair, config, index, cursor),
-      Rule::flow_to => Self::flow_from(pair, cursor, config),
-      Rule::move_to => Self::move_from(&pair, cursor, config),
+      Rule::flow_to => Self::flow_from(pair, cursor, config, index),
+      Rule::move_to => Self::move_from(&pair, cursor, config, index),
       Rule::font_config => {
-        config.font = Conversion::font_from(pair, &config.unit);
+        let font = Conversion::font_from(pair, &config.unit, index);
+        if let Some(font) = index.diagnostics.report(font) {
+          config.font = font;
+        }
         let rect = Rect::from_xywh(cursor.x, cursor.y, 0., 0.);
         let node = Node::Font(config.font.clone());
         Some((rect, node))
       }
+      Rule::sketch_config => {
+        let (sketch, handwriting) = Conversion::sketch_from(pair, &config.font, index);
+        index.sketch = Some(sketch);
+        let font = index.diagnostics.report(handwriting?)?;
+        config.font = font;
+        Some((Rect::from_xywh(cursor.x, cursor.y, 0., 0.), Node::Font(config.font.clone())))
+      }
       Rule::unit_config => {
-        config.unit = Unit::from(pair.into_inner().as_str());
+        match Unit::try_from(pair.clone().into_inner().as_str()) {
+          Ok(unit) => config.unit = unit,
+          Err(message) => index.diagnostics.error(&pair, message),
+        }
         None
       }
-      Rule::closed_config => {
-        Self::config_shape(config, pair);
+      Rule::shape_config => {
+        Self::config_shape(config, pair, index);
         None
       }
-      Rule::line_config => {
-        let length = Conversion::length_in(&pair, Rule::length, &config.unit).unwrap();
-        config.line = length;
+      Rule::push => {
+        index.push_config(config.clone());
         None
       }
+      Rule::pop => {
+        let Some(saved) = index.pop_config() else {
+          index.diagnostics.error(&pair, "`pop` without a `push`");
+          return None;
+        };
+        let font = saved.font != config.font;
+        // only what `set` changes comes back, the way on and the unit stay
+        *config = Config { continuation: config.continuation.clone(), unit: config.unit, ..saved };
+        font.then(|| (Rect::from_xywh(cursor.x, cursor.y, 0., 0.), Node::Font(config.font.clone())))
+      }
       Rule::continuation => {
         config.continuation = Continuation::new(pair.as_str());
         None
       }
+      Rule::assignment => {
+        let mut inner = pair.into_inner();
+        let name = inner.next().unwrap().into_inner().as_str();
+        let value = Expression::new(&config.unit, index).value_from(inner.next().unwrap());
+        index.assign(name, value);
+        None
+      }
       Rule::continue_from => {
         let direction = Conversion::str_for(&pair, Rule::continue_direction).unwrap();
         config.continuation = Continuation::new(direction);
@@ -150,28 +357,35 @@ impl<'i> Diagram<'i> {
       Rule::comment => None,
       Rule::EOI => None,
       _ => {
-        warn!("Ignored {:?}", pair);
+        index.diagnostics.warning(&pair, format!("ignored {:?}", pair.as_rule()));
         None
       }
     };
     result
   }
 
-  fn canvas_from<'a>(pair: &Pair<'a, Rule>, config: &mut Config) -> Option<(Rect, Node<'a>)> {
+  fn canvas_from<'a>(pair: &Pair<'a, Rule>, config: &mut Config, index: &mut Index) -> Option<(Rect, Node<'a>)> {
     let mut size = Size::new_empty();
     pair.clone().into_inner().for_each(|pair| {
       match pair.as_rule() {
-        Rule::sized => size = Conversion::sized_from(pair, &config.unit),
-        Rule::width => size.width = Conversion::length_from(pair, &config.unit).pixels(),
-        Rule::height => size.height = Conversion::length_from(pair, &config.unit).pixels(),
+        Rule::sized => size = Conversion::sized_from(pair, &config.unit, index),
+        Rule::width => size.width = Conversion::length_from(pair, &config.unit, index).pixels(),
+        Rule::height => size.height = Conversion::length_from(pair, &config.unit, index).pixels(),
         _ => panic!("Unexpected {:?}", pair)
       }
     });
     Some((Rect::from_size(size), Node::Canvas(size)))
   }
 
-  fn group_from<'a>(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index<'a>, cursor: &Point, shape: &ShapeConfig) -> Option<(Rect, Node<'a>)> {
-    let closed = ClosedAttributes::from(pair, config, shape);
+  /// statements inside a group or definition
+  fn group_body<'a>(pair: &Pair<'a, Rule>) -> Vec<Pair<'a, Rule>> {
+    pair.clone().into_inner().filter(|pair| {
+      !matches!(pair.as_rule(), Rule::id | Rule::identified | Rule::parameters | Rule::closed_attributes)
+    }).collect()
+  }
+
+  fn group_from<'a>(pair: &Pair<'a, Rule>, body: Vec<Pair<'a, Rule>>, scope: Option<Scope>, config: &Config, index: &mut Index<'a>, cursor: &Point, shape: &ShapeConfig) -> Option<(Rect, Node<'a>)> {
+    let closed = ClosedAttributes::from(pair, config, index, shape);
     let mut attrs = Self::closed_attrs(closed);
     Self::copy_same_attributes(index, &mut attrs, ShapeName::Container);
 
@@ -189,13 +403,24 @@ impl<'i> Diagram<'i> {
       inset.offset((*padding, *padding));
 
       let original = inset;
+      let placed = index.placed().len();
 
       let (mut nodes, bounds) = {
         let mut config = config.clone();
         Conversion::continuation_in(pair).into_iter().for_each(|continuation| {
           config.continuation = continuation;
         });
-        Self::nodes_from(pair.clone().into_inner(), vec![], &inset, config, index)
+        let scoped = scope.is_some();
+        if let Some(scope) = scope {
+          index.enter(scope);
+        }
+        index.open_group();
+        let nodes = Self::nodes_from(body, vec![], &inset, config, index);
+        index.close_group();
+        if scoped {
+          index.leave();
+        }
+        nodes
       };
 
       let moved = original - Point::new(bounds.left, bounds.top);
@@ -211,6 +436,7 @@ impl<'i> Diagram<'i> {
       Self::adjust_topleft(&config.continuation, &mut shifted);
       let offset = Point::new(shifted.left, shifted.top) - Point::new(used.left, used.top);
       Self::shift_nodes(&mut nodes, offset);
+      index.shift_placed(placed, offset);
 
       index.add(ShapeName::Container, attrs.clone(), shifted);
 
@@ -219,8 +445,40 @@ impl<'i> Diagram<'i> {
     None
   }
 
+  /// Lay out the body of a definition as a group, with the arguments bound to its parameters
+  fn expansion_from<'a>(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index<'a>, cursor: &Point) -> Option<(Rect, Node<'a>)> {
+    let name = Conversion::str_for(pair, Rule::id).unwrap();
+    let Some(definition) = index.definition(name) else {
+      index.diagnostics.error(pair, format!("unknown macro `{}`", name));
+      return None;
+    };
+    if index.depth() >= MAX_EXPANSION_DEPTH {
+      index.diagnostics.error(pair, format!("macro `{}` expands too deep", name));
+      return None;
+    }
+
+    let parameters = Rules::get_rule(&definition, Rule::parameters).into_inner().collect::<Vec<_>>();
+    let arguments = Rules::get_rule(pair, Rule::arguments).into_inner().collect::<Vec<_>>();
+    if parameters.len() != arguments.len() {
+      let message = format!("macro `{}` takes {} arguments, got {}", name, parameters.len(), arguments.len());
+      index.diagnostics.error(pair, message);
+      return None;
+    }
+
+    let variables = parameters.iter().zip(arguments).map(|(parameter, argument)| {
+      (parameter.as_str().to_string(), Self::argument_from(argument, config, index))
+    }).collect::<HashMap<_, _>>();
+
+    let namespace = match Conversion::identified_in(pair) {
+      Some(id) => index.interpolate(id),
+      None => index.anonymous(),
+    };
+    let body = Self::group_body(&definition);
+    Self::group_from(pair, body, Some(Scope::new(namespace, variables)), config, index, cursor, &config.group)
+  }
+
   fn circle_from<'a>(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index<'a>, cursor: &Point) -> Option<(Rect, Node<'a>)> {
-    let closed = ClosedAttributes::from(pair, config, &config.circle);
+    let closed = ClosedAttributes::from(pair, config, index, &config.circle);
     let mut attrs = Self::closed_attrs(closed);
     Self::copy_same_attributes(index, &mut attrs, ShapeName::Circle);
 
@@ -246,7 +504,7 @@ impl<'i> Diagram<'i> {
   }
 
   fn cylinder_from<'a>(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index, cursor: &Point) -> Option<(Rect, Node<'a>)> {
-    let closed = ClosedAttributes::from(pair, config, &config.cylinder);
+    let closed = ClosedAttributes::from(pair, config, index, &config.cylinder);
     let mut attrs = Self::closed_attrs(closed);
     Self::copy_same_attributes(index, &mut attrs, ShapeName::Cylinder);
 
@@ -274,7 +532,7 @@ impl<'i> Diagram<'i> {
   }
 
   fn ellipse_from<'a>(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index, cursor: &Point) -> Option<(Rect, Node<'a>)> {
-    let closed = ClosedAttributes::from(pair, config, &config.ellipse);
+    let closed = ClosedAttributes::from(pair, config, index, &config.ellipse);
     let mut attrs = Self::closed_attrs(closed);
     Self::copy_same_attributes(index, &mut attrs, ShapeName::Ellipse);
 
@@ -301,7 +559,7 @@ impl<'i> Diagram<'i> {
   }
 
   fn file_from<'a>(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index, cursor: &Point) -> Option<(Rect, Node<'a>)> {
-    let closed = ClosedAttributes::from(pair, config, &config.rectangle);
+    let closed = ClosedAttributes::from(pair, config, index, &config.file);
     let mut attrs = Self::closed_attrs(closed);
     Self::copy_same_attributes(index, &mut attrs, ShapeName::File);
 
@@ -329,7 +587,7 @@ impl<'i> Diagram<'i> {
   }
 
   fn oval_from<'a>(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index, cursor: &Point) -> Option<(Rect, Node<'a>)> {
-    let closed = ClosedAttributes::from(pair, config, &config.oval);
+    let closed = ClosedAttributes::from(pair, config, index, &config.oval);
     let mut attrs = Self::closed_attrs(closed);
     Self::copy_same_attributes(index, &mut attrs, ShapeName::Oval);
 
@@ -355,6 +613,37 @@ impl<'i> Diagram<'i> {
     None
   }
 
+  /// the text wraps in the inner area, and the outline grows with it
+  fn polygon_from<'a>(pair: &Pair<'a, Rule>, polygon: Polygon, config: &Config, index: &mut Index<'a>, cursor: &Point) -> Option<(Rect, Node<'a>)> {
+    let shape = config.polygon(polygon);
+    let closed = ClosedAttributes::from(pair, config, index, shape);
+    let mut attrs = Self::closed_attrs(closed);
+    Self::copy_same_attributes(index, &mut attrs, ShapeName::Polygon(polygon));
+
+    if let Attributes::Closed {
+      title,
+      width,
+      height,
+      location,
+      ..
+    } = &attrs
+    {
+      let rect = Self::create_rect(*width, *height, shape);
+      let inner = polygon.inner(&rect);
+      let (paragraph, size) = Self::paragraph_sized_(title.as_deref(), inner.size(), config);
+      let grow = if inner.height() > 0. { size.height / inner.height() } else { 1. };
+      let mut used = Rect::from_point_and_size(*cursor, (rect.width(), rect.height() * grow));
+
+      Self::adjust_topleft(&config.continuation, &mut used);
+      index.position_rect(location, &mut used);
+      index.add(ShapeName::Polygon(polygon), attrs.clone(), used);
+
+      let node = Node::Closed(attrs, used, paragraph, Shape::Polygon(polygon));
+      return Some((used, node));
+    }
+    None
+  }
+
   fn create_rect(width: Option<f32>, height: Option<f32>, config: &ShapeConfig) -> Rect {
     let width = width.unwrap_or(config.width);
     let height = height.unwrap_or(config.height);
@@ -362,7 +651,7 @@ impl<'i> Diagram<'i> {
   }
 
   fn box_from<'a>(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index<'a>, cursor: &Point) -> Option<(Rect, Node<'a>)> {
-    let closed = ClosedAttributes::from(pair, config, &config.rectangle);
+    let closed = ClosedAttributes::from(pair, config, index, &config.rectangle);
     let mut attrs = Self::closed_attrs(closed);
     Self::copy_same_attributes(index, &mut attrs, ShapeName::Box);
 
@@ -411,9 +700,11 @@ impl<'i> Diagram<'i> {
       endings: closed.endings,
       stroke: closed.stroke,
       fill: closed.fill,
+      shading: closed.shading,
       text: closed.text,
       thickness: closed.thickness,
       effect: closed.effect,
+      shadow: closed.shadow,
     }
   }
 
@@ -427,10 +718,10 @@ impl<'i> Diagram<'i> {
   }
 
   fn arrow_from<'a>(pair: Pair<'a, Rule>, config: &Config, index: &mut Index<'a>, cursor: &Point) -> Option<(Rect, Node<'a>)> {
-    let mut open = OpenAttributes::from(&pair, config);
+    let mut open = OpenAttributes::from(&pair, config, index, &config.arrow);
     index.copy_open_attributes(&mut open, ShapeName::Arrow);
 
-    let (mut attrs, _) = Attributes::open_attributes(&pair, config, Rule::open_attributes);
+    let (mut attrs, _) = Attributes::open_attributes(&pair, config, index, &config.arrow, Rule::open_attributes);
     Self::copy_same_attributes(index, &mut attrs, ShapeName::Arrow);
 
     if let Attributes::Open {
@@ -444,18 +735,31 @@ impl<'i> Diagram<'i> {
     } = &attrs
     {
       let displacement = Self::movement_or_default(movement, target, length, &config.continuation.end);
-      let points = index.points_from(cursor, source, &displacement, target, open.route);
-      let rect = Bounds::bounds_from_points(&points);
-      let used = Self::used_with_caption(&caption, rect);
-
-      index.add(ShapeName::Arrow, attrs.clone(), rect);
+      let mut points = index.points_from(cursor, source, &displacement, target, open.route);
+      if open.chop {
+        index.chop(&mut points, source, target);
+      }
 
       let mut endings = endings.clone();
       if endings == Endings::default() {
         endings.end = Ending::Arrow;
       }
 
-      let shape = Shape::Arrow(points, caption.clone(), endings.clone());
+      let (rect, used, shape) = match open.smooth {
+        true => {
+          let curve = Curve::through(&points, open.tension.unwrap_or(1.));
+          let (rect, used) = Self::curve_bounds(&curve, caption);
+          (rect, used, Shape::Curve(curve, caption.clone(), endings))
+        }
+        false => {
+          let rect = Bounds::bounds_from_points(&points);
+          (rect, Self::used_with_caption(&caption, rect), Shape::Arrow(points.clone(), caption.clone(), endings))
+        }
+      };
+
+      index.add(ShapeName::Arrow, attrs.clone(), rect);
+      index.insert_ends(&points);
+
       let node = Node::Open(attrs, rect, shape);
 
       return Some((used, node));
@@ -473,38 +777,55 @@ impl<'i> Diagram<'i> {
     used
   }
 
+  /// Captions of curves go by the middle of the curve
+  fn curve_bounds(curve: &Curve, caption: &Option<Caption>) -> (Rect, Rect) {
+    let rect = Bounds::bounds_from_curve(curve);
+    let mut used = Self::used_with_caption(&caption, Rect::from_point_and_size(curve.point_at(0.5), (0., 0.)));
+    Bounds::bounds_from_rect(&mut used, rect);
+    (rect, used)
+  }
+
   fn line_from<'a>(pair: Pair<'a, Rule>, config: &Config, index: &mut Index<'a>, cursor: &Point) -> Option<(Rect, Node<'a>)> {
-    let mut open = OpenAttributes::from(&pair, config);
+    let mut open = OpenAttributes::from(&pair, config, index, &config.line);
     index.copy_open_attributes(&mut open, ShapeName::Line);
 
-    let (mut attrs, _) = Attributes::open_attributes(&pair, config, Rule::open_attributes);
+    let (mut attrs, _) = Attributes::open_attributes(&pair, config, index, &config.line, Rule::open_attributes);
     Self::copy_same_attributes(index, &mut attrs, ShapeName::Line);
 
-    match &attrs {
-      Attributes::Closed {
-        ..
-      } => panic!("Wrong type"),
-      Attributes::Open {
-        source,
-        target,
-        movement,
-        caption,
-        length,
-        ..
-      } => {
-        let displacement = Self::movement_or_default(movement, target, length, &config.continuation.end);
-        let points = index.points_from(cursor, source, &displacement, target, open.route);
+    if let Attributes::Open {
+      source,
+      target,
+      movement,
+      caption,
+      length,
+      ..
+    } = &attrs
+    {
+      let displacement = Self::movement_or_default(movement, target, length, &config.continuation.end);
+      let mut points = index.points_from(cursor, source, &displacement, target, open.route);
+      if open.chop {
+        index.chop(&mut points, source, target);
+      }
 
-        let rect = Bounds::bounds_from_points(&points);
-        let used = Self::used_with_caption(&caption, rect);
+      let (rect, used, shape) = match open.smooth {
+        true => {
+          let curve = Curve::through(&points, open.tension.unwrap_or(1.));
+          let (rect, used) = Self::curve_bounds(&curve, caption);
+          (rect, used, Shape::Curve(curve, caption.clone(), open.endings))
+        }
+        false => {
+          let rect = Bounds::bounds_from_points(&points);
+          (rect, Self::used_with_caption(&caption, rect), Shape::Line(points.clone(), caption.clone(), open.endings))
+        }
+      };
 
-        index.add(ShapeName::Line, attrs.clone(), rect);
+      index.add(ShapeName::Line, attrs.clone(), rect);
+      index.insert_ends(&points);
 
-        let shape = Shape::Line(points, caption.clone(), open.endings);
-        let node = Node::Open(attrs, rect, shape);
-        Some((used, node))
-      }
+      let node = Node::Open(attrs, rect, shape);
+      return Some((used, node));
     }
+    None
   }
 
   fn movement_or_default(movement: &Option<Displacement>, target: &Option<ObjectEdge>, length: &f32, edge: &Edge) -> Option<Displacement> {
@@ -516,67 +837,175 @@ impl<'i> Diagram<'i> {
   }
 
   fn sline_from<'a>(pair: Pair<'a, Rule>, config: &Config, index: &mut Index<'a>, cursor: &Point) -> Option<(Rect, Node<'a>)> {
-    let (mut attrs, _) = Attributes::open_attributes(&pair, config, Rule::open_attributes);
+    let (mut attrs, _) = Attributes::open_attributes(&pair, config, index, &config.sline, Rule::open_attributes);
     Self::copy_same_attributes(index, &mut attrs, ShapeName::Line);
 
-    match &attrs {
-      Attributes::Closed {
-        ..
-      } => panic!("Wrong type"),
-      Attributes::Open {
-        source,
-        target,
-        movement,
-        caption,
-        length,
-        ref endings,
-        stroke,
-        ..
-      } => {
-        let mut movements = vec!();
-        let mut points = vec!();
-        if let Some(object) = source {
-          movements.push(Movement::ObjectStart { object: object.clone() })
-        } else {
-          points.push(*cursor);
-        }
-        if let Some(movement) = movement {
-          movements.push(Movement::Relative { displacement: movement.clone() })
-        }
-        if let Some(object) = target {
-          movements.push(Movement::ObjectStart { object: object.clone() })
-        }
+    if let Attributes::Open {
+      source,
+      target,
+      movement,
+      caption,
+      length,
+      ref endings,
+      stroke,
+      ..
+    } = &attrs
+    {
+      let mut movements = vec!();
+      let mut points = vec!();
+      if let Some(object) = source {
+        movements.push(Movement::ObjectStart { object: object.clone() })
+      } else {
+        points.push(*cursor);
+      }
+      if let Some(movement) = movement {
+        movements.push(Movement::Relative { displacement: movement.clone() })
+      }
+      if let Some(object) = target {
+        movements.push(Movement::ObjectStart { object: object.clone() })
+      }
 
-        let start = index.point_index(source.as_ref(), &[]).unwrap_or(*cursor);
-        let end = index.point_index(target.as_ref(), &[])
-          .unwrap_or(Self::displace_from_start(start, movement, &config.continuation, *length));
+      let start = index.point_index(source.as_ref(), &[]).unwrap_or(*cursor);
+      let end = index.point_index(target.as_ref(), &[])
+        .unwrap_or(Self::displace_from_start(start, movement, &config.continuation, *length));
 
-        let mut rect = Rect::from_point_and_size(start, (0, 0));
-        Bounds::bounds_from_point(&mut rect, &end);
-        debug!("sline_from {:?} {:?}", pair.as_str(), stroke);
+      let mut rect = Rect::from_point_and_size(start, (0, 0));
+      Bounds::bounds_from_point(&mut rect, &end);
+      debug!("sline_from {:?} {:?}", pair.as_str(), stroke);
 
-        index.add(ShapeName::Line, attrs.clone(), rect);
+      index.add(ShapeName::Line, attrs.clone(), rect);
+      index.insert_ends(&[start, end]);
 
-        let shape = Shape::Sline(vec!(start, end), caption.clone(), endings.clone());
-        let node = Node::Open(attrs, rect, shape);
-        Some((rect, node))
+      let shape = Shape::Sline(vec!(start, end), caption.clone(), endings.clone());
+      let node = Node::Open(attrs, rect, shape);
+      return Some((rect, node));
+    }
+    None
+  }
+
+  /// a quarter circle turning off the flow, or bent from one point to another
+  fn arc_from<'a>(pair: Pair<'a, Rule>, config: &Config, index: &mut Index<'a>, cursor: &Point) -> Option<(Rect, Node<'a>)> {
+    let open = OpenAttributes::from(&pair, config, index, &config.arc);
+    let (mut attrs, _) = Attributes::open_attributes(&pair, config, index, &config.arc, Rule::arc_attributes);
+    Self::copy_same_attributes(index, &mut attrs, ShapeName::Arc);
+
+    if let Attributes::Open {
+      source,
+      target,
+      movement,
+      caption,
+      endings,
+      ..
+    } = &attrs
+    {
+      let start = index.point_index(source.as_ref(), &[]).unwrap_or(*cursor);
+      let to = open.movements.iter().find_map(|movement| match movement {
+        Movement::ObjectStart { object } => Some(object.clone()),
+        _ => None
+      });
+      let end = match target.clone().or(to) {
+        Some(object) => index.point_index(Some(&object), &[]),
+        None => movement.as_ref().map(|movement| start.add(movement.offset())),
+      };
+      let through = end.map(|end| Arc::through(start, end, open.radius, open.clockwise));
+      if let Some(None) = through {
+        index.diagnostics.warning(&pair, "an arc ending where it starts turns with the flow instead");
       }
+      let arc = through.flatten()
+        .unwrap_or_else(|| Arc::turning(start, config.continuation.end.vector(), open.radius.unwrap_or(config.arc.radius), open.clockwise));
+
+      let rect = Bounds::bounds_from_arc(&arc);
+      let used = Self::used_with_caption(&caption, rect);
+      index.add(ShapeName::Arc, attrs.clone(), rect);
+      index.insert_ends(&[arc.start_point(), arc.end_point()]);
+
+      let shape = Shape::Arc(arc, caption.clone(), endings.clone());
+      let node = Node::Open(attrs, rect, shape);
+      return Some((used, node));
     }
+    None
   }
 
   pub(crate) fn path_from<'a>(pair: Pair<'a, Rule>, config: &Config, index: &mut Index<'a>, cursor: &Point) -> Option<(Rect, Node<'a>)> {
-    let open = OpenAttributes::from(&pair, config);
-    let (attrs, _) = Attributes::open_attributes(&pair, config, Rule::open_attributes);
+    let open = OpenAttributes::from(&pair, config, index, &config.path);
+    let (attrs, _) = Attributes::open_attributes(&pair, config, index, &config.path, Rule::open_attributes);
 
     let points = index.points_from_movements(cursor, &open.movements);
+    if open.smooth {
+      let curve = Curve::through(&points, open.tension.unwrap_or(1.));
+      let (rect, used) = Self::curve_bounds(&curve, &open.caption);
+      index.insert_shape(ShapeName::Path, open.id, rect);
+      index.insert_ends(&points);
+      let shape = Shape::Curve(curve, open.caption.clone(), Endings::default());
+      return Some((used, Node::Open(attrs, rect, shape)));
+    }
     let used = Bounds::bounds_from_points(&points);
     index.insert_shape(ShapeName::Path, open.id, used);
+    index.insert_ends(&points);
 
     let shape = Shape::Path(points, open.caption.clone());
     let node = Node::Open(attrs, used, shape);
     Some((used, node))
   }
 
+  /// a curve through the points it moves to, or bent toward its control points
+  fn curve_from<'a>(pair: Pair<'a, Rule>, config: &Config, index: &mut Index<'a>, cursor: &Point) -> Option<(Rect, Node<'a>)> {
+    let open = OpenAttributes::from(&pair, config, index, &config.curve);
+    let (mut attrs, _) = Attributes::open_attributes(&pair, config, index, &config.curve, Rule::curve_attributes);
+    Self::copy_same_attributes(index, &mut attrs, ShapeName::Curve);
+
+    if let Attributes::Open {
+      source,
+      target,
+      length,
+      caption,
+      endings,
+      ..
+    } = &attrs
+    {
+      let start = index.point_index(source.as_ref(), &[]).unwrap_or(*cursor);
+      let mut movements = open.movements.clone();
+      movements.extend(target.clone().map(|object| Movement::ObjectEnd { object }));
+      if movements.is_empty() {
+        let displacement = Displacement::new(*length, Unit::Px, config.continuation.end.clone());
+        movements.push(Movement::Relative { displacement });
+      }
+      let mut points = index.points_from_movements(&start, &movements);
+      let last = points.len() - 1;
+
+      let controls = open.controls.iter().enumerate().map(|(nth, control)| match control {
+        Movement::Relative { displacement } => if nth == 0 { start } else { points[last] }.add(displacement.offset()),
+        Movement::ObjectStart { object } | Movement::ObjectEnd { object } => index.point_index(Some(object), &[]).unwrap_or(start),
+      }).collect::<Vec<_>>();
+      if open.chop {
+        let destination = match movements.last() {
+          Some(Movement::ObjectStart { object } | Movement::ObjectEnd { object }) => Some(object.clone()),
+          _ => None,
+        };
+        let mut guide = match controls.is_empty() {
+          true => points.clone(),
+          false => [&[start], &controls[..], &[points[last]]].concat(),
+        };
+        index.chop(&mut guide, source, &destination);
+        (points[0], points[last]) = (guide[0], guide[guide.len() - 1]);
+      }
+      let (start, end) = (points[0], points[last]);
+      let curve = match controls.is_empty() {
+        true => Curve::through(&points, open.tension.unwrap_or(1.)),
+        false => Curve::controlled(start, &controls, end),
+      };
+
+      let (rect, used) = Self::curve_bounds(&curve, caption);
+      index.add(ShapeName::Curve, attrs.clone(), rect);
+      index.insert_ends(&[start, end]);
+
+      let shape = Shape::Curve(curve, caption.clone(), endings.clone());
+      let node = Node::Open(attrs, rect, shape);
+      return Some((used, node));
+    }
+    None
+  }
+
   fn copy_same_attributes(index: &mut Index, attrs: &mut Attributes, shape: ShapeName) {
     let other = match attrs {
       Attributes::Closed { .. } => index.last_closed(shape.clone()),
@@ -587,9 +1016,14 @@ impl<'i> Diagram<'i> {
 
   fn text_from<'a>(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index, cursor: &Point) -> Option<(Rect, Node<'a>)> {
     let id = Conversion::identified_in(pair);
-    let title = Conversion::string_in(pair, Rule::inner).unwrap();
+    let Some(title) = Conversion::string_in(pair, Rule::inner) else {
+      index.diagnostics.error(pair, "expected a string for text");
+      return None;
+    };
+    let title = &index.interpolate(title);
     let attributes = Rules::find_rule(pair, Rule::text_attributes).unwrap();
-    let location = Conversion::location_for(pair, &config.unit);
+    let location = Conversion::location_for(pair, &config.unit, index)
+      .and_then(|location| index.diagnostics.report(location));
 
     let fit = Rules::dig_rule(&attributes, Rule::fit);
     let paragraph = match fit {
@@ -598,7 +1032,7 @@ impl<'i> Diagram<'i> {
         Paragraph { text: title.into(), widths: vec![bounds.width()], height: bounds.height(), size: bounds.size() }
       }
       None => {
-        let width = Conversion::width_into(&attributes, &config.unit).unwrap_or(config.text.width);
+        let width = Conversion::width_into(&attributes, &config.unit, index).unwrap_or(config.text.width);
         let (widths, height) = config.measure_strings(title, width - 2. * TEXT_PADDING);
         let size = Size::new(width, height);
         Paragraph { text: title.into(), widths, height, size }
@@ -613,52 +1047,45 @@ impl<'i> Diagram<'i> {
 
     index.insert_shape(ShapeName::Text, id, used);
 
-    let common = CommonAttributes::new(id, used, Color::BLACK, 1.);
+    let opacity = Rules::find_rule(&attributes, Rule::opacity).map(Conversion::opacity_from).unwrap_or(1.);
+    let common = CommonAttributes::new(id, used, Colors::faded(config.text.text, opacity), 1.);
     let shape = Shape::Text(paragraph, location);
     let text = Node::Primitive(common, shape);
     Some((used, text))
   }
 
   fn dot_from<'a>(pair: &Pair<'a, Rule>, config: &Config, index: &mut Index, cursor: &Point) -> Option<(Rect, Node<'a>)> {
-    let (mut attrs, attributes) = Attributes::open_attributes(pair, config, Rule::dot_attributes);
+    let (mut attrs, attributes) = Attributes::open_attributes(pair, config, index, &config.dot, Rule::dot_attributes);
     Self::copy_same_attributes(index, &mut attrs, ShapeName::Dot);
 
-    match &attrs {
-      Attributes::Closed {
-        ..
-      } => panic!("Wrong type"),
-      Attributes::Open {
-        id,
-        caption,
-        source,
-        ..
-      } => {
-        let radius = Conversion::radius_into(&attributes, &config.unit).unwrap_or(config.dot.pixels());
-
-        let point = match source {
-          Some(object) => {
-            index.point_index(Some(object), &[]).unwrap()
-          }
-          None => *cursor
-        };
-
-        let mut bounds = Rect::from_xywh(point.x, point.y, 0., 0.);
-        if let Some(caption) = &caption {
-          let rect = Renderer::dot_offset_of(&point, &radius, caption);
-          Bounds::bounds_from_rect(&mut bounds, rect);
-        }
+    if let Attributes::Open {
+      id,
+      caption,
+      source,
+      ..
+    } = &attrs
+    {
+      let radius = Conversion::radius_into(&attributes, &config.unit, index).unwrap_or(config.dot.radius);
 
-        index.insert_shape(ShapeName::Dot, *id, bounds);
+      let point = index.point_index(source.as_ref(), &[]).unwrap_or(*cursor);
 
-        let shape = Shape::Dot(point, radius, caption.clone());
-        let node = Node::Open(attrs, bounds, shape);
-        Some((bounds, node))
+      let mut bounds = Rect::from_xywh(point.x, point.y, 0., 0.);
+      if let Some(caption) = &caption {
+        let rect = Renderer::dot_offset_of(&point, &radius, caption);
+        Bounds::bounds_from_rect(&mut bounds, rect);
       }
+
+      index.insert_shape(ShapeName::Dot, *id, bounds);
+
+      let shape = Shape::Dot(point, radius, caption.clone());
+      let node = Node::Open(attrs, bounds, shape);
+      return Some((bounds, node));
     }
+    None
   }
 
-  fn flow_from<'a>(pair: Pair<'a, Rule>, cursor: &Point, config: &mut Config) -> Option<(Rect, Node<'a>)> {
-    let length = Conversion::length_from(pair, &config.unit);
+  fn flow_from<'a>(pair: Pair<'a, Rule>, cursor: &Point, config: &mut Config, index: &mut Index) -> Option<(Rect, Node<'a>)> {
+    let length = Conversion::length_from(pair, &config.unit, index);
     let mut used = Rect::from_xywh(cursor.x, cursor.y, 0., 0.);
     if config.continuation.end.horizontal() {
       used.right += length.pixels();
@@ -669,8 +1096,8 @@ impl<'i> Diagram<'i> {
     Some((used, node))
   }
 
-  fn move_from<'a>(pair: &Pair<'a, Rule>, cursor: &Point, config: &mut Config) -> Option<(Rect, Node<'a>)> {
-    Conversion::displacements_from(pair, &config.unit).map(|movements| {
+  fn move_from<'a>(pair: &Pair<'a, Rule>, cursor: &Point, config: &mut Config, index: &mut Index) -> Option<(Rect, Node<'a>)> {
+    Conversion::displacements_from(pair, &config.unit, index).map(|movements| {
       let used = if movements.is_empty() {
         let mut used = Rect::from_xywh(cursor.x, cursor.y, HEIGHT.pixels(), HEIGHT.pixels());
         Self::adjust_topleft(&config.continuation, &mut used);
@@ -684,32 +1111,87 @@ impl<'i> Diagram<'i> {
     })
   }
 
-  fn config_shape(config: &mut Config, pair: Pair<Rule>) {
+  /// `set line` also sets arrows and slines
+  fn config_shape(config: &mut Config, pair: Pair<Rule>, index: &mut Index) {
     let mut inner = pair.into_inner();
-    let name = inner.next().unwrap().as_str();
-    let shape = match name {
-      "box" => &mut config.rectangle,
-      "circle" => &mut config.circle,
-      "group" => &mut config.group,
-      &_ => panic!("Not implemented for {:?}", name)
+    let kind = inner.next().unwrap().as_str();
+    let accepted: &[Rule] = match kind {
+      "text" => &[Rule::width, Rule::text_color],
+      "dot" => &[Rule::radius, Rule::stroke, Rule::captioned, Rule::opaque],
+      "path" => &[Rule::stroke, Rule::thickness, Rule::effect, Rule::shadow, Rule::captioned, Rule::opaque],
+      "arrow" | "line" | "sline" => &[Rule::length, Rule::space, Rule::stroke, Rule::thickness, Rule::effect, Rule::shadow, Rule::endings, Rule::captioned, Rule::opaque],
+      "arc" => &[Rule::radius, Rule::stroke, Rule::thickness, Rule::effect, Rule::shadow, Rule::endings, Rule::captioned, Rule::opaque],
+      "curve" | "spline" => &[Rule::length, Rule::stroke, Rule::thickness, Rule::effect, Rule::shadow, Rule::endings, Rule::captioned, Rule::opaque],
+      _ => &[Rule::padding, Rule::width, Rule::height, Rule::radius, Rule::space, Rule::stroke, Rule::fill, Rule::text_color,
+        Rule::thickness, Rule::effect, Rule::shadow, Rule::endings],
     };
 
-    let unit = &config.unit;
-    inner.for_each(|pair| {
-      match pair.as_rule() {
-        Rule::stroke => shape.stroke = Conversion::color_from(pair).unwrap(),
-        Rule::padding => shape.padding = Conversion::length_from(pair, unit).pixels(),
-        Rule::effect => shape.effect = Conversion::effect_from(pair),
-        Rule::height => shape.height = Conversion::length_from(pair, unit).pixels(),
-        Rule::width => shape.width = Conversion::length_from(pair, unit).pixels(),
-        Rule::radius => shape.radius = Conversion::length_from(pair, unit).pixels(),
-        Rule::space => shape.space = Conversion::length_from(pair, unit).pixels(),
-        _ => {
-          warn!("Ignored {:?}", pair);
+    let unit = config.unit;
+    let mut shapes = match kind {
+      "box" => vec![&mut config.rectangle],
+      "circle" => vec![&mut config.circle],
+      "cylinder" => vec![&mut config.cylinder],
+      "ellipse" => vec![&mut config.ellipse],
+      "file" => vec![&mut config.file],
+      "group" => vec![&mut config.group],
+      "oval" => vec![&mut config.oval],
+      "diamond" => vec![&mut config.diamond],
+      "triangle" => vec![&mut config.triangle],
+      "hexagon" => vec![&mut config.hexagon],
+      "parallelogram" => vec![&mut config.parallelogram],
+      "trapezoid" => vec![&mut config.trapezoid],
+      "star" => vec![&mut config.star],
+      "text" => vec![&mut config.text],
+      "dot" => vec![&mut config.dot],
+      "arrow" => vec![&mut config.arrow],
+      "line" => vec![&mut config.line, &mut config.arrow, &mut config.sline],
+      "sline" => vec![&mut config.sline],
+      "arc" => vec![&mut config.arc],
+      "curve" | "spline" => vec![&mut config.curve],
+      _ => vec![&mut config.path],
+    };
+    for pair in inner {
+      let rule = pair.as_rule();
+      if !accepted.contains(&rule) {
+        index.diagnostics.warning(&pair, format!("`set {}` ignores {:?}", kind, rule));
+        continue;
+      }
+      for shape in shapes.iter_mut() {
+        let pair = pair.clone();
+        match rule {
+          Rule::stroke => shape.stroke = index.diagnostics.report(Conversion::color_from(pair)).unwrap_or(shape.stroke),
+          Rule::fill => match Conversion::shading_in(&pair, &unit, index) {
+            Some(shading) => shape.shading = index.diagnostics.report(shading).or(shape.shading.take()),
+            None => {
+              shape.fill = index.diagnostics.report(Conversion::color_from(pair)).unwrap_or(shape.fill);
+              shape.shading = None;
+            }
+          },
+          Rule::text_color => shape.text = index.diagnostics.report(Conversion::color_from(pair)).unwrap_or(shape.text),
+          Rule::padding => shape.padding = Conversion::length_from(pair, &unit, index).pixels(),
+          Rule::height => shape.height = Conversion::length_from(pair, &unit, index).pixels(),
+          Rule::width => shape.width = Conversion::length_from(pair, &unit, index).pixels(),
+          Rule::radius => shape.radius = Conversion::length_from(pair, &unit, index).pixels(),
+          Rule::space => shape.space = Conversion::length_from(pair, &unit, index).pixels(),
+          Rule::length => shape.length = Conversion::length_from(pair, &unit, index).pixels(),
+          Rule::thickness => {
+            let thickness = Conversion::thickness_from(pair, &unit, index);
+            shape.thickness = index.diagnostics.report(thickness).unwrap_or(shape.thickness)
+          }
+          Rule::effect => {
+            let effect = Conversion::effect_from(pair, &unit, index);
+            shape.effect = index.diagnostics.report(effect).unwrap_or(shape.effect.clone())
+          }
+          Rule::shadow => {
+            let shadow = Conversion::shadow_from(pair, &unit, index);
+            shape.shadow = index.diagnostics.report(shadow).or(shape.shadow.take())
+          }
+          Rule::endings => shape.endings = Some(Conversion::endings_from(pair)),
+          Rule::captioned => shape.caption = Some(Conversion::alignment_from(pair.into_inner().next().unwrap())),
+          _ => shape.opaque = true,
         }
       }
-    });
-
+    }
   }
 
   fn displace_from_start(start: Point, movement: &Option<Displacement>, flow: &Continuation, default: f32) -> Point {
@@ -811,7 +1293,8 @@ impl<'i> Diagram<'i> {
   }
 
   pub fn render_to_file(&mut self, filepath: &str) {
-    let mut canvas = Canvas::new(self.size, None);
+    let background = self.picked.as_ref().unwrap_or(&self.theme).background;
+    let mut canvas = Canvas::new(self.size, background);
     self.write_to_file(filepath, &mut canvas);
   }
 
@@ -824,13 +1307,19 @@ impl<'i> Diagram<'i> {
     let rect = rect.with_outset(self.inset);
     let size = ISize::new(rect.width() as i32, rect.height() as i32);
 
-    let mut canvas = Canvas::new(size, background);
+    let theme = self.picked.as_ref().unwrap_or(&self.theme);
+    let mut canvas = Canvas::new(size, background.or(theme.background));
     self.write_to_file(path, &mut canvas);
   }
 
   fn write_to_file<P: AsRef<Path>>(&mut self, filepath: P, canvas: &mut Canvas) {
+    let theme = self.picked.as_ref().unwrap_or(&self.theme);
+    canvas.font = theme.font();
+    canvas.arrowhead = theme.arrowhead;
+    canvas.caption_background = theme.caption_background;
+    canvas.sketch = self.sketched.clone().or_else(|| self.sketch.clone());
     if self.nodes.iter().any(|node| matches!(node, Node::Grid)) {
-      Renderer::render_grid(canvas, self.inset);
+      Renderer::render_grid(canvas, self.inset, theme.grid);
     }
 
     let (dx, dy) = (-self.bounds.left + self.inset.x, -self.bounds.top + self.inset.y);
@@ -843,6 +1332,8 @@ impl<'i> Diagram<'i> {
 }
 
 pub const TEXT_PADDING: f32 = 4.;
+const MAX_EXPANSION_DEPTH: usize = 32;
+const MAX_ITERATIONS: usize = 10_000;
 
 #[allow(dead_code)]
 pub fn dump_nested(level: usize, pairs: Pairs<Rule>) {
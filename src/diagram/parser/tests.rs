@@ -1,15 +1,130 @@
 use std::ops::Mul;
 
-use skia_safe::{Point, Rect, Vector};
+use skia_safe::{Color, Point, Rect, Vector};
 
+use crate::diagram::attributes::Attributes;
 use crate::diagram::conversion::Conversion;
 use crate::diagram::create_diagram;
-use crate::diagram::index::Index;
+use crate::diagram::index::{Index, Ordinal, ShapeName};
 use crate::diagram::parser::{Diagram, Rule};
-use crate::diagram::types::{Config, Displacement, Edge, Node, Unit};
+use crate::diagram::renderer::Renderer;
+use crate::diagram::types::{Arc, Caption, Config, Curve, Displacement, Edge, Ending, Node, Polygon, Shape, Unit};
+use crate::skia::{Canvas, Cap, Effect, Join, Pattern, Shading, Shadow, Sketch, A5};
 
 // static TQBF: &str = "the quick brown fox jumps over the lazy dog";
 
+fn titles(nodes: &[Node]) -> Vec<String> {
+  nodes.iter().filter_map(|node| match node {
+    Node::Closed(Attributes::Closed { title, .. }, ..) => title.clone(),
+    _ => None
+  }).collect()
+}
+
+fn spaces(nodes: &[Node]) -> Vec<f32> {
+  nodes.iter().filter_map(|node| match node {
+    Node::Closed(Attributes::Closed { space, .. }, ..) => Some(*space),
+    _ => None
+  }).collect()
+}
+
+fn rects(nodes: &[Node]) -> Vec<Rect> {
+  nodes.iter().filter_map(|node| match node {
+    Node::Closed(_, used, ..) => Some(*used),
+    _ => None
+  }).collect()
+}
+
+fn lines(nodes: &[Node]) -> Vec<Vec<Point>> {
+  nodes.iter().filter_map(|node| match node {
+    Node::Open(_, _, Shape::Line(points, ..) | Shape::Arrow(points, ..)) => Some(points.clone()),
+    _ => None
+  }).collect()
+}
+
+fn attributes<'a>(nodes: &'a [Node<'a>]) -> Vec<&'a Attributes<'a>> {
+  nodes.iter().filter_map(|node| match node {
+    Node::Closed(attributes, ..) | Node::Open(attributes, ..) => Some(attributes),
+    _ => None
+  }).collect()
+}
+
+fn colors(attributes: &Attributes) -> (Color, Color, Color, f32) {
+  match attributes {
+    Attributes::Closed { stroke, fill, text, thickness, .. } => (*stroke, *fill, *text, *thickness),
+    Attributes::Open { stroke, thickness, .. } => (*stroke, Color::TRANSPARENT, Color::BLACK, *thickness),
+  }
+}
+
+fn caption<'a>(nodes: &'a [Node<'a>]) -> &'a Caption {
+  match nodes.last() {
+    Some(Node::Open(_, _, Shape::Arrow(_, Some(caption), _))) => caption,
+    _ => panic!("Expected captioned arrow"),
+  }
+}
+
+fn fills(nodes: &[Node]) -> Vec<(Color, Option<Shading>)> {
+  nodes.iter().filter_map(|node| match node {
+    Node::Closed(Attributes::Closed { fill, shading, .. }, ..) => Some((*fill, shading.clone())),
+    _ => None
+  }).collect()
+}
+
+fn shadows(nodes: &[Node]) -> Vec<Option<Shadow>> {
+  nodes.iter().filter_map(|node| match node {
+    Node::Closed(attributes, ..) | Node::Open(attributes, ..) | Node::Group(attributes, ..) => Some(attributes.shadow().cloned()),
+    _ => None
+  }).collect()
+}
+
+fn polygons(nodes: &[Node]) -> Vec<(Polygon, Rect)> {
+  nodes.iter().filter_map(|node| match node {
+    Node::Closed(_, used, _, Shape::Polygon(polygon)) => Some((*polygon, *used)),
+    _ => None
+  }).collect()
+}
+
+fn starts(nodes: &[Node]) -> Vec<Point> {
+  nodes.iter().filter_map(|node| match node {
+    Node::Open(_, _, Shape::Line(points, ..)) => points.first().copied(),
+    _ => None
+  }).collect()
+}
+
+fn arcs(nodes: &[Node]) -> Vec<(Arc, Rect)> {
+  nodes.iter().filter_map(|node| match node {
+    Node::Open(_, used, Shape::Arc(arc, ..)) => Some((arc.clone(), *used)),
+    _ => None
+  }).collect()
+}
+
+fn rounded(point: Point) -> (f32, f32) {
+  (point.x.round(), point.y.round())
+}
+
+fn curves(nodes: &[Node]) -> Vec<(Curve, Rect)> {
+  nodes.iter().filter_map(|node| match node {
+    Node::Open(_, used, Shape::Curve(curve, ..)) => Some((curve.clone(), *used)),
+    _ => None
+  }).collect()
+}
+
+/// how close a route comes to a rect, negative when it goes through
+fn distance(points: &[Point], rect: Rect) -> f32 {
+  points.windows(2).map(|pair| {
+    let (a, b) = (pair[0], pair[1]);
+    let x = (rect.left - a.x.max(b.x)).max(a.x.min(b.x) - rect.right);
+    let y = (rect.top - a.y.max(b.y)).max(a.y.min(b.y) - rect.bottom);
+    x.max(y)
+  }).fold(f32::INFINITY, f32::min)
+}
+
+/// every diagnostic of a parse, in order
+fn messages(text: &str) -> Vec<String> {
+  let mut diagram = Diagram::inset(A5, (0., 0.));
+  diagram.parse_string(text).unwrap();
+  diagram.diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect()
+}
+
 #[test]
 fn should_copy_same_attributes_from_line() {
   let mut index = Index::default();
@@ -68,3 +183,611 @@ fn offset_from_rect() {
 }
 
 
+
+#[test]
+fn space_on_closed_shapes() {
+  let diagram = create_diagram("box sp 0.5in\nbox");
+  assert_eq!(vec![48., 0.], spaces(&diagram.nodes));
+  assert!(messages("box sp 0.5in").is_empty());
+}
+
+const SERVICE: &str = r#"
+  define service(name, size) {
+    box.api "{name} API" wd $size
+    cylinder.db "{name} DB"
+    arrow from api.e end db.w
+  }
+"#;
+
+#[test]
+fn macro_expands_as_group() {
+  let string = format!("{}\nservice.orders(\"Orders\", 2in)\nservice.users(\"Users\", 1in) 1in down from orders.s", SERVICE);
+  let diagram = create_diagram(&string);
+
+  let groups = diagram.nodes.iter().filter_map(|node| match node {
+    Node::Group(_, used, nodes) => Some((used, nodes)),
+    _ => None
+  }).collect::<Vec<_>>();
+  assert_eq!(2, groups.len());
+
+  let (orders, nodes) = groups[0];
+  assert_eq!(vec!["Orders API", "Orders DB"], titles(nodes));
+  let Some(Node::Closed(_, api, ..)) = nodes.first() else { panic!("Expected box") };
+  assert_eq!(192., api.width());
+
+  let (users, nodes) = groups[1];
+  assert_eq!(vec!["Users API", "Users DB"], titles(nodes));
+  assert!(users.top > orders.bottom, "{:?} below {:?}", users, orders);
+}
+
+#[test]
+fn unknown_macro() {
+  assert_eq!(vec!["unknown macro `service`"], messages("service(\"Orders\")"));
+}
+
+#[test]
+fn macro_argument_count() {
+  let string = format!("{}\nservice(\"Orders\")", SERVICE);
+  assert_eq!(vec!["macro `service` takes 2 arguments, got 1"], messages(&string));
+}
+
+#[test]
+fn macro_recursion() {
+  assert_eq!(vec!["macro `forever` expands too deep"], messages("define forever() { box forever() }\nforever()"));
+}
+
+#[test]
+fn placeholders_only_in_bodies() {
+  let diagram = create_diagram("$x = 1\ngroup.g{box \"{x}\"}\nfor i = 1 to 1 { box \"{i} {x}\" }");
+  assert!(diagram.ids.contains_key("g"));
+  let Some(Node::Group(_, _, nodes)) = diagram.nodes.first() else { panic!("Expected group") };
+  assert_eq!(vec!["{x}"], titles(nodes));
+  assert_eq!(vec!["1 1"], titles(&diagram.nodes));
+}
+
+#[test]
+fn loop_range() {
+  let diagram = create_diagram("for i = 1 to 4 { box.k{i} wd $i * 10px \"{i}\" }\narrow from k1.s end k4.s");
+  assert_eq!(vec!["1", "2", "3", "4"], titles(&diagram.nodes));
+  let widths = rects(&diagram.nodes).iter().map(|rect| rect.width()).collect::<Vec<_>>();
+  assert_eq!(vec![10., 20., 30., 40.], widths);
+}
+
+#[test]
+fn loop_flows_like_written_out() {
+  let looped = create_diagram("for i = 1 to 3 { box \"{i}\" }");
+  let written = create_diagram("box \"1\"\nbox \"2\"\nbox \"3\"");
+  assert_eq!(rects(&written.nodes), rects(&looped.nodes));
+}
+
+#[test]
+fn loop_items() {
+  let diagram = create_diagram("for label in [\"Timer\", \"Keyboard\", Mouse] { box \"{label}\" }");
+  assert_eq!(vec!["Timer", "Keyboard", "Mouse"], titles(&diagram.nodes));
+}
+
+#[test]
+fn loop_fractions() {
+  let diagram = create_diagram("box.pic1 ht 2in wd 1in\nfor i = 1 to 8 by 7 { line from $i/9 pic1.w 1in left }");
+  let sources = diagram.nodes.iter().filter_map(|node| match node {
+    Node::Open(Attributes::Open { source: Some(source), .. }, ..) => Some(source.edge.y),
+    _ => None
+  }).collect::<Vec<_>>();
+  assert_eq!(vec![1. / 9. - 0.5, 8. / 9. - 0.5], sources);
+}
+
+#[test]
+fn loop_assignments_outlive_iterations() {
+  let diagram = create_diagram("$sum = 0\nfor i = 1 to 4 { $sum = $sum + $i }\nbox wd $sum * 1px");
+  assert_eq!(10., rects(&diagram.nodes)[0].width());
+}
+
+#[test]
+fn loop_zero_step() {
+  assert_eq!(vec!["loop step cannot be zero"], messages("for i = 1 to 4 by 0 { box }"));
+}
+
+#[test]
+fn parse_ordinals() {
+  assert_eq!(Ordinal::parse("2nd box"), Ordinal::parse("2nd   box"));
+  assert!(Ordinal::parse("3rd last circle").is_some());
+  assert!(Ordinal::parse("previous").is_some());
+  assert!(Ordinal::parse("0th box").is_none());
+  assert!(Ordinal::parse("2nd banana").is_none());
+  assert_eq!(Some(ShapeName::Path), ShapeName::some("path"));
+  assert_eq!(Some(ShapeName::Line), ShapeName::some("sline"));
+}
+
+#[test]
+fn ordinals_counted_from_either_end() {
+  let diagram = create_diagram("box\ncircle\nbox\nbox\nline from 1st box.n end 2nd last box.n\nline from first box.s end 3rd box.s");
+  let rects = rects(&diagram.nodes);
+  let lines = lines(&diagram.nodes);
+  assert_eq!(vec![rects[0].center() - Point::new(0., rects[0].height() / 2.), Point::new(rects[2].center_x(), rects[2].top)], lines[0]);
+  assert_eq!(Point::new(rects[3].center_x(), rects[3].bottom), *lines[1].last().unwrap());
+}
+
+#[test]
+fn previous_and_last() {
+  let diagram = create_diagram("box\ncircle\narrow from previous.s 1cm down\nline from last circle.e end last arrow.end");
+  let lines = lines(&diagram.nodes);
+  let circle = rects(&diagram.nodes)[1];
+  assert_eq!(Point::new(circle.center_x(), circle.bottom), lines[0][0]);
+  assert_eq!(Point::new(circle.right, circle.center_y()), lines[1][0]);
+  assert_eq!(*lines[0].last().unwrap(), *lines[1].last().unwrap());
+}
+
+#[test]
+fn start_and_end_of_lines() {
+  let diagram = create_diagram("line 2cm right\narrow from 1st line.end 1cm down\nline from last arrow.start 1cm up\nline from 1st line.start 1cm up");
+  let lines = lines(&diagram.nodes);
+  assert_eq!(*lines[0].last().unwrap(), lines[1][0]);
+  assert_eq!(lines[1][0], lines[2][0]);
+  assert_eq!(lines[0][0], lines[3][0]);
+}
+
+#[test]
+fn ordinals_scoped_to_the_group() {
+  let diagram = create_diagram("box\nbox\ngroup {\n  circle\n  box\n  line from 1st box.e end 1st circle.w\n}\nline from 2nd box.e 1cm right");
+  let Some(Node::Group(_, _, nodes)) = diagram.nodes.get(2) else { panic!("Expected group") };
+  let inner = rects(nodes);
+  assert_eq!(vec![Point::new(inner[1].right, inner[1].center_y()), Point::new(inner[0].left, inner[0].center_y())], lines(nodes)[0]);
+
+  let outer = rects(&diagram.nodes)[1];
+  assert_eq!(Point::new(outer.right, outer.center_y()), lines(&diagram.nodes).last().unwrap()[0]);
+}
+
+#[test]
+fn every_last_scoped_to_the_group() {
+  let text = "box\ngroup {\n  circle 1cm down last box\n  line from last box.e 1cm right\n  circle wd last box.wd\n}";
+  assert_eq!(vec!["no previous box", "no previous box", "unknown object `last box`"], messages(text));
+
+  let diagram = create_diagram("box wd 2in\ngroup {\n  box wd 1in\n  circle 1cm right last box\n  box wd last box.wd\n}");
+  let Some(Node::Group(_, _, nodes)) = diagram.nodes.get(1) else { panic!("Expected group") };
+  let inner = rects(nodes);
+  assert!((inner[1].left - inner[0].right - 96. / 2.54).abs() < 2.);
+  assert_eq!(96., inner[2].width());
+}
+
+#[test]
+fn kinds_only_after_last_or_an_ordinal() {
+  assert_eq!(vec!["unknown object `box`"], messages("box\nline from box.e 1cm right"));
+  assert!(messages("box.line\nsline from line.e 1cm right\nline from last sline.end 1cm down").is_empty());
+}
+
+#[test]
+fn ordinal_properties() {
+  let diagram = create_diagram("box wd 1in\nbox wd 2in\nbox wd 2nd box.wd + 1st box.wd");
+  assert_eq!(288., rects(&diagram.nodes)[2].width());
+}
+
+#[test]
+fn missing_ordinals() {
+  assert_eq!(vec!["there is no 2nd box", "there is no 3rd last circle"], messages("box\nline from 2nd box.n end 3rd  last circle.s"));
+}
+
+#[test]
+fn style_applied_with_class() {
+  let diagram = create_diagram("style warning { stroke red fill pink thick text white }\nbox \"careful\" class warning\nbox");
+  let attributes = attributes(&diagram.nodes);
+  assert_eq!((Color::RED, Color::from_rgb(255, 192, 203), Color::WHITE, 3.), colors(attributes[0]));
+  assert_eq!((Color::BLACK, Color::TRANSPARENT, Color::BLACK, 1.), colors(attributes[1]));
+}
+
+#[test]
+fn explicit_attributes_win_over_style() {
+  let diagram = create_diagram("style warning { stroke red fill pink }\nbox fill blue class warning");
+  let (stroke, fill, ..) = colors(attributes(&diagram.nodes)[0]);
+  assert_eq!((Color::RED, Color::BLUE), (stroke, fill));
+}
+
+#[test]
+fn styles_stacked_and_inherited() {
+  let text = "style base { stroke blue fill gray }\nstyle loud { class base fill yellow thick }\nstyle quiet { stroke green }\nbox class loud, quiet\nbox class quiet, loud";
+  let diagram = create_diagram(text);
+  let attributes = attributes(&diagram.nodes);
+  assert_eq!((Color::GREEN, Color::YELLOW, Color::BLACK, 3.), colors(attributes[0]));
+  assert_eq!((Color::BLUE, Color::YELLOW, Color::BLACK, 3.), colors(attributes[1]));
+}
+
+#[test]
+fn styled_open_shapes() {
+  let diagram = create_diagram("style link { stroke red thick <-> ln 2cm }\nline class link\narrow class link stroke blue");
+  let attributes = attributes(&diagram.nodes);
+  assert_eq!((Color::RED, Color::TRANSPARENT, Color::BLACK, 3.), colors(attributes[0]));
+  assert_eq!(Color::BLUE, colors(attributes[1]).0);
+  let Some(Node::Open(_, rect, Shape::Line(_, _, endings))) = diagram.nodes.first() else { panic!("Expected line") };
+  assert_eq!(Ending::Arrow, endings.start);
+  assert_eq!(78., rect.width());
+}
+
+#[test]
+fn styled_space() {
+  let diagram = create_diagram("style wide { sp 1in }\nbox class wide\nbox sp 0.5in");
+  assert_eq!(vec![96., 48.], spaces(&diagram.nodes));
+}
+
+#[test]
+fn unknown_and_recursive_styles() {
+  let messages = messages("style a { class b }\nstyle b { class a }\nbox class a\nbox class missing");
+  assert_eq!(vec!["style `a` inherits from itself", "unknown style `missing`"], messages);
+}
+
+#[test]
+fn set_open_shapes() {
+  let diagram = create_diagram("set arrow color red thick <-> ln 2cm\narrow\nline");
+  let Some(Node::Open(Attributes::Open { stroke, thickness, .. }, rect, Shape::Arrow(_, _, endings))) = diagram.nodes.first() else { panic!("Expected arrow") };
+  assert_eq!((Color::RED, 3., Ending::Arrow, 78.), (*stroke, *thickness, endings.start.clone(), rect.width()));
+  let Some(Node::Open(Attributes::Open { stroke, .. }, ..)) = diagram.nodes.last() else { panic!("Expected line") };
+  assert_eq!(Color::BLACK, *stroke);
+}
+
+#[test]
+fn set_ending_styles() {
+  let diagram = create_diagram("set arrow <>-|>\narrow\nline 1in right }|->-o|");
+  let endings = diagram.nodes.iter().filter_map(|node| match node {
+    Node::Open(_, _, Shape::Arrow(_, _, endings) | Shape::Line(_, _, endings)) => Some(endings.clone()),
+    _ => None
+  }).collect::<Vec<_>>();
+  assert_eq!((Ending::HollowDiamond, Ending::Hollow, Ending::None), (endings[0].start.clone(), endings[0].end.clone(), endings[0].middle.clone()));
+  assert_eq!((Ending::OneOrMany, Ending::ZeroOrOne, Ending::Arrow), (endings[1].start.clone(), endings[1].end.clone(), endings[1].middle.clone()));
+}
+
+#[test]
+fn set_strokes() {
+  let diagram = create_diagram("style async { dashed }\narrow class async\narrow thickness 2.5px\nset line dash 4px 2px\nline cap round join bevel double");
+  let strokes = attributes(&diagram.nodes).into_iter().map(|attributes| match attributes {
+    Attributes::Open { thickness, effect, cap, join, double, .. } => (*thickness, effect.clone(), *cap, *join, *double),
+    _ => panic!("Expected an open shape"),
+  }).collect::<Vec<_>>();
+  assert_eq!(vec![
+    (1., Effect::Dashed, Cap::Butt, Join::Miter, false),
+    (2.5, Effect::Solid, Cap::Butt, Join::Miter, false),
+    (1., Effect::Dash(vec![4., 2.]), Cap::Round, Join::Bevel, true),
+  ], strokes);
+}
+
+#[test]
+fn set_line_covers_arrows() {
+  let diagram = create_diagram("set line ln 2cm color blue\narrow\nsline\nline");
+  for node in &diagram.nodes {
+    let Node::Open(Attributes::Open { stroke, .. }, rect, _) = node else { continue };
+    assert_eq!((Color::BLUE, 78.), (*stroke, rect.width()));
+  }
+}
+
+#[test]
+fn set_caption_alignment() {
+  let set = create_diagram("set arrow caption above opaque\narrow \"x\"");
+  let explicit = create_diagram("arrow \"x\" above opaque");
+  assert_eq!(caption(&explicit.nodes), caption(&set.nodes));
+}
+
+#[test]
+fn set_closed_shapes() {
+  let diagram = create_diagram("set ellipse fill yellow dashed\nset text text red\nset dot rad 2\nellipse\ntext \"t\"\ndot");
+  let attributes = attributes(&diagram.nodes);
+  let Attributes::Closed { fill, effect, .. } = attributes[0] else { panic!("Expected ellipse") };
+  assert_eq!((Color::YELLOW, "Dashed"), (*fill, format!("{:?}", effect).as_str()));
+  let Some(Node::Primitive(common, Shape::Text(..))) = diagram.nodes.get(1) else { panic!("Expected text") };
+  assert_eq!(Color::RED, common.stroke);
+  let Some(Node::Open(_, _, Shape::Dot(_, radius, _))) = diagram.nodes.last() else { panic!("Expected dot") };
+  assert_eq!(2., *radius);
+}
+
+#[test]
+fn push_and_pop() {
+  let diagram = create_diagram("push\nset box color red\nbox\npop\nbox");
+  let strokes = attributes(&diagram.nodes).iter().map(|attributes| match attributes {
+    Attributes::Closed { stroke, .. } => *stroke,
+    _ => panic!("Expected box"),
+  }).collect::<Vec<_>>();
+  assert_eq!(vec![Color::RED, Color::BLACK], strokes);
+}
+
+#[test]
+fn push_and_pop_in_the_same_group() {
+  assert_eq!(vec!["`pop` without a `push`"], messages("push\ngroup {\n  pop\n}"));
+  assert_eq!(vec!["`pop` without a `push`"], messages("group {\n  push\n}\npop"));
+
+  let diagram = create_diagram("push\nset box color red\ngroup {\n  push\n  set box color blue\n  box\n  pop\n  box\n}\nbox\npop\nbox");
+  let Some(Node::Group(_, _, nodes)) = diagram.nodes.first() else { panic!("Expected group") };
+  let strokes = |nodes| attributes(nodes).iter().map(|attributes| colors(attributes).0).collect::<Vec<_>>();
+  assert_eq!(vec![Color::BLUE, Color::RED], strokes(nodes));
+  assert_eq!(vec![Color::RED, Color::BLACK], strokes(&diagram.nodes[1..]));
+}
+
+#[test]
+fn pop_keeps_the_way_and_the_unit() {
+  let diagram = create_diagram("push\ndown\nset unit in\npop\nbox wd 1\nbox");
+  let rects = rects(&diagram.nodes);
+  assert_eq!(96., rects[0].width());
+  assert_eq!(rects[0].bottom, rects[1].top);
+}
+
+#[test]
+fn set_problems() {
+  assert_eq!(vec!["`pop` without a `push`"], messages("pop\nbox"));
+  assert_eq!(vec!["`set dot` ignores fill"], messages("set dot fill red\ndot"));
+}
+
+#[test]
+fn fill_over_the_fill_color() {
+  let diagram = create_diagram("cylinder fill white fill hatch(red)\nfile fill gradient(white, gray) fill pink");
+  let hatch = Shading::Pattern { pattern: Pattern::Hatch, spacing: 6., angle: 45., color: Some(Color::RED) };
+  assert_eq!(vec![(Color::WHITE, Some(hatch)), (Color::from_rgb(255, 192, 203), None)], fills(&diagram.nodes));
+}
+
+#[test]
+fn fills_styled_set_and_faded() {
+  let diagram = create_diagram("style shaded { fill radial(white, black) }\nset oval fill dots()\nbox class shaded opacity 50%\noval");
+  let fills = fills(&diagram.nodes);
+  assert_eq!(Some(Shading::Radial(vec![Color::from(0x80FFFFFF), Color::from(0x80000000)])), fills[0].1);
+  assert!(matches!(fills[1].1, Some(Shading::Pattern { pattern: Pattern::Dots, .. })));
+}
+
+#[test]
+fn shadows_on_closed_open_and_groups() {
+  let diagram = create_diagram("box shadow
+arrow glow(red)
+group shadow(2px) { circle }
+file");
+  let shadows = shadows(&diagram.nodes);
+  assert_eq!(Some(Vector::new(4., 4.)), shadows[0].as_ref().map(|shadow| shadow.offset));
+  assert_eq!(Some(Shadow { offset: Vector::default(), blur: 6., color: Some(Color::RED) }), shadows[1]);
+  assert_eq!(Some(2.), shadows[2].as_ref().map(|shadow| shadow.offset.x));
+  assert_eq!(None, shadows[3]);
+}
+
+#[test]
+fn shadows_set_and_styled() {
+  let diagram = create_diagram("set cylinder shadow
+set line glow
+style lifted { shadow(6px) }
+cylinder
+line
+arrow
+box class lifted");
+  let blurs = shadows(&diagram.nodes).iter().map(|shadow| shadow.as_ref().map(|shadow| shadow.blur)).collect::<Vec<_>>();
+  assert_eq!(vec![Some(4.), Some(6.), Some(6.), Some(4.)], blurs);
+}
+
+#[test]
+fn shadows_grow_the_bounds() {
+  let plain = create_diagram("box");
+  let shadowed = create_diagram("box shadow(10px, 20px, 5px)");
+  assert_eq!(plain.bounds.left, shadowed.bounds.left);
+  assert_eq!(plain.bounds.right + 17.5, shadowed.bounds.right);
+  assert_eq!(plain.bounds.bottom + 27.5, shadowed.bounds.bottom);
+}
+
+#[test]
+fn sketch_picked_in_the_picture() {
+  assert_eq!(None, create_diagram("box").sketched);
+  assert_eq!(Some(Sketch { seed: 1, hachure: false }), create_diagram("set style sketch\nbox").sketched);
+  assert_eq!(Some(Sketch { seed: 7, hachure: true }), create_diagram("set style sketch hachure seed 7\nbox").sketched);
+}
+
+#[test]
+fn sketch_handwriting() {
+  let fonts = |text| create_diagram(text).nodes.iter().filter(|node| matches!(node, Node::Font(..))).count();
+  assert_eq!(0, fonts("set style sketch seed 2\nbox"));
+  assert_eq!(1, fonts("set style sketch \"Comic Neue\"\nbox"));
+}
+
+#[test]
+fn sketch_same_seed_same_drawing() {
+  let draw = |style: &str| {
+    let text = format!("{}\nbox fill pink\narrow\ncylinder dashed", style);
+    let diagram = create_diagram(&text);
+    let mut canvas = Canvas::new((400, 200), None);
+    canvas.sketch = diagram.sketched.clone();
+    Renderer::render_to_canvas(&mut canvas, &diagram.nodes);
+    canvas.data().as_bytes().to_vec()
+  };
+  let sketched = draw("set style sketch hachure seed 3");
+  assert_eq!(sketched, draw("set style sketch hachure seed 3"));
+  assert_ne!(sketched, draw("set style sketch hachure seed 4"));
+  assert_ne!(sketched, draw(""));
+}
+
+#[test]
+fn polygon_kinds() {
+  let diagram = create_diagram("diamond\ntriangle\nhexagon\nparallelogram\ntrapezoid\nstar");
+  let kinds = polygons(&diagram.nodes).iter().map(|(polygon, _)| *polygon).collect::<Vec<_>>();
+  assert_eq!(vec![Polygon::Diamond, Polygon::Triangle, Polygon::Hexagon, Polygon::Parallelogram, Polygon::Trapezoid, Polygon::Star], kinds);
+}
+
+#[test]
+fn polygon_edges_on_the_outline() {
+  let diagram = create_diagram("diamond.d wd 2in ht 1in\nline from d.ne 1cm right\nline from d.w 1cm left\nline from last diamond.3:00 1cm right\nline from 1st diamond.45 1cm up");
+  let rect = polygons(&diagram.nodes)[0].1;
+  let starts = starts(&diagram.nodes);
+  assert_eq!(Point::new(rect.center_x() + rect.width() / 4., rect.center_y() - rect.height() / 4.), starts[0]);
+  assert_eq!(Point::new(rect.left, rect.center_y()), starts[1]);
+  assert_eq!(Point::new(rect.right, rect.center_y()), starts[2]);
+  assert!(starts[3].x < rect.right && starts[3].y > rect.top);
+}
+
+#[test]
+fn polygon_text_inside() {
+  let plain = create_diagram("diamond");
+  let titled = create_diagram("diamond \"a title long enough to wrap over more lines\"");
+  let (_, plain) = polygons(&plain.nodes)[0];
+  let (_, used) = polygons(&titled.nodes)[0];
+  assert_eq!(plain.width(), used.width());
+  assert!(used.height() > plain.height());
+  let Some(Node::Closed(_, _, Some(paragraph), _)) = titled.nodes.iter().find(|node| matches!(node, Node::Closed(..))) else { panic!("Expected a title") };
+  assert_eq!(Polygon::Diamond.inner(&used).width(), paragraph.size.width);
+}
+
+#[test]
+fn polygons_set_and_same() {
+  let diagram = create_diagram("set star fill gold\nstar\nhexagon wd 2in\nhexagon same");
+  let fills = fills(&diagram.nodes);
+  assert_eq!(Color::new(0xFFFFD700), fills[0].0);
+  assert_eq!(Color::TRANSPARENT, fills[1].0);
+  assert_eq!(polygons(&diagram.nodes)[1].1.width(), polygons(&diagram.nodes)[2].1.width());
+}
+
+#[test]
+fn arc_turning_with_the_flow() {
+  let diagram = create_diagram("arc rad 1in\ndown\narc cw");
+  let arcs = arcs(&diagram.nodes);
+  let (first, used) = &arcs[0];
+  assert_eq!((96., 96.), (used.width().round(), used.height().round()));
+  assert_eq!((96., -96.), rounded(first.end_point() - first.start_point()));
+  let (second, _) = &arcs[1];
+  assert_eq!((-48., 48.), rounded(second.end_point() - second.start_point()));
+}
+
+#[test]
+fn arc_between_points() {
+  let diagram = create_diagram("box.a\nbox.b\narc from a.n to b.n cw ->\narc from a.s end b.s rad 0.1in\nline from 2nd arc.end 1cm down");
+  let rects = rects(&diagram.nodes);
+  let (a, b) = (rects[0], rects[1]);
+  let arcs = arcs(&diagram.nodes);
+  assert_eq!(rounded(Point::new(a.center_x(), a.top)), rounded(arcs[0].0.start_point()));
+  assert_eq!(rounded(Point::new(b.center_x(), b.top)), rounded(arcs[0].0.end_point()));
+  assert!(arcs[0].1.top < a.top && arcs[1].1.bottom > a.bottom);
+  assert_eq!(180., arcs[1].0.sweep.abs().round());
+  let Some(Node::Open(_, _, Shape::Line(points, ..))) = diagram.nodes.last() else { panic!("Expected a line") };
+  assert_eq!(rounded(arcs[1].0.end_point()), rounded(points[0]));
+}
+
+#[test]
+fn arc_ending_where_it_starts() {
+  assert_eq!(vec!["an arc ending where it starts turns with the flow instead"], messages("box.a\narc from a.n to a.n"));
+  let diagram = create_diagram("box.a\narc from a.n to a.n");
+  let (arc, _) = &arcs(&diagram.nodes)[0];
+  assert!(arc.center.x.is_finite() && arc.center.y.is_finite());
+}
+
+#[test]
+fn arc_endings_and_captions() {
+  let diagram = create_diagram("set arc ->\narc \"loop\" above\narc <-");
+  let endings = diagram.nodes.iter().filter_map(|node| match node {
+    Node::Open(_, _, Shape::Arc(_, caption, endings)) => Some((caption.is_some(), endings.start.clone(), endings.end.clone())),
+    _ => None
+  }).collect::<Vec<_>>();
+  assert_eq!(vec![(true, Ending::None, Ending::Arrow), (false, Ending::Arrow, Ending::None)], endings);
+}
+
+#[test]
+fn curve_through_movements() {
+  let diagram = create_diagram("curve 1in right 1in down 1in right\nspline");
+  let curves = curves(&diagram.nodes);
+  let (first, used) = &curves[0];
+  assert_eq!(3, first.0.len());
+  assert_eq!((192., 96.), rounded(first.end() - first.start()));
+  assert!(used.height() > 96.);
+  let (second, _) = &curves[1];
+  assert_eq!(1, second.0.len());
+}
+
+#[test]
+fn curve_with_controls() {
+  let diagram = create_diagram("box.a\nbox.b\ncurve from a.s to b.s control 1in down control 1in down ->");
+  let curves = curves(&diagram.nodes);
+  let (curve, used) = &curves[0];
+  assert_eq!(1, curve.0.len());
+  let [start, first, second, end] = curve.0[0];
+  assert_eq!((0., 96.), rounded(first - start));
+  assert_eq!((0., 96.), rounded(second - end));
+  assert!(used.bottom > start.y);
+  let Some(Node::Open(_, _, Shape::Curve(_, _, endings))) = diagram.nodes.last() else { panic!("Expected a curve") };
+  assert_eq!(Ending::Arrow, endings.end);
+}
+
+#[test]
+fn smooth_lines_and_paths() {
+  let diagram = create_diagram("line 1in right 1in down smooth\narrow 1in up 1in right smooth tension 0.5\npath 1in right 1in up smooth\nline 1in right");
+  let kinds = diagram.nodes.iter().filter_map(|node| match node {
+    Node::Open(_, _, Shape::Curve(_, _, endings)) => Some(endings.end == Ending::Arrow),
+    Node::Open(..) => None,
+    _ => None
+  }).collect::<Vec<_>>();
+  assert_eq!(vec![false, true, false], kinds);
+  assert!(matches!(diagram.nodes.last(), Some(Node::Open(_, _, Shape::Line(..)))));
+}
+
+#[test]
+fn curve_caption_in_the_middle() {
+  let diagram = create_diagram("curve 1in right 1in up 1in right \"flow\" above");
+  let (curve, used) = &curves(&diagram.nodes)[0];
+  let middle = curve.point_at(0.5);
+  assert!(used.top < middle.y - 10.);
+  let Some(Node::Open(_, _, Shape::Curve(_, caption, _))) = diagram.nodes.first() else { panic!("Expected a curve") };
+  assert!(caption.is_some());
+}
+
+#[test]
+fn outline_anchors() {
+  let diagram = create_diagram("circle.c\nline from c.ne 1cm right\nline from c.30 1cm up\noval\nline from last oval.se 1cm down\ncylinder\nline from last cylinder.n 1cm up");
+  let rects = rects(&diagram.nodes);
+  let lines = lines(&diagram.nodes);
+  let (circle, oval, cylinder) = (rects[0], rects[1], rects[2]);
+  let radius = (circle.width() / 2.).round();
+  assert_eq!(radius, (lines[0][0] - circle.center()).length().round());
+  assert_eq!(radius, (lines[1][0] - circle.center()).length().round());
+  assert!(lines[0][0].x < circle.right && lines[0][0].y > circle.top);
+  assert!(lines[2][0].x < oval.right && lines[2][0].y < oval.bottom);
+  assert_eq!(rounded(Point::new(cylinder.center_x(), cylinder.top)), rounded(lines[3][0]));
+}
+
+#[test]
+fn chop() {
+  let diagram = create_diagram("circle.a\nbox.b .w 1in right from a.e\narrow from a end b chop\nline from a end b\nline from a.n end b chop\ncurve from a to b chop");
+  let rects = rects(&diagram.nodes);
+  let lines = lines(&diagram.nodes);
+  let (a, b) = (rects[0], rects[1]);
+  assert_eq!(rounded(Point::new(a.right, a.center_y())), rounded(lines[0][0]));
+  assert_eq!(rounded(Point::new(b.left, b.center_y())), rounded(lines[0][1]));
+  assert_eq!(vec![a.center(), b.center()], lines[1]);
+  assert_eq!(rounded(Point::new(a.center_x(), a.top)), rounded(lines[2][0]));
+  assert!(lines[2][1].x == b.left || lines[2][1].y == b.top || lines[2][1].y == b.bottom);
+  let Some(Node::Open(_, _, Shape::Curve(curve, ..))) = diagram.nodes.last() else { panic!("Expected a curve") };
+  assert_eq!(rounded(lines[0][0]), rounded(curve.start()));
+  assert_eq!(rounded(lines[0][1]), rounded(curve.end()));
+}
+
+#[test]
+fn route_around_shapes() {
+  let diagram = create_diagram("box.a\nbox.x .w 0.5in right from a.e\nbox.b .w 0.5in right from x.e\narrow from a end b route");
+  let rects = rects(&diagram.nodes);
+  let (a, x, b) = (rects[0], rects[1], rects[2]);
+  let points = &lines(&diagram.nodes)[0];
+  assert_eq!(Point::new(a.right, a.center_y()), points[0]);
+  assert_eq!(Point::new(b.left, b.center_y()), *points.last().unwrap());
+  assert!(points.windows(2).all(|pair| pair[0].x == pair[1].x || pair[0].y == pair[1].y));
+  assert_eq!(12., distance(points, x).round());
+}
+
+#[test]
+fn route_clearance() {
+  let diagram = create_diagram("set arrow space 20px\nbox.a\nbox.x .w 0.5in right from a.e\nbox.b .w 0.5in right from x.e\narrow from a end b route");
+  let x = rects(&diagram.nodes)[1];
+  assert_eq!(20., distance(&lines(&diagram.nodes)[0], x).round());
+}
+
+#[test]
+fn routes_apart() {
+  let diagram = create_diagram("box.a\nbox.x .w 0.5in right from a.e\nbox.b .w 0.5in right from x.e\narrow from a end b route\narrow from a end b route");
+  let lines = lines(&diagram.nodes);
+  let middle = |points: &[Point]| points[2..points.len() - 2].to_vec();
+  assert_ne!(middle(&lines[0]), middle(&lines[1]));
+}
+
+#[test]
+fn route_without_a_way_around() {
+  let text = "box.a\nbox.x wd 0.5in .w 5px right from a.e\nbox.b .w 1in right from x.e\narrow from a end b route";
+  assert_eq!(vec!["no route around the shapes in the way, bending once instead"], messages(text));
+}
+
+#[test]
+fn route_one_bend_as_before() {
+  let diagram = create_diagram("box.a\nbox.b .w 1in right 1in up from a.e\narrow route from a.n end b.w");
+  let rects = rects(&diagram.nodes);
+  let (a, b) = (rects[0], rects[1]);
+  assert_eq!(vec![Point::new(a.center_x(), a.top), Point::new(a.center_x(), b.center_y()), Point::new(b.left, b.center_y())], lines(&diagram.nodes)[0]);
+}
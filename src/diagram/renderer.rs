@@ -1,26 +1,26 @@
 use std::f32::consts::PI;
-use std::ops::Sub;
+use std::ops::{Add, Mul, Sub};
 
 use log::warn;
 use skia_safe::textlayout::TextAlign;
-use skia_safe::{Color, PaintStyle, Point, Rect};
+use skia_safe::{Color, PaintStyle, Point, Rect, Vector};
 
 use crate::diagram::attributes::Attributes;
 use crate::diagram::parser::TEXT_PADDING;
 use crate::diagram::types::Node::{Closed, Group, Open, Primitive};
 use crate::diagram::types::{Caption, Ending, Endings, Length, Node, Paragraph, Radius, Shape, Unit};
-use crate::skia::Canvas;
+use crate::skia::{Canvas, Cap, Effect, Join, Pattern, Shading};
 use crate::skia::Effect::{Dotted, Solid};
 
 pub struct Renderer {}
 
 impl Renderer {
-  pub fn render_grid(canvas: &mut Canvas, inset: Point) {
+  pub fn render_grid(canvas: &mut Canvas, inset: Point, color: Color) {
     let step = Length::new(0.25, Unit::In).pixels();
 
     canvas.save();
     canvas.translate(-(step - inset.x) + 0.5, -(step - inset.y) + 0.5);
-    canvas.stroke_with(1.0, Color::GRAY, &Dotted);
+    canvas.stroke_with(1.0, color, &Dotted);
 
     let (width, height) = (canvas.surface.width() + step as i32, canvas.surface.height() + step as i32);
 
@@ -43,42 +43,62 @@ impl Renderer {
       canvas.paint.set_stroke_width(1.0);
 
       match node {
-        Group(Attributes::Closed { radius, title, thickness, effect, stroke, .. }, used, nodes) => {
+        Group(Attributes::Closed { radius, title, thickness, effect, stroke, fill, shading, text, shadow, .. }, used, nodes) => {
+          let background = |canvas: &mut Canvas| Self::fill(canvas, used, *fill, shading, *stroke, |canvas| canvas.rectangle(used, *radius));
+          let frame = |canvas: &mut Canvas| if thickness > &0. {
+            canvas.stroke_with(*thickness, *stroke, effect);
+            canvas.rectangle(used, *radius);
+          };
+          if let Some(shadow) = shadow {
+            canvas.shadow_with(shadow, *stroke);
+            background(canvas);
+            frame(canvas);
+            canvas.restore();
+          }
+
+          background(canvas);
           Self::render_to_canvas(canvas, nodes);
 
           if let Some(title) = title {
-            canvas.fill_with(Color::BLACK);
+            canvas.fill_with(*text);
             let inset = used.with_inset((TEXT_PADDING, TEXT_PADDING));
             let origin = (inset.left, inset.bottom - 16.);
             canvas.draw_paragraph(title, origin, inset.width());
           }
-
-          if thickness > &0. {
-            canvas.stroke_with(*thickness, *stroke, effect);
-            canvas.rectangle(used, *radius);
-          }
+          frame(canvas);
         }
         Primitive(common, shape) => {
           let used = Self::align_rect(&common.used, common.thickness);
-          Self::render_shape(canvas, &used, &common.stroke, shape, &common.thickness);
+          Self::render_shape(canvas, &used, &common.stroke, shape, &common.thickness, &Solid);
         }
-        Open(Attributes::Open { thickness, stroke, .. }, used, shape) => {
+        Open(Attributes::Open { thickness, stroke, effect, cap, join, double, shadow, .. }, used, shape) => {
           let used = Self::align_rect(used, *thickness);
-          Self::render_shape(canvas, &used, stroke, shape, thickness);
+          canvas.cap_with(*cap, *join);
+          canvas.double = *double;
+          if let Some(shadow) = shadow {
+            canvas.shadow_with(shadow, *stroke);
+            Self::render_shape(canvas, &used, stroke, shape, thickness, effect);
+            canvas.restore();
+          }
+          Self::render_shape(canvas, &used, stroke, shape, thickness, effect);
+          canvas.cap_with(Cap::default(), Join::default());
+          canvas.double = false;
         }
-        Closed(Attributes::Closed { radius, thickness, effect, stroke, fill, text, location, endings, .. }, used, paragraph, shape) => {
+        Closed(Attributes::Closed { radius, thickness, effect, stroke, fill, shading, text, location, endings, shadow, .. }, used, paragraph, shape) => {
           let used = Self::align_rect(used, *thickness);
 
-          canvas.stroke_with(*thickness, *stroke, effect);
-          match shape {
-            Shape::Rectangle => canvas.rectangle(&used, *radius),
-            Shape::Circle => canvas.circle(&used.center(), used.width() / 2.),
-            Shape::Ellipse => canvas.ellipse(&used),
-            Shape::File => canvas.file(&used),
-            Shape::Oval => canvas.oval(&used),
-            Shape::Cylinder => canvas.cylinder(&used),
-            _ => {}
+          let outline = |canvas: &mut Canvas| Self::outline(canvas, &used, *radius, shape);
+          let body = |canvas: &mut Canvas| {
+            Self::fill(canvas, &used, *fill, shading, *stroke, outline);
+            canvas.stroke_with(*thickness, *stroke, effect);
+            outline(canvas);
+          };
+          if let Some(shadow) = shadow {
+            canvas.shadow_with(shadow, *stroke);
+            body(canvas);
+            canvas.restore();
           }
+          body(canvas);
 
           if let (Some(_endings), Some((my, displacements, _))) = (endings, location) {
             let mut points: Vec<Point> = vec![];
@@ -91,24 +111,14 @@ impl Renderer {
               canvas.line_to(point.x, point.y);
             }
 
+            Self::stroke_open(canvas, _endings);
+
             let start = points.first().unwrap();
             let end = points.get(1).unwrap();
             Self::draw_ending(&_endings.start, start, end, canvas);
             let start = points.get(points.len() - 2).unwrap();
             let end = points.last().unwrap();
             Self::draw_ending(&_endings.end, end, start, canvas);
-            canvas.stroke();
-          }
-
-          canvas.fill_with(*fill);
-          match shape {
-            Shape::Rectangle => canvas.rectangle(&used, *radius),
-            Shape::Circle => canvas.circle(&used.center(), used.width() / 2.),
-            Shape::Ellipse => canvas.ellipse(&used),
-            Shape::File => canvas.file(&used),
-            Shape::Oval => canvas.oval(&used),
-            Shape::Cylinder => canvas.cylinder(&used),
-            _ => {}
           }
 
           match shape {
@@ -116,6 +126,7 @@ impl Renderer {
               let rect = Rect::from_xywh(used.left, used.top + used.height() / 3., used.width(), used.height() * 0.666);
               Self::paint_paragraph(canvas, &rect, text, paragraph);
             }
+            Shape::Polygon(polygon) => Self::paint_paragraph(canvas, &polygon.inner(&used), text, paragraph),
             _ => Self::paint_paragraph(canvas, &used, text, paragraph)
           }
         }
@@ -128,8 +139,37 @@ impl Renderer {
     }
   }
 
-  fn render_shape(canvas: &mut Canvas, used: &Rect, color: &Color, shape: &Shape, thickness: &f32) {
-    canvas.stroke_with(*thickness, *color, &Solid);
+  /// the fill color, hatched when sketching by hand, then any gradient or pattern over it
+  fn fill(canvas: &mut Canvas, used: &Rect, fill: Color, shading: &Option<Shading>, stroke: Color, outline: impl Fn(&mut Canvas)) {
+    match &canvas.sketch {
+      Some(sketch) if sketch.hachure && fill.a() > 0 => {
+        let hachure = Shading::Pattern { pattern: Pattern::Hatch, spacing: 6., angle: 45., color: Some(fill) };
+        canvas.shade_with(&hachure, used, stroke);
+      }
+      _ => canvas.fill_with(fill),
+    }
+    outline(canvas);
+    if let Some(shading) = shading {
+      canvas.shade_with(shading, used, stroke);
+      outline(canvas);
+    }
+  }
+
+  fn outline(canvas: &mut Canvas, used: &Rect, radius: f32, shape: &Shape) {
+    match shape {
+      Shape::Rectangle => canvas.rectangle(used, radius),
+      Shape::Circle => canvas.circle(&used.center(), used.width() / 2.),
+      Shape::Ellipse => canvas.ellipse(used),
+      Shape::File => canvas.file(used),
+      Shape::Oval => canvas.oval(used),
+      Shape::Cylinder => canvas.cylinder(used),
+      Shape::Polygon(polygon) => canvas.polygon(&polygon.outline(used).corners()),
+      _ => {}
+    }
+  }
+
+  fn render_shape(canvas: &mut Canvas, used: &Rect, color: &Color, shape: &Shape, thickness: &f32, effect: &Effect) {
+    canvas.stroke_with(*thickness, *color, effect);
     match shape {
       Shape::Path(points, caption) => {
         let mut iter = points.iter();
@@ -153,7 +193,7 @@ impl Renderer {
             let point = Self::align_point(point, *thickness);
             canvas.line_to(point.x, point.y);
           }
-          canvas.stroke();
+          Self::stroke_open(canvas, endings);
         }
 
         Self::render_endings(points, endings, canvas);
@@ -171,6 +211,29 @@ impl Renderer {
       }
       Shape::Line(points, caption, endings) =>
         Self::render_line(canvas, used, points, caption, endings),
+      Shape::Curve(curve, caption, endings) => {
+        canvas.paint.set_style(PaintStyle::Stroke);
+        let (start, end) = (curve.start(), curve.end());
+        canvas.move_to(start.x, start.y);
+        for [_, first, second, to] in curve.0.iter() {
+          canvas.cubic_to(first.x, first.y, second.x, second.y, to.x, to.y);
+        }
+        Self::stroke_open(canvas, endings);
+        let (leaving, arriving) = curve.headings();
+        Self::draw_ending(&endings.start, &start, &start.add(leaving), canvas);
+        Self::draw_ending(&endings.end, &end, &end.sub(arriving), canvas);
+        Self::draw_caption_in(caption, &Rect::from_point_and_size(curve.point_at(0.5), (0., 0.)), canvas);
+      }
+      Shape::Arc(arc, caption, endings) => {
+        canvas.paint.set_style(PaintStyle::Stroke);
+        canvas.arc_to(&arc.oval(), arc.start, arc.sweep);
+        Self::stroke_open(canvas, endings);
+        // arrowheads along the tangent, not along a chord
+        let (start, end) = (arc.start_point(), arc.end_point());
+        Self::draw_ending(&endings.start, &start, &start.add(arc.heading(arc.start)), canvas);
+        Self::draw_ending(&endings.end, &end, &end.sub(arc.heading(arc.start + arc.sweep)), canvas);
+        Self::draw_caption_in(caption, used, canvas);
+      }
       Shape::Text(paragraph, _) => {
         if paragraph.widths.len() > 1 {
           Self::render_paragraph(canvas, used, &paragraph.text);
@@ -193,7 +256,7 @@ impl Renderer {
       canvas.line_to(point.x, point.y);
     }
 
-    canvas.stroke();
+    Self::stroke_open(canvas, endings);
 
     Self::render_endings(points, endings, canvas);
     Self::draw_caption_in(caption, used, canvas);
@@ -210,15 +273,114 @@ impl Renderer {
     Self::draw_ending(&endings.start, first, next, canvas);
   }
 
+  /// strokes the path so far, stopping short of hollow heads so they show only their outline, then any head halfway
+  fn stroke_open(canvas: &mut Canvas, endings: &Endings) {
+    let size = Self::head_size(canvas);
+    let length = canvas.length();
+    let (start, end) = (Self::setback(&endings.start, size), length - Self::setback(&endings.end, size));
+    let middle = match endings.middle {
+      Ending::None => None,
+      _ => canvas.along(length / 2.),
+    };
+    let tip = (length + size) / 2.;
+    match middle {
+      Some(_) => canvas.keep(&[(start, tip - Self::setback(&endings.middle, size)), (tip, end)]),
+      None if start > 0. || end < length => canvas.keep(&[(start, end)]),
+      None => {}
+    }
+    canvas.stroke();
+
+    if let Some((point, heading)) = middle {
+      let tip = point.add(heading.mul(size / 2. / heading.length()));
+      Self::draw_ending(&endings.middle, &tip, &point, canvas);
+    }
+  }
+
+  /// arrowheads grow with the thickness of their line
+  fn head_size(canvas: &Canvas) -> f32 {
+    canvas.arrowhead * canvas.paint.stroke_width().max(1.).sqrt()
+  }
+
+  /// how far short of the end its line stops for a head that is only an outline
+  fn setback(ending: &Ending, size: f32) -> f32 {
+    match ending {
+      Ending::Hollow => size * (25. * PI / 180.).cos(),
+      Ending::HollowDiamond | Ending::ZeroOrOne => size,
+      Ending::Circle => size / 2.,
+      _ => 0.,
+    }
+  }
+
+  /// the head at `last` of a line coming from `before`, in the color of the line and growing with its thickness
   fn draw_ending(ending: &Ending, last: &Point, before: &Point, canvas: &mut Canvas) {
+    let direction = last.sub(*before);
+    if *ending == Ending::None || direction.length() == 0. {
+      return;
+    }
+    let (color, effect, double) = (canvas.paint.color(), canvas.paint.path_effect(), canvas.double);
+    // heads are drawn whole, not dashed or doubled like their line
+    canvas.paint.set_path_effect(None);
+    canvas.double = false;
+    let scale = canvas.paint.stroke_width().max(1.).sqrt();
+    let size = Self::head_size(canvas);
+    let (sin, cos) = (25. * PI / 180.).sin_cos();
+    let (depth, width) = (size * cos, size * sin);
+    // back along the line from its end, and out to its left
+    let along = direction.mul(1. / direction.length());
+    let at = |back: f32, left: f32| last.sub(along.mul(back)).add(Vector::new(along.y, -along.x).mul(left));
+    let solid = |canvas: &mut Canvas, corners: &[Point]| {
+      canvas.fill_with(color);
+      canvas.polygon(corners);
+    };
+    // outlines only, as their line stops at their edge
+    let hollow = |canvas: &mut Canvas, corners: &[Point]| {
+      canvas.paint.set_style(PaintStyle::Stroke);
+      canvas.polygon(corners);
+    };
+    let circle = |canvas: &mut Canvas, back: f32, radius: f32| {
+      canvas.paint.set_style(PaintStyle::Stroke);
+      canvas.circle(&at(back, 0.), radius);
+    };
+    let strokes = |canvas: &mut Canvas, lines: &[[Point; 2]]| {
+      canvas.paint.set_color(color);
+      for [from, to] in lines {
+        canvas.move_to(from.x, from.y);
+        canvas.line_to(to.x, to.y);
+      }
+      canvas.stroke();
+    };
+    let bar = |back: f32| [at(back, width), at(back, -width)];
+    let foot = [[at(depth, 0.), at(0., width)], [at(depth, 0.), *last], [at(depth, 0.), at(0., -width)]];
+
     match ending {
-      Ending::Dot => Self::draw_dot(canvas, last),
-      Ending::Arrow => {
-        let direction = last.sub(*before);
-        Self::draw_arrow_head(canvas, last, direction);
+      Ending::None => {}
+      Ending::Arrow => solid(canvas, &[at(depth, width), *last, at(depth, -width)]),
+      Ending::Open => strokes(canvas, &[[at(depth, width), *last], [*last, at(depth, -width)]]),
+      Ending::Hollow => hollow(canvas, &[at(depth, width), *last, at(depth, -width)]),
+      Ending::Half => solid(canvas, &[at(depth, width), *last, at(depth, 0.)]),
+      Ending::Diamond => solid(canvas, &[*last, at(size / 2., width), at(size, 0.), at(size / 2., -width)]),
+      Ending::HollowDiamond => hollow(canvas, &[*last, at(size / 2., width), at(size, 0.), at(size / 2., -width)]),
+      Ending::Dot => {
+        canvas.fill_with(color);
+        canvas.circle(last, 4. * scale);
+      }
+      Ending::Circle => circle(canvas, size / 4., size / 4.),
+      Ending::Bar => strokes(canvas, &[bar(0.)]),
+      Ending::One => strokes(canvas, &[bar(size / 3.), bar(size * 0.6)]),
+      Ending::Many => strokes(canvas, &foot),
+      Ending::ZeroOrOne => {
+        // the line picks up again between the circle and the end
+        strokes(canvas, &[bar(size / 3.), [at(size * 0.6, 0.), *last]]);
+        circle(canvas, size * 0.8, size / 5.);
+      }
+      Ending::OneOrMany => {
+        strokes(canvas, &foot);
+        strokes(canvas, &[bar(depth + size / 5.)]);
       }
-      _ => {}
     }
+    canvas.paint.set_color(color);
+    canvas.paint.set_path_effect(effect);
+    canvas.double = double;
   }
 
   fn align_point(point: &Point, thickness: f32) -> Point {
@@ -249,7 +411,7 @@ impl Renderer {
         let mut rect = Self::align_rect(&rect, 1.);
         rect.outset(Caption::padding());
         let color = canvas.paint.color();
-        canvas.paint.set_color(Color::LIGHT_GRAY);
+        canvas.paint.set_color(canvas.caption_background);
         canvas.paint.set_style(PaintStyle::StrokeAndFill);
         canvas.rectangle(&rect, 0.);
         canvas.paint.set_color(color);
@@ -291,16 +453,6 @@ impl Renderer {
     }
   }
 
-  fn draw_arrow_head(canvas: &mut Canvas, p2: &Point, direction: Point) {
-    let angle = direction.y.atan2(direction.x);
-    let arrow = 25. * PI / 180.;
-    let size = 15.;
-    canvas.move_to(p2.x - size * (angle - arrow).cos(), p2.y - size * (angle - arrow).sin());
-    canvas.line_to(p2.x, p2.y);
-    canvas.line_to(p2.x - size * (angle + arrow).cos(), p2.y - size * (angle + arrow).sin());
-    canvas.fill();
-  }
-
   fn render_paragraph(canvas: &mut Canvas, rect: &Rect, title: &str) {
     let origin = (rect.left, rect.top);
     canvas.draw_paragraph(title, origin, rect.width());
@@ -321,11 +473,6 @@ impl Renderer {
       }
     }
   }
-  fn draw_dot(canvas: &mut Canvas, point: &Point) {
-    canvas.paint.set_style(PaintStyle::Fill);
-    canvas.paint.set_color(Color::BLACK);
-    canvas.circle(point, 4.);
-  }
 }
 
 #[cfg(test)]
@@ -0,0 +1,212 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::ops::{Add, Mul, Sub};
+
+use skia_safe::{Point, Rect, Vector};
+
+#[cfg(test)]
+mod tests;
+
+/// what a bend costs, as a length of line
+const BEND: f32 = 48.;
+
+/// how close coordinates are to count as the same
+const CLOSE: f32 = 0.01;
+
+const HEADINGS: [Vector; 4] = [Vector::new(1., 0.), Vector::new(0., 1.), Vector::new(-1., 0.), Vector::new(0., -1.)];
+
+/// Orthogonal routes for connectors, around the shapes in their way
+pub struct Router {
+  obstacles: Vec<Rect>,
+  clearance: f32,
+}
+
+/// a corner of the grid reached going one way, and what it cost to get there
+#[derive(PartialEq)]
+struct Step {
+  cost: f32,
+  state: usize,
+}
+
+impl Eq for Step {}
+
+impl Ord for Step {
+  fn cmp(&self, other: &Self) -> Ordering {
+    other.cost.total_cmp(&self.cost)
+  }
+}
+
+impl PartialOrd for Step {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Router {
+  /// keeps `clearance` away from the shapes
+  pub fn new(shapes: &[Rect], clearance: f32) -> Self {
+    let obstacles = shapes.iter()
+      .map(|rect| Rect::new(rect.left - clearance, rect.top - clearance, rect.right + clearance, rect.bottom + clearance))
+      .collect();
+    Router { obstacles, clearance }
+  }
+
+  /// the corners of a route from `start` leaving along `leaving` to `end` arriving along `arriving`,
+  /// with few bends and out of the way of the segments of earlier routes
+  pub fn route(&self, start: Point, leaving: Vector, end: Point, arriving: Vector, taken: &[[Point; 2]]) -> Option<Vec<Point>> {
+    let from = start.add(leaving.mul(self.clearance));
+    let to = end.sub(arriving.mul(self.clearance));
+    if self.inside(from) || self.inside(to) {
+      return None;
+    }
+
+    let xs = Self::lines(self.obstacles.iter().flat_map(|rect| [rect.left, rect.right]), [from.x, to.x, (from.x + to.x) / 2.]);
+    let ys = Self::lines(self.obstacles.iter().flat_map(|rect| [rect.top, rect.bottom]), [from.y, to.y, (from.y + to.y) / 2.]);
+    let find = |lines: &[f32], value: f32| lines.iter().position(|line| (line - value).abs() < CLOSE);
+    let corner = |node: usize| Point::new(xs[node % xs.len()], ys[node / xs.len()]);
+    let first = find(&ys, from.y)? * xs.len() + find(&xs, from.x)?;
+    let last = find(&ys, to.y)? * xs.len() + find(&xs, to.x)?;
+    let (leaving, arriving) = (Self::heading(leaving)?, Self::heading(arriving)?);
+
+    let states = xs.len() * ys.len() * 4;
+    let mut costs = vec![f32::INFINITY; states];
+    let mut previous = vec![usize::MAX; states];
+    let mut queue = BinaryHeap::new();
+    costs[first * 4 + leaving] = 0.;
+    queue.push(Step { cost: 0., state: first * 4 + leaving });
+
+    let mut found = None;
+    while let Some(Step { cost, state }) = queue.pop() {
+      if cost > costs[state] {
+        continue;
+      }
+      let (node, heading) = (state / 4, state % 4);
+      if node == last {
+        let cost = if heading == arriving { cost } else { cost + BEND };
+        if heading != (arriving + 2) % 4 && found.is_none_or(|(best, _)| cost < best) {
+          found = Some((cost, state));
+        }
+        continue;
+      }
+      for turn in [heading, (heading + 1) % 4, (heading + 3) % 4] {
+        let Some(next) = self.neighbour(node, turn, xs.len(), ys.len()) else { continue };
+        let (a, b) = (corner(node), corner(next));
+        if self.blocked(a, b) || self.inside(b) {
+          continue;
+        }
+        let shared: f32 = taken.iter().map(|segment| Self::overlap(a, b, segment)).sum();
+        let cost = cost + (b - a).length() + shared + if turn == heading { 0. } else { BEND };
+        let state = next * 4 + turn;
+        if cost < costs[state] {
+          costs[state] = cost;
+          previous[state] = node * 4 + heading;
+          queue.push(Step { cost, state });
+        }
+      }
+    }
+
+    let (_, mut state) = found?;
+    let mut corners = vec![end];
+    while state != usize::MAX {
+      corners.push(corner(state / 4));
+      state = previous[state];
+    }
+    corners.push(start);
+    corners.reverse();
+    let mut points = Self::straight(&corners);
+    self.spread(&mut points, taken);
+    Some(points)
+  }
+
+  /// the grid lines along the sides of the obstacles and through the ends
+  fn lines(sides: impl Iterator<Item = f32>, ends: [f32; 3]) -> Vec<f32> {
+    let mut lines = sides.chain(ends).collect::<Vec<_>>();
+    lines.sort_by(|a, b| a.total_cmp(b));
+    lines.dedup_by(|a, b| (*a - *b).abs() < CLOSE);
+    lines
+  }
+
+  fn heading(vector: Vector) -> Option<usize> {
+    HEADINGS.iter().position(|heading| *heading == vector)
+  }
+
+  fn neighbour(&self, node: usize, heading: usize, width: usize, height: usize) -> Option<usize> {
+    let (x, y) = (node % width, node / width);
+    let (x, y) = match heading {
+      0 => (x + 1, y),
+      1 => (x, y + 1),
+      2 => (x.checked_sub(1)?, y),
+      _ => (x, y.checked_sub(1)?),
+    };
+    (x < width && y < height).then_some(y * width + x)
+  }
+
+  fn inside(&self, point: Point) -> bool {
+    self.obstacles.iter().any(|rect| {
+      rect.left + CLOSE < point.x && point.x < rect.right - CLOSE && rect.top + CLOSE < point.y && point.y < rect.bottom - CLOSE
+    })
+  }
+
+  /// whether a straight segment goes through an obstacle, not only along its side
+  fn blocked(&self, a: Point, b: Point) -> bool {
+    let (left, right) = (a.x.min(b.x), a.x.max(b.x));
+    let (top, bottom) = (a.y.min(b.y), a.y.max(b.y));
+    self.obstacles.iter().any(|rect| {
+      left < rect.right - CLOSE && right > rect.left + CLOSE && top < rect.bottom - CLOSE && bottom > rect.top + CLOSE
+    })
+  }
+
+  /// how long `a` to `b` runs along a segment of another route
+  fn overlap(a: Point, b: Point, segment: &[Point; 2]) -> f32 {
+    let [c, d] = *segment;
+    let along = |a: f32, b: f32, c: f32, d: f32| (a.max(b).min(c.max(d)) - a.min(b).max(c.min(d))).max(0.);
+    if (a.y - b.y).abs() < CLOSE && (c.y - d.y).abs() < CLOSE && (a.y - c.y).abs() < CLOSE {
+      along(a.x, b.x, c.x, d.x)
+    } else if (a.x - b.x).abs() < CLOSE && (c.x - d.x).abs() < CLOSE && (a.x - c.x).abs() < CLOSE {
+      along(a.y, b.y, c.y, d.y)
+    } else {
+      0.
+    }
+  }
+
+  /// only the corners, without points along the way
+  fn straight(points: &[Point]) -> Vec<Point> {
+    let mut straight: Vec<Point> = vec![];
+    for point in points {
+      if straight.last().is_some_and(|last| (*last - *point).length() < CLOSE) {
+        continue;
+      }
+      if let [.., before, last] = straight[..] {
+        let turn = (last - before).cross(*point - last);
+        if turn.abs() < CLOSE {
+          straight.pop();
+        }
+      }
+      straight.push(*point);
+    }
+    straight
+  }
+
+  /// move middle segments that run along earlier routes aside, where that stays clear
+  fn spread(&self, points: &mut [Point], taken: &[[Point; 2]]) {
+    let gap = (self.clearance / 2.).max(2.);
+    for i in 1..points.len().saturating_sub(2) {
+      let shares = |a: Point, b: Point| taken.iter().any(|segment| Self::overlap(a, b, segment) > CLOSE);
+      if !shares(points[i], points[i + 1]) {
+        continue;
+      }
+      let vertical = (points[i].x - points[i + 1].x).abs() < CLOSE;
+      for shift in [gap, -gap, 2. * gap, -2. * gap] {
+        let offset = if vertical { Vector::new(shift, 0.) } else { Vector::new(0., shift) };
+        let (a, b) = (points[i].add(offset), points[i + 1].add(offset));
+        let clear = !self.blocked(a, b) && !shares(a, b)
+          && (i == 1 || !self.blocked(points[i - 1], a))
+          && (i + 2 == points.len() - 1 || !self.blocked(b, points[i + 2]));
+        if clear {
+          (points[i], points[i + 1]) = (a, b);
+          break;
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,59 @@
+use skia_safe::{Point, Rect, Vector};
+
+use crate::diagram::router::Router;
+
+const RIGHT: Vector = Vector::new(1., 0.);
+const DOWN: Vector = Vector::new(0., 1.);
+
+fn clear_of(points: &[Point], rect: Rect) -> bool {
+  points.windows(2).all(|pair| {
+    let (a, b) = (pair[0], pair[1]);
+    a.x.max(b.x) <= rect.left || a.x.min(b.x) >= rect.right || a.y.max(b.y) <= rect.top || a.y.min(b.y) >= rect.bottom
+  })
+}
+
+fn orthogonal(points: &[Point]) -> bool {
+  points.windows(2).all(|pair| pair[0].x == pair[1].x || pair[0].y == pair[1].y)
+}
+
+#[test]
+fn straight_when_clear() {
+  let router = Router::new(&[], 10.);
+  let points = router.route(Point::new(0., 0.), RIGHT, Point::new(100., 0.), RIGHT, &[]).unwrap();
+  assert_eq!(vec![Point::new(0., 0.), Point::new(100., 0.)], points);
+}
+
+#[test]
+fn few_bends() {
+  let router = Router::new(&[], 10.);
+  let points = router.route(Point::new(0., 0.), RIGHT, Point::new(100., 100.), DOWN, &[]).unwrap();
+  assert_eq!(vec![Point::new(0., 0.), Point::new(100., 0.), Point::new(100., 100.)], points);
+}
+
+#[test]
+fn around_shapes() {
+  let obstacle = Rect::from_xywh(40., -30., 20., 60.);
+  let router = Router::new(&[obstacle], 10.);
+  let points = router.route(Point::new(0., 0.), RIGHT, Point::new(100., 0.), RIGHT, &[]).unwrap();
+  assert!(orthogonal(&points));
+  assert!(clear_of(&points, Rect::from_xywh(30., -40., 40., 80.)), "{:?}", points);
+  assert_eq!(6, points.len());
+}
+
+#[test]
+fn no_way_out() {
+  let router = Router::new(&[Rect::from_xywh(5., -20., 20., 40.)], 10.);
+  assert_eq!(None, router.route(Point::new(0., 0.), RIGHT, Point::new(100., 0.), RIGHT, &[]));
+}
+
+#[test]
+fn apart_from_other_routes() {
+  let obstacle = Rect::from_xywh(40., -30., 20., 200.);
+  let router = Router::new(&[obstacle], 10.);
+  let first = router.route(Point::new(0., 0.), RIGHT, Point::new(100., 0.), RIGHT, &[]).unwrap();
+  let taken = first.windows(2).map(|pair| [pair[0], pair[1]]).collect::<Vec<_>>();
+  let second = router.route(Point::new(0., 10.), RIGHT, Point::new(100., 10.), RIGHT, &taken).unwrap();
+  assert!(orthogonal(&second));
+  let shared: f32 = second.windows(2).flat_map(|pair| taken.iter().map(|segment| Router::overlap(pair[0], pair[1], segment))).sum();
+  assert_eq!(0., shared, "{:?} and {:?}", first, second);
+}
@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+use crate::diagram::diagnostics::{Diagnostic, Severity};
+use crate::diagram::error::ParseError;
+
+#[cfg(test)]
+mod tests;
+
+/// Diagram text with every `include` spliced in, remembering where each line came from
+#[derive(Debug, Default)]
+pub struct Source {
+  text: String,
+  files: Vec<SourceFile>,
+  segments: Vec<Segment>,
+  search: Vec<PathBuf>,
+  pub(crate) diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Debug)]
+struct SourceFile {
+  name: String,
+  dir: Option<PathBuf>,
+  text: String,
+}
+
+/// a line of the spliced text, starting at `start`, copied from `offset` in `file`
+#[derive(Debug)]
+struct Segment {
+  start: usize,
+  file: usize,
+  offset: usize,
+}
+
+impl Source {
+  /// Read a file and everything it includes; includes are resolved relative to the including file, then in `search`
+  pub fn load(path: impl AsRef<Path>, search: &[PathBuf]) -> io::Result<Self> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    let mut source = Self { search: search.to_vec(), ..Self::default() };
+    let mut stack = vec![path.canonicalize()?];
+    source.splice(path.display().to_string(), path.parent(), text, &mut stack);
+    Ok(source)
+  }
+
+  /// Like `load`, for text that isn't a file, such as stdin; includes are resolved relative to the working directory
+  pub fn from_string(name: &str, text: impl Into<String>, search: &[PathBuf]) -> Self {
+    let mut source = Self { search: search.to_vec(), ..Self::default() };
+    let dir = env::current_dir().ok();
+    source.splice(name.into(), dir.as_deref(), text.into(), &mut vec![]);
+    source
+  }
+
+  /// Like `load`, with the text already read, such as an unsaved editor buffer
+  pub fn from_file_text(path: &Path, text: impl Into<String>, search: &[PathBuf]) -> Self {
+    let mut source = Self { search: search.to_vec(), ..Self::default() };
+    let mut stack = path.canonicalize().into_iter().collect();
+    source.splice(path.display().to_string(), path.parent(), text.into(), &mut stack);
+    source
+  }
+
+  pub fn text(&self) -> &str {
+    &self.text
+  }
+
+  fn splice(&mut self, name: String, dir: Option<&Path>, text: String, stack: &mut Vec<PathBuf>) {
+    let file = self.files.len();
+    self.files.push(SourceFile { name, dir: dir.map(Path::to_path_buf), text: String::new() });
+    let mut offset = 0;
+    for (number, line) in text.split_inclusive('\n').enumerate() {
+      match Self::include_in(line) {
+        Some((column, include)) => {
+          let span = (self.text.len(), self.text.len() + include.len() + 2);
+          if let Err(message) = self.include(dir, include, stack) {
+            self.diagnostics.push(Diagnostic {
+              severity: Severity::Error,
+              message,
+              span,
+              line: number + 1,
+              column: column + 1,
+              snippet: line.trim_end().to_string(),
+              file: self.name(file),
+            });
+          }
+        }
+        None => {
+          self.segments.push(Segment { start: self.text.len(), file, offset });
+          self.text.push_str(line);
+        }
+      }
+      offset += line.len();
+    }
+    if !self.text.is_empty() && !self.text.ends_with('\n') {
+      self.text.push('\n');
+    }
+    self.files[file].text = text;
+  }
+
+  fn include(&mut self, dir: Option<&Path>, include: &str, stack: &mut Vec<PathBuf>) -> Result<(), String> {
+    let path = self.resolve(dir, include)
+      .ok_or_else(|| format!("cannot find `{}` to include", include))?;
+    let canonical = path.canonicalize().map_err(|error| format!("cannot read `{}`: {}", include, error))?;
+    if let Some(start) = stack.iter().position(|path| path == &canonical) {
+      let cycle = stack[start..].iter().chain([&canonical])
+        .map(|path| path.file_name().unwrap_or_default().to_string_lossy())
+        .collect::<Vec<_>>();
+      return Err(format!("include cycle {}", cycle.join(" -> ")));
+    }
+    let text = fs::read_to_string(&path).map_err(|error| format!("cannot read `{}`: {}", include, error))?;
+    stack.push(canonical);
+    self.splice(path.display().to_string(), path.parent(), text, stack);
+    stack.pop();
+    Ok(())
+  }
+
+  /// where a file named at `position` is looked for: next to the file that names it, then in `search`
+  pub(crate) fn dirs(&self, position: usize) -> Vec<PathBuf> {
+    let index = self.segments.partition_point(|segment| segment.start <= position).checked_sub(1);
+    index.and_then(|index| self.files[self.segments[index].file].dir.clone()).into_iter()
+      .chain(self.search.iter().cloned())
+      .collect()
+  }
+
+  fn resolve(&self, dir: Option<&Path>, include: &str) -> Option<PathBuf> {
+    dir.into_iter().chain(self.search.iter().map(PathBuf::as_path))
+      .map(|dir| dir.join(include))
+      .find(|path| path.is_file())
+  }
+
+  /// `include "file"` on a line of its own, optionally followed by a comment
+  fn include_in(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start();
+    let rest = trimmed.strip_prefix("include")?;
+    let quoted = rest.trim_start();
+    if quoted.len() == rest.len() {
+      return None;
+    }
+    let quoted = quoted.strip_prefix('"')?;
+    let end = quoted.find('"')?;
+    let after = quoted[end + 1..].trim();
+    if !(after.is_empty() || after.starts_with('#') || after.starts_with("//")) {
+      return None;
+    }
+    let column = line.len() - quoted.len() - 1;
+    Some((column, &quoted[..end]))
+  }
+
+  fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map(|line| line.chars().count()).unwrap_or(0) + 1;
+    (line, column)
+  }
+
+  /// files other than the one being rendered are named in diagnostics
+  fn name(&self, file: usize) -> Option<String> {
+    (file > 0).then(|| self.files[file].name.clone())
+  }
+
+  /// Point a diagnostic at the file and line its span came from
+  pub(crate) fn relocate(&self, diagnostic: &mut Diagnostic) {
+    if let Some((file, line, column, snippet)) = self.locate(diagnostic.span.0) {
+      (diagnostic.file, diagnostic.line, diagnostic.column, diagnostic.snippet) = (file, line, column, snippet);
+    }
+  }
+
+  pub(crate) fn relocate_error(&self, mut error: Box<ParseError>) -> Box<ParseError> {
+    if let Some((file, line, column, snippet)) = self.locate(error.span.0) {
+      (error.file, error.line, error.column, error.snippet) = (file, line, column, snippet);
+    }
+    error
+  }
+
+  /// The offset in the file being rendered, for a position that isn't from an included file
+  pub(crate) fn original(&self, position: usize) -> Option<usize> {
+    let index = self.segments.partition_point(|segment| segment.start <= position).checked_sub(1)?;
+    let segment = &self.segments[index];
+    (segment.file == 0).then(|| segment.offset + position - segment.start)
+  }
+
+  fn locate(&self, position: usize) -> Option<(Option<String>, usize, usize, String)> {
+    let index = self.segments.partition_point(|segment| segment.start <= position).checked_sub(1)?;
+    let segment = &self.segments[index];
+    let text = &self.files[segment.file].text;
+    let offset = segment.offset + position - segment.start;
+    let (line, column) = Self::line_column(text, offset);
+    let snippet = text.lines().nth(line - 1).unwrap_or_default().to_string();
+    Some((self.name(segment.file), line, column, snippet))
+  }
+}
@@ -0,0 +1,138 @@
+use std::path::PathBuf;
+use std::{env, fs, process};
+
+use crate::diagram::parser::Diagram;
+use crate::diagram::source::Source;
+use crate::diagram::types::Node;
+use crate::skia::A5;
+
+/// a fresh directory with the given files in it
+fn files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+  let dir = env::temp_dir().join(format!("picturs-{}-{}", name, process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  for (path, text) in files {
+    let path = dir.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, text).unwrap();
+  }
+  dir
+}
+
+fn messages(source: &Source) -> Vec<String> {
+  source.diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect()
+}
+
+#[test]
+fn splices_included_file() {
+  let dir = files("splice", &[
+    ("main.pic", "include \"common.pic\"\nbox.b \"two\"\n"),
+    ("common.pic", "box.a \"one\""),
+  ]);
+  let source = Source::load(dir.join("main.pic"), &[]).unwrap();
+  assert_eq!("box.a \"one\"\nbox.b \"two\"\n", source.text());
+
+  let mut diagram = Diagram::inset(A5, (0., 0.));
+  diagram.parse_source(&source).unwrap();
+  assert_eq!(2, diagram.nodes.iter().filter(|node| matches!(node, Node::Closed(..))).count());
+  assert!(diagram.diagnostics.is_empty());
+}
+
+#[test]
+fn relative_to_including_file() {
+  let dir = files("relative", &[
+    ("main.pic", "include \"parts/outer.pic\"\n"),
+    ("parts/outer.pic", "include \"inner.pic\" # next to outer.pic\n"),
+    ("parts/inner.pic", "circle\n"),
+  ]);
+  let source = Source::load(dir.join("main.pic"), &[]).unwrap();
+  assert_eq!("circle\n", source.text());
+  assert!(source.diagnostics.is_empty());
+}
+
+#[test]
+fn search_paths() {
+  let dir = files("search", &[
+    ("main.pic", "include \"shared.pic\"\n"),
+    ("lib/shared.pic", "box\n"),
+  ]);
+  let source = Source::load(dir.join("main.pic"), &[dir.join("lib")]).unwrap();
+  assert_eq!("box\n", source.text());
+}
+
+#[test]
+fn missing_file() {
+  let dir = files("missing", &[("main.pic", "box\ninclude \"nowhere.pic\"\n")]);
+  let source = Source::load(dir.join("main.pic"), &[]).unwrap();
+  assert_eq!(vec!["cannot find `nowhere.pic` to include"], messages(&source));
+  let diagnostic = &source.diagnostics[0];
+  assert_eq!((None, 2, 9), (diagnostic.file.clone(), diagnostic.line, diagnostic.column));
+}
+
+#[test]
+fn cycle() {
+  let dir = files("cycle", &[
+    ("main.pic", "include \"a.pic\"\n"),
+    ("a.pic", "box\ninclude \"b.pic\"\n"),
+    ("b.pic", "include \"a.pic\"\ncircle\n"),
+  ]);
+  let source = Source::load(dir.join("main.pic"), &[]).unwrap();
+  assert_eq!(vec!["include cycle a.pic -> b.pic -> a.pic"], messages(&source));
+  assert_eq!("box\ncircle\n", source.text());
+}
+
+#[test]
+fn diagnostics_point_into_included_file() {
+  let dir = files("diagnostics", &[
+    ("main.pic", "box \"one\"\ninclude \"common.pic\"\nbox \"three\"\n"),
+    ("common.pic", "box \"two\"\nbox color mauve\n"),
+  ]);
+  let source = Source::load(dir.join("main.pic"), &[]).unwrap();
+  let mut diagram = Diagram::inset(A5, (0., 0.));
+  diagram.parse_source(&source).unwrap();
+
+  let error = diagram.diagnostics.errors().next().unwrap();
+  let file = dir.join("common.pic").display().to_string();
+  assert_eq!((Some(file.clone()), 2, 11), (error.file.clone(), error.line, error.column));
+  assert_eq!("box color mauve", error.snippet);
+  let rendered = error.to_string();
+  assert_eq!(format!("error: unknown color `mauve` in {} at line 2, column 11", file), rendered.lines().next().unwrap());
+}
+
+#[test]
+fn parse_error_in_included_file() {
+  let dir = files("parse_error", &[
+    ("main.pic", "box\ninclude \"broken.pic\"\n"),
+    ("broken.pic", "circle\nbox wd \"two\"\n"),
+  ]);
+  let source = Source::load(dir.join("main.pic"), &[]).unwrap();
+  let error = Diagram::inset(A5, (0., 0.)).parse_source(&source).unwrap_err();
+  assert_eq!((2, 8), (error.line, error.column));
+  assert_eq!(Some(dir.join("broken.pic").display().to_string()), error.file);
+}
+
+#[test]
+fn include_needs_its_own_line() {
+  let mut diagram = Diagram::inset(A5, (0., 0.));
+  diagram.parse_string("group { include \"common.pic\" }").unwrap();
+  let error = diagram.diagnostics.errors().next().unwrap();
+  assert_eq!("cannot include `common.pic` here, it needs a line of its own", error.message);
+}
+
+#[test]
+fn themes_found_like_includes() {
+  let dir = files("themes", &[
+    ("pics/main.pic", "theme \"ink.toml\"\ninclude \"other/shared.pic\"\n"),
+    ("pics/ink.toml", "[box]\nstroke = \"navy\"\n"),
+    ("pics/other/shared.pic", "theme \"shared.toml\"\nbox\n"),
+    ("themes/shared.toml", "[box]\nstroke = \"red\"\n"),
+  ]);
+  let mut diagram = Diagram::inset(A5, (0., 0.));
+  let source = Source::load(dir.join("pics/main.pic"), &[dir.join("themes")]).unwrap();
+  diagram.parse_source(&source).unwrap();
+  assert!(diagram.diagnostics.is_empty(), "{}", diagram.diagnostics);
+
+  let source = Source::load(dir.join("pics/main.pic"), &[]).unwrap();
+  diagram.parse_source(&source).unwrap();
+  let messages = diagram.diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect::<Vec<_>>();
+  assert_eq!(vec!["cannot find theme `shared.toml`"], messages);
+}
@@ -0,0 +1,287 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use pest::iterators::Pair;
+use serde_json::Value;
+use skia_safe::{Color, Font, FontMgr, FontStyle};
+
+use crate::diagram::conversion::Conversion;
+use crate::diagram::diagnostics::Diagnostic;
+use crate::diagram::expression::Expression;
+use crate::diagram::index::Index;
+use crate::diagram::parser::Rule;
+use crate::diagram::types::{Length, ShapeConfig, Unit, HEIGHT, WIDTH};
+
+#[cfg(test)]
+mod tests;
+
+const BUILT_IN: &[(&str, &str)] = &[
+  ("light", include_str!("../../themes/light.toml")),
+  ("dark", include_str!("../../themes/dark.toml")),
+  ("print", include_str!("../../themes/print.toml")),
+];
+
+/// Colors, fonts and sizes a picture starts with, built in or read from TOML or JSON;
+/// what a theme leaves out stays as in `light`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+  pub(crate) font_family: String,
+  pub(crate) font_size: f32,
+  pub(crate) arrowhead: f32,
+  pub(crate) caption_background: Color,
+  pub(crate) background: Option<Color>,
+  pub(crate) grid: Color,
+  pub(crate) group: ShapeConfig,
+  pub(crate) circle: ShapeConfig,
+  pub(crate) cylinder: ShapeConfig,
+  pub(crate) ellipse: ShapeConfig,
+  pub(crate) oval: ShapeConfig,
+  pub(crate) rectangle: ShapeConfig,
+  pub(crate) text: ShapeConfig,
+  pub(crate) file: ShapeConfig,
+  pub(crate) diamond: ShapeConfig,
+  pub(crate) triangle: ShapeConfig,
+  pub(crate) hexagon: ShapeConfig,
+  pub(crate) parallelogram: ShapeConfig,
+  pub(crate) trapezoid: ShapeConfig,
+  pub(crate) star: ShapeConfig,
+  pub(crate) dot: ShapeConfig,
+  pub(crate) arrow: ShapeConfig,
+  pub(crate) line: ShapeConfig,
+  pub(crate) sline: ShapeConfig,
+  pub(crate) arc: ShapeConfig,
+  pub(crate) curve: ShapeConfig,
+  pub(crate) path: ShapeConfig,
+}
+
+impl Default for Theme {
+  fn default() -> Self {
+    Self {
+      font_family: "Helvetica".into(),
+      font_size: 17.0,
+      arrowhead: 15.,
+      caption_background: Color::LIGHT_GRAY,
+      background: None,
+      grid: Color::GRAY,
+      group: ShapeConfig::stroke(Color::TRANSPARENT),
+      circle: ShapeConfig::default(),
+      cylinder: ShapeConfig::default(),
+      ellipse: ShapeConfig::default(),
+      oval: ShapeConfig::default(),
+      rectangle: ShapeConfig::default(),
+      text: ShapeConfig::default(),
+      file: ShapeConfig {
+        padding: 8.0,
+        width: HEIGHT.pixels(),
+        height: WIDTH.pixels(),
+        radius: 8.0,
+        ..ShapeConfig::default()
+      },
+      diamond: ShapeConfig::default(),
+      triangle: ShapeConfig::default(),
+      hexagon: ShapeConfig::default(),
+      parallelogram: ShapeConfig::default(),
+      trapezoid: ShapeConfig::default(),
+      star: ShapeConfig::default(),
+      dot: ShapeConfig { radius: 4., ..ShapeConfig::default() },
+      arrow: ShapeConfig { space: 12., ..ShapeConfig::default() },
+      line: ShapeConfig { space: 12., ..ShapeConfig::default() },
+      sline: ShapeConfig::default(),
+      arc: ShapeConfig { radius: Length::new(0.5, Unit::In).pixels(), ..ShapeConfig::default() },
+      curve: ShapeConfig::default(),
+      path: ShapeConfig::default(),
+    }
+  }
+}
+
+impl Theme {
+  /// a built-in theme, or else a theme file looked for in `dirs` in turn, like an include
+  pub fn find(name: &str, dirs: &[PathBuf]) -> Result<Self, String> {
+    match Self::built_in(name) {
+      Some(theme) => theme,
+      None if Path::new(name).extension().is_some() => {
+        let path = dirs.iter().map(|dir| dir.join(name)).find(|path| path.is_file())
+          .ok_or_else(|| format!("cannot find theme `{}`", name))?;
+        Self::load(&path)
+      }
+      None => Err(format!("unknown theme `{}`, expected one of {} or a .toml or .json file", name, Self::names().join(", "))),
+    }
+  }
+
+  pub fn built_in(name: &str) -> Option<Result<Self, String>> {
+    BUILT_IN.iter().find(|(built_in, _)| *built_in == name)
+      .map(|(name, text)| Self::from_toml(text).map_err(|error| format!("built-in theme {}: {}", name, error)))
+  }
+
+  pub fn names() -> Vec<&'static str> {
+    BUILT_IN.iter().map(|(name, _)| *name).collect()
+  }
+
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("cannot read theme {}: {}", path.display(), error))?;
+    let theme = match path.extension().and_then(|extension| extension.to_str()) {
+      Some("json") => Self::from_json(&text),
+      _ => Self::from_toml(&text),
+    };
+    theme.map_err(|error| format!("{}: {}", path.display(), error))
+  }
+
+  pub fn from_toml(text: &str) -> Result<Self, String> {
+    let value = toml::from_str::<Value>(text).map_err(|error| error.message().to_string())?;
+    Self::from_value(&value)
+  }
+
+  pub fn from_json(text: &str) -> Result<Self, String> {
+    let value = serde_json::from_str::<Value>(text).map_err(|error| error.to_string())?;
+    Self::from_value(&value)
+  }
+
+  fn from_value(value: &Value) -> Result<Self, String> {
+    let mut theme = Theme::default();
+    for (key, value) in Self::table(value, "theme")? {
+      match key.as_str() {
+        "background" => theme.background = match value.as_str() {
+          Some("none") => None,
+          _ => Some(Self::color(value, key)?),
+        },
+        "grid" => theme.grid = Self::color(value, key)?,
+        "caption_background" => theme.caption_background = Self::color(value, key)?,
+        "arrowhead" => theme.arrowhead = Self::length(value, key)?,
+        "font" => for (name, value) in Self::table(value, key)? {
+          match name.as_str() {
+            "family" => theme.font_family = value.as_str().ok_or("font.family should be a string")?.into(),
+            "size" => theme.font_size = Self::length(value, "font.size")?,
+            _ => return Err(format!("unknown setting `font.{}`", name)),
+          }
+        },
+        _ => {
+          let shape = theme.shape(key).ok_or_else(|| format!("unknown setting `{}`", key))?;
+          Self::shape_from(value, key, shape)?;
+        }
+      }
+    }
+    Ok(theme)
+  }
+
+  fn shape(&mut self, name: &str) -> Option<&mut ShapeConfig> {
+    Some(match name {
+      "box" => &mut self.rectangle,
+      "circle" => &mut self.circle,
+      "cylinder" => &mut self.cylinder,
+      "ellipse" => &mut self.ellipse,
+      "file" => &mut self.file,
+      "group" => &mut self.group,
+      "oval" => &mut self.oval,
+      "diamond" => &mut self.diamond,
+      "triangle" => &mut self.triangle,
+      "hexagon" => &mut self.hexagon,
+      "parallelogram" => &mut self.parallelogram,
+      "trapezoid" => &mut self.trapezoid,
+      "star" => &mut self.star,
+      "text" => &mut self.text,
+      "dot" => &mut self.dot,
+      "arrow" => &mut self.arrow,
+      "line" => &mut self.line,
+      "sline" => &mut self.sline,
+      "arc" => &mut self.arc,
+      "curve" => &mut self.curve,
+      "path" => &mut self.path,
+      _ => return None,
+    })
+  }
+
+  fn shape_from(value: &Value, shape: &str, config: &mut ShapeConfig) -> Result<(), String> {
+    for (name, value) in Self::table(value, shape)? {
+      let key = format!("{}.{}", shape, name);
+      match name.as_str() {
+        "padding" => config.padding = Self::length(value, &key)?,
+        "width" => config.width = Self::length(value, &key)?,
+        "height" => config.height = Self::length(value, &key)?,
+        "radius" => config.radius = Self::length(value, &key)?,
+        "space" => config.space = Self::length(value, &key)?,
+        "length" => config.length = Self::length(value, &key)?,
+        "stroke" => config.stroke = Self::color(value, &key)?,
+        "fill" => match Self::parsed(value, Rule::shading, &key) {
+          Ok(pair) => config.shading = Some(Self::evaluated(&key, |index| Conversion::shading_from(pair, &Unit::Px, index))?),
+          Err(_) => config.fill = Self::color(value, &key)?,
+        },
+        "text" => config.text = Self::color(value, &key)?,
+        "thickness" => config.thickness = match value.as_str() {
+          Some(name) => Conversion::named_thickness(name).ok_or_else(|| format!("unknown thickness `{}` for {}", name, key))?,
+          None => match Self::length(value, &key)? {
+            thickness if thickness < 0. => return Err(format!("{} can't be negative", key)),
+            thickness => thickness,
+          },
+        },
+        "effect" => {
+          let pair = Self::parsed(value, Rule::effect, &key)?;
+          config.effect = Self::evaluated(&key, |index| Conversion::effect_from(pair, &Unit::Px, index))?;
+        }
+        "shadow" => {
+          let pair = Self::parsed(value, Rule::shadow, &key)?;
+          config.shadow = Some(Self::evaluated(&key, |index| Conversion::shadow_from(pair, &Unit::Px, index))?);
+        }
+        "endings" => config.endings = Some(Self::parsed(value, Rule::endings, &key).map(Conversion::endings_from)?),
+        "caption" => config.caption = Some(Self::parsed(value, Rule::alignment, &key).map(Conversion::alignment_from)?),
+        "opaque" => config.opaque = value.as_bool().ok_or_else(|| format!("{} should be true or false", key))?,
+        _ => return Err(format!("unknown setting `{}`", key)),
+      }
+    }
+    Ok(())
+  }
+
+  fn table<'v>(value: &'v Value, key: &str) -> Result<&'v serde_json::Map<String, Value>, String> {
+    value.as_object().ok_or_else(|| format!("{} should be a table", key))
+  }
+
+  /// a number of pixels, or a length as written in a picture like `0.5in`
+  fn length(value: &Value, key: &str) -> Result<f32, String> {
+    if let Some(pixels) = value.as_f64() {
+      return Ok(pixels as f32);
+    }
+    let pair = Self::parsed(value, Rule::expression, key)?;
+    Self::evaluated(key, |index| Ok(Expression::new(&Unit::Px, index).length_from(pair).pixels()))
+  }
+
+  /// what `evaluate` makes of a setting, or the first problem it has with it, such as an unknown variable
+  fn evaluated<T>(key: &str, evaluate: impl FnOnce(&mut Index) -> Result<T, Diagnostic>) -> Result<T, String> {
+    let mut index = Index::default();
+    let result = evaluate(&mut index);
+    let reported = index.diagnostics.errors().next().map(|diagnostic| diagnostic.message.clone());
+    match reported {
+      Some(message) => Err(format!("{} for {}", message, key)),
+      None => result.map_err(|diagnostic| format!("{} for {}", diagnostic.message, key)),
+    }
+  }
+
+  /// a string that is all `rule`, as in a picture
+  fn parsed<'v>(value: &'v Value, rule: Rule, key: &str) -> Result<Pair<'v, Rule>, String> {
+    let expected = match rule {
+      Rule::expression => "a length",
+      Rule::endings => "endings like `->`",
+      Rule::color_value => "a color",
+      Rule::shadow => "a shadow like `shadow(2px, 2px)` or `glow`",
+      Rule::effect => "solid, dashed, dotted or a dash like `dash 6px 3px`",
+      _ => "a caption alignment like `above`",
+    };
+    let text = value.as_str().ok_or_else(|| format!("{} should be {}", key, expected))?;
+    Conversion::pairs_for(rule, text).ok()
+      .and_then(|mut pairs| pairs.next())
+      .filter(|pair| pair.as_str() == text.trim())
+      .ok_or_else(|| format!("{} should be {}, not `{}`", key, expected, text))
+  }
+
+  /// a color as written in a picture, like `#ffcc00` or `lighten(navy, 20%)`
+  fn color(value: &Value, key: &str) -> Result<Color, String> {
+    let pair = Self::parsed(value, Rule::color_value, key)?;
+    Conversion::color_from(pair).map_err(|diagnostic| format!("{} for {}", diagnostic.message, key))
+  }
+
+  pub(crate) fn font(&self) -> Font {
+    let fonts = FontMgr::default();
+    let typeface = fonts.match_family_style(&self.font_family, FontStyle::default())
+      .or_else(|| fonts.match_family_style("Helvetica", FontStyle::default()))
+      .unwrap();
+    Font::from_typeface(typeface, self.font_size)
+  }
+}
@@ -0,0 +1,97 @@
+use skia_safe::Color;
+
+use crate::diagram::attributes::Attributes;
+use crate::diagram::parser::Diagram;
+use crate::diagram::theme::Theme;
+use crate::diagram::types::Node;
+use crate::skia::{Effect, Shading, A5};
+
+fn box_colors(diagram: &Diagram) -> (Color, Color, f32) {
+  match diagram.nodes.iter().find(|node| matches!(node, Node::Closed(..))) {
+    Some(Node::Closed(Attributes::Closed { stroke, text, thickness, .. }, ..)) => (*stroke, *text, *thickness),
+    _ => panic!("Expected a box"),
+  }
+}
+
+#[test]
+fn light_is_the_default() {
+  assert_eq!(Some(Ok(Theme::default())), Theme::built_in("light"));
+}
+
+#[test]
+fn built_in() {
+  assert_eq!(vec!["light", "dark", "print"], Theme::names());
+  let dark = Theme::find("dark", &[]).unwrap();
+  assert_eq!(Some(Color::new(0xFF1E1E1E)), dark.background);
+  assert_eq!(Color::WHITE, dark.rectangle.stroke);
+  assert_eq!(2., Theme::find("print", &[]).unwrap().circle.thickness);
+}
+
+#[test]
+fn toml_and_json() {
+  let toml = "arrowhead = 10\n[font]\nsize = \"12pt\"\n[box]\nwidth = \"1in\"\nfill = \"#ff0000\"\neffect = \"dashed\"\n";
+  let json = r#"{ "arrowhead": 10, "font": { "size": "12pt" }, "box": { "width": 96, "fill": "red", "effect": "dashed" } }"#;
+  let theme = Theme::from_toml(toml).unwrap();
+  assert_eq!(theme, Theme::from_json(json).unwrap());
+  assert_eq!(96., theme.rectangle.width);
+  assert_eq!(Effect::Dashed, theme.rectangle.effect);
+  assert_eq!(Theme::default().circle, theme.circle);
+}
+
+#[test]
+fn colors_as_in_pictures() {
+  let theme = Theme::from_toml("grid = \"#ccc8\"\n[box]\nfill = \"mix(navy, white, 20%)\"\n").unwrap();
+  assert_eq!(Color::new(0x88CCCCCC), theme.grid);
+  assert_eq!(Color::new(0xFFCCCCE6), theme.rectangle.fill);
+  assert_eq!(Err("grid should be a color, not `#ccccc`".into()), Theme::from_toml("grid = \"#ccccc\""));
+}
+
+#[test]
+fn shadows() {
+  let theme = Theme::from_toml("[box]\nshadow = \"shadow(2px, 2px)\"\n[arrow]\nshadow = \"glow\"\n").unwrap();
+  assert_eq!(Some(2.), theme.rectangle.shadow.map(|shadow| shadow.offset.x));
+  assert_eq!(Some(6.), theme.arrow.shadow.map(|shadow| shadow.blur));
+  assert_eq!(Err("box.shadow should be a shadow like `shadow(2px, 2px)` or `glow`, not `drop`".into()), Theme::from_toml("[box]\nshadow = \"drop\""));
+}
+
+#[test]
+fn shaded_fills() {
+  let theme = Theme::from_toml("[cylinder]\nfill = \"gradient(white, silver)\"\n").unwrap();
+  assert_eq!(Some(Shading::Linear(180., vec![Color::WHITE, Color::new(0xFFC0C0C0)])), theme.cylinder.shading);
+  assert_eq!(Err("`radial` takes two or more colors for box.fill".into()), Theme::from_toml("[box]\nfill = \"radial(red)\""));
+}
+
+#[test]
+fn dashes() {
+  let theme = Theme::from_toml("[arrow]\neffect = \"dash 6px 3px\"\nthickness = \"thick\"\n").unwrap();
+  assert_eq!(Effect::Dash(vec![6., 3.]), theme.arrow.effect);
+  assert_eq!(Err("box.effect should be solid, dashed, dotted or a dash like `dash 6px 3px`, not `wavy`".into()), Theme::from_toml("[box]\neffect = \"wavy\""));
+}
+
+#[test]
+fn errors() {
+  assert_eq!(Err("unknown setting `box.colour`".into()), Theme::from_toml("[box]\ncolour = \"red\""));
+  assert_eq!(Err("unknown color `reddish` for grid".into()), Theme::from_toml("grid = \"reddish\""));
+  assert_eq!(Err("line.length should be a length, not `long`".into()), Theme::from_toml("[line]\nlength = \"long\""));
+  assert_eq!(Err("unknown variable `$long` for line.length".into()), Theme::from_toml("[line]\nlength = \"$long\""));
+  assert_eq!(Err("unknown variable `$blur` for box.shadow".into()), Theme::from_toml("[box]\nshadow = \"shadow(2px, 2px, $blur)\""));
+  assert!(Theme::find("neon", &[]).unwrap_err().contains("light, dark, print"));
+}
+
+#[test]
+fn picked_in_the_picture() {
+  let mut diagram = Diagram::inset(A5, (0., 0.));
+  diagram.parse_string("theme \"dark\"\nbox \"x\"").unwrap();
+  assert_eq!((Color::WHITE, Color::WHITE, 1.), box_colors(&diagram));
+
+  diagram.parse_string("theme \"neon\"\nbox").unwrap();
+  assert_eq!(1, diagram.diagnostics.errors().count());
+}
+
+#[test]
+fn given_to_the_diagram() {
+  let mut diagram = Diagram::inset(A5, (0., 0.));
+  diagram.theme = Theme::find("print", &[]).unwrap();
+  diagram.parse_string("box fill pink\nbox thick").unwrap();
+  assert_eq!((Color::BLACK, Color::BLACK, 2.), box_colors(&diagram));
+}
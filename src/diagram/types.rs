@@ -1,15 +1,18 @@
 #[cfg(test)]
 mod tests;
 
+use std::f32::consts::SQRT_2;
 use std::fmt::Display;
 use std::ops::{Add, Mul};
 
 use crate::diagram::attributes::{Attributes, EdgeMovement};
-use skia_safe::{scalar, Color, Font, FontMgr, FontStyle, Point, Rect, Size, Vector};
+use crate::diagram::edges::EdgeFinder;
+use skia_safe::{scalar, Color, Font, Point, Rect, Size, Vector};
 
 use crate::diagram::parser::TEXT_PADDING;
+use crate::diagram::theme::Theme;
 use crate::diagram::types::EdgeDirection::{Horizontal, Vertical};
-use crate::skia::Effect;
+use crate::skia::{Effect, Shading, Shadow};
 use crate::trig::{x_from_degrees, y_from_degrees};
 
 pub const BLOCK_PADDING: f32 = 8.;
@@ -66,16 +69,198 @@ pub enum Shape {
   Rectangle,
   Cylinder,
   File,
+  Polygon(Polygon),
   Text(Paragraph, Option<EdgeMovement>),
 
   Arrow(Vec<Point>, Option<Caption>, Endings),
   Line(Vec<Point>, Option<Caption>, Endings),
   Sline(Vec<Point>, Option<Caption>, Endings),
+  Arc(Arc, Option<Caption>, Endings),
+  Curve(Curve, Option<Caption>, Endings),
   Path(Vec<Point>, Option<Caption>),
 
   Dot(Point, Radius, Option<Caption>),
 }
 
+/// Closed shapes of straight edges, stretched to their rect
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Polygon {
+  Diamond,
+  Triangle,
+  Hexagon,
+  Parallelogram,
+  Trapezoid,
+  Star,
+}
+
+impl Polygon {
+  pub fn some(name: &str) -> Option<Self> {
+    match name {
+      "diamond" => Some(Polygon::Diamond),
+      "triangle" => Some(Polygon::Triangle),
+      "hexagon" => Some(Polygon::Hexagon),
+      "parallelogram" => Some(Polygon::Parallelogram),
+      "trapezoid" => Some(Polygon::Trapezoid),
+      "star" => Some(Polygon::Star),
+      _ => None
+    }
+  }
+
+  pub fn outline(&self, rect: &Rect) -> EdgeFinder {
+    let (x, y, width, height) = (rect.left, rect.top, rect.width(), rect.height());
+    match self {
+      Polygon::Diamond => EdgeFinder::diamond(x, y, width, height),
+      Polygon::Triangle => EdgeFinder::triangle(x, y, width, height),
+      Polygon::Hexagon => EdgeFinder::hexagon(x, y, width, height),
+      Polygon::Parallelogram => EdgeFinder::parallelogram(x, y, width, height),
+      Polygon::Trapezoid => EdgeFinder::trapezoid(x, y, width, height),
+      Polygon::Star => EdgeFinder::star(x, y, width, height),
+    }
+  }
+
+  /// the part of `rect` inside the outline that text goes in
+  pub fn inner(&self, rect: &Rect) -> Rect {
+    let (width, height) = (rect.width(), rect.height());
+    let slant = EdgeFinder::slant(width, height);
+    match self {
+      Polygon::Diamond => rect.with_inset((width / 4., height / 4.)),
+      Polygon::Triangle => Rect::from_xywh(rect.left + width / 4., rect.top + height / 2., width / 2., height / 2.),
+      Polygon::Hexagon => rect.with_inset((slant / 2., height / 4.)),
+      Polygon::Parallelogram | Polygon::Trapezoid => rect.with_inset((slant * 0.75, height / 4.)),
+      Polygon::Star => {
+        // from the two upper inner corners down to the level of the two side ones
+        let corners = self.outline(rect).corners();
+        Rect::new(corners[9].x, corners[9].y, corners[1].x, corners[3].y)
+      }
+    }
+  }
+}
+
+/// Part of a circle, in degrees clockwise from the right, as skia draws them
+#[derive(Clone, Debug, PartialEq)]
+pub struct Arc {
+  pub center: Point,
+  pub radius: f32,
+  pub start: f32,
+  pub sweep: f32,
+}
+
+impl Arc {
+  /// a quarter circle from `start`, turning off `heading` to the right when clockwise
+  pub fn turning(start: Point, heading: Vector, radius: f32, clockwise: bool) -> Self {
+    let side = if clockwise { 1. } else { -1. };
+    let heading = heading.mul(1. / heading.length().max(f32::EPSILON));
+    let center = start.add(Vector::new(-heading.y, heading.x).mul(radius * side));
+    Self { center, radius, start: Self::angle(start - center), sweep: 90. * side }
+  }
+
+  /// from `start` to `end`, a quarter circle unless the radius makes it flatter or rounder, up to a half;
+  /// none when they are the same point
+  pub fn through(start: Point, end: Point, radius: Option<f32>, clockwise: bool) -> Option<Self> {
+    let chord = end - start;
+    if chord.length() == 0. {
+      return None;
+    }
+    let half = chord.length() / 2.;
+    let radius = radius.unwrap_or(half * SQRT_2).max(half);
+    let side = if clockwise { 1. } else { -1. };
+    let normal = Vector::new(-chord.y, chord.x).mul(side / chord.length());
+    let center = start.add(chord.mul(0.5)).add(normal.mul((radius * radius - half * half).sqrt()));
+    let (from, to) = (Self::angle(start - center), Self::angle(end - center));
+    let sweep = match clockwise {
+      true => (to - from).rem_euclid(360.),
+      false => -(from - to).rem_euclid(360.),
+    };
+    Some(Self { center, radius, start: from, sweep })
+  }
+
+  fn angle(vector: Vector) -> f32 {
+    vector.y.atan2(vector.x).to_degrees()
+  }
+
+  pub fn point_at(&self, degrees: f32) -> Point {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    self.center.add(Vector::new(cos, sin).mul(self.radius))
+  }
+
+  pub fn start_point(&self) -> Point {
+    self.point_at(self.start)
+  }
+
+  pub fn end_point(&self) -> Point {
+    self.point_at(self.start + self.sweep)
+  }
+
+  /// the way the arc goes at `degrees`, along the tangent
+  pub fn heading(&self, degrees: f32) -> Vector {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    Vector::new(-sin, cos).mul(self.sweep.signum())
+  }
+
+  pub fn oval(&self) -> Rect {
+    Rect::from_point_and_size(self.center, (0., 0.)).with_outset((self.radius, self.radius))
+  }
+}
+
+/// Cubic béziers end to end, each a start, two control points and an end
+#[derive(Clone, Debug, PartialEq)]
+pub struct Curve(pub Vec<[Point; 4]>);
+
+impl Curve {
+  /// Catmull-Rom through all `points`, straight at tension 0 and rounder above 1
+  pub fn through(points: &[Point], tension: f32) -> Self {
+    let at = |index: isize| points[index.clamp(0, points.len() as isize - 1) as usize];
+    let segments = (0..points.len() as isize - 1).map(|index| {
+      let (before, from, to, after) = (at(index - 1), at(index), at(index + 1), at(index + 2));
+      [from, from.add((to - before).mul(tension / 6.)), to.add((from - after).mul(tension / 6.)), to]
+    }).collect();
+    Self(segments)
+  }
+
+  /// from `start` to `end`, pulled toward one control point, or two for the start and the end
+  pub fn controlled(start: Point, controls: &[Point], end: Point) -> Self {
+    let segment = match controls {
+      [] => [start, start, end, end],
+      [control] => [start, start.add((*control - start).mul(2. / 3.)), end.add((*control - end).mul(2. / 3.)), end],
+      [first, second, ..] => [start, *first, *second, end],
+    };
+    Self(vec![segment])
+  }
+
+  pub fn start(&self) -> Point {
+    self.0[0][0]
+  }
+
+  pub fn end(&self) -> Point {
+    self.0[self.0.len() - 1][3]
+  }
+
+  /// where the curve is at `t`, from 0 at the start to 1 at the end, each segment taking an equal part
+  pub fn point_at(&self, t: f32) -> Point {
+    let scaled = t.clamp(0., 1.) * self.0.len() as f32;
+    let index = (scaled as usize).min(self.0.len() - 1);
+    let t = scaled - index as f32;
+    let [a, b, c, d] = self.0[index];
+    let u = 1. - t;
+    a.mul(u * u * u).add(b.mul(3. * u * u * t)).add(c.mul(3. * u * t * t)).add(d.mul(t * t * t))
+  }
+
+  /// the way the curve leaves its start and arrives at its end
+  pub fn headings(&self) -> (Vector, Vector) {
+    let [start, ..] = self.0[0];
+    let [.., end] = self.0[self.0.len() - 1];
+    let first = self.0[0][1..].iter().map(|point| *point - start).find(|heading| heading.length() > 0.);
+    let last = self.0[self.0.len() - 1][..3].iter().rev().map(|point| end - *point).find(|heading| heading.length() > 0.);
+    (first.unwrap_or_default(), last.unwrap_or_default())
+  }
+
+  /// points along the curve, `steps` to a segment
+  pub fn flatten(&self, steps: usize) -> Vec<Point> {
+    let count = steps * self.0.len();
+    (0..=count).map(|step| self.point_at(step as f32 / count as f32)).collect()
+  }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Paragraph {
   pub text: String,
@@ -116,15 +301,45 @@ impl Caption {
 pub enum Ending {
   #[default]
   None,
+  /// filled triangle
   Arrow,
+  /// two strokes, without a fill
+  Open,
+  /// outlined triangle, as for inheritance
+  Hollow,
+  /// one barb only, on the left going toward the end
+  Half,
+  /// filled diamond, as for composition
+  Diamond,
+  /// outlined diamond, as for aggregation
+  HollowDiamond,
   Dot,
+  Circle,
+  Bar,
+  /// crow's foot cardinalities
+  One,
+  Many,
+  ZeroOrOne,
+  OneOrMany,
 }
 
 impl From<&str> for Ending {
+  /// the same ending from either side, so `<|` and `|>` are both hollow
   fn from(item: &str) -> Self {
     match item {
       "<" | ">" => Ending::Arrow,
+      "<<" | ">>" => Ending::Open,
+      "<|" | "|>" => Ending::Hollow,
+      "\\" => Ending::Half,
+      "<*>" => Ending::Diamond,
+      "<>" => Ending::HollowDiamond,
       "*" => Ending::Dot,
+      "o" => Ending::Circle,
+      "|" => Ending::Bar,
+      "||" => Ending::One,
+      "}" | "{" => Ending::Many,
+      "|o" | "o|" => Ending::ZeroOrOne,
+      "}|" | "|{" => Ending::OneOrMany,
       _ => Ending::None,
     }
   }
@@ -134,24 +349,20 @@ impl From<&str> for Ending {
 pub struct Endings {
   pub start: Ending,
   pub end: Ending,
+  /// halfway along, pointing toward the end
+  pub middle: Ending,
 }
 
 impl From<&str> for Endings {
   fn from(str: &str) -> Self {
-    let mut chars = str.chars();
-    let start = match chars.next().unwrap() {
-      '<' => Ending::Arrow,
-      '*' => Ending::Dot,
-      _ => Ending::None,
-    };
-    let end = match chars.last().unwrap() {
-      '>' => Ending::Arrow,
-      '*' => Ending::Dot,
-      _ => Ending::None,
-    };
+    let mut parts = str.split('-');
+    let start = Ending::from(parts.next().unwrap_or_default());
+    let end = parts.next_back().map(Ending::from).unwrap_or_default();
+    let middle = parts.next().map(Ending::from).unwrap_or_default();
     Self {
       start,
       end,
+      middle,
     }
   }
 }
@@ -161,15 +372,26 @@ pub struct Config {
   pub(crate) group: ShapeConfig,
   pub(crate) continuation: Continuation,
   pub(crate) unit: Unit,
-  pub(crate) line: Length,
+  pub(crate) line: ShapeConfig,
+  pub(crate) arrow: ShapeConfig,
+  pub(crate) sline: ShapeConfig,
+  pub(crate) arc: ShapeConfig,
+  pub(crate) curve: ShapeConfig,
+  pub(crate) path: ShapeConfig,
   pub(crate) circle: ShapeConfig,
-  pub(crate) dot: Length,
+  pub(crate) dot: ShapeConfig,
   pub(crate) ellipse: ShapeConfig,
   pub(crate) oval: ShapeConfig,
   pub(crate) rectangle: ShapeConfig,
   pub(crate) text: ShapeConfig,
   pub(crate) file: ShapeConfig,
   pub(crate) cylinder: ShapeConfig,
+  pub(crate) diamond: ShapeConfig,
+  pub(crate) triangle: ShapeConfig,
+  pub(crate) hexagon: ShapeConfig,
+  pub(crate) parallelogram: ShapeConfig,
+  pub(crate) trapezoid: ShapeConfig,
+  pub(crate) star: ShapeConfig,
   pub(crate) font: Font,
 }
 
@@ -181,8 +403,19 @@ pub struct ShapeConfig {
   pub(crate) height: f32,
   pub(crate) radius: f32,
   pub(crate) space: f32,
+  pub(crate) length: f32,
   pub(crate) stroke: Color,
+  pub(crate) fill: Color,
+  /// a gradient or pattern over the fill
+  pub(crate) shading: Option<Shading>,
+  pub(crate) text: Color,
+  pub(crate) thickness: f32,
   pub(crate) effect: Effect,
+  pub(crate) shadow: Option<Shadow>,
+  pub(crate) endings: Option<Endings>,
+  /// where captions go, and whether they hide what is behind them
+  pub(crate) caption: Option<(Edge, Edge)>,
+  pub(crate) opaque: bool,
 }
 
 impl Default for ShapeConfig {
@@ -199,8 +432,17 @@ impl ShapeConfig {
       height: HEIGHT.pixels(),
       radius: 0.,
       space: 0.,
+      length: WIDTH.pixels(),
       stroke: color,
+      fill: Color::TRANSPARENT,
+      shading: None,
+      text: Color::BLACK,
+      thickness: 1.0,
       effect: Effect::default(),
+      shadow: None,
+      endings: None,
+      caption: None,
+      opaque: false,
     }
   }
 }
@@ -213,30 +455,46 @@ impl Default for Config {
 
 impl Config {
   pub fn new(flow: Continuation) -> Self {
-    let typeface = FontMgr::default().match_family_style("Helvetica", FontStyle::default()).unwrap();
-    let font = Font::from_typeface(typeface, 17.0);
+    Self::themed(flow, &Theme::default())
+  }
+
+  pub fn themed(flow: Continuation, theme: &Theme) -> Self {
     Self {
-      group: ShapeConfig::stroke(Color::TRANSPARENT),
+      group: theme.group.clone(),
       continuation: flow,
-      dot: Length::new(4., Unit::Px),
-      circle: ShapeConfig::default(),
-      cylinder: ShapeConfig::default(),
-      ellipse: ShapeConfig::default(),
-      line: WIDTH,
-      oval: ShapeConfig::default(),
-      rectangle: ShapeConfig::default(),
-      text: ShapeConfig::default(),
+      dot: theme.dot.clone(),
+      circle: theme.circle.clone(),
+      cylinder: theme.cylinder.clone(),
+      ellipse: theme.ellipse.clone(),
+      line: theme.line.clone(),
+      arrow: theme.arrow.clone(),
+      sline: theme.sline.clone(),
+      arc: theme.arc.clone(),
+      curve: theme.curve.clone(),
+      path: theme.path.clone(),
+      oval: theme.oval.clone(),
+      rectangle: theme.rectangle.clone(),
+      text: theme.text.clone(),
       unit: Unit::default(),
-      file: ShapeConfig {
-        padding: 8.0,
-        width: HEIGHT.pixels(),
-        height: WIDTH.pixels(),
-        radius: 8.0,
-        space: 0.0,
-        stroke: Color::BLUE,
-        effect: Effect::default(),
-      },
-      font,
+      file: theme.file.clone(),
+      diamond: theme.diamond.clone(),
+      triangle: theme.triangle.clone(),
+      hexagon: theme.hexagon.clone(),
+      parallelogram: theme.parallelogram.clone(),
+      trapezoid: theme.trapezoid.clone(),
+      star: theme.star.clone(),
+      font: theme.font(),
+    }
+  }
+
+  pub(crate) fn polygon(&self, polygon: Polygon) -> &ShapeConfig {
+    match polygon {
+      Polygon::Diamond => &self.diamond,
+      Polygon::Triangle => &self.triangle,
+      Polygon::Hexagon => &self.hexagon,
+      Polygon::Parallelogram => &self.parallelogram,
+      Polygon::Trapezoid => &self.trapezoid,
+      Polygon::Star => &self.star,
     }
   }
 
@@ -499,22 +757,26 @@ pub enum Unit {
   Pt,
   Pc,
   Cm,
+  Mm,
   In,
   #[default]
   Px,
   Unit,
 }
 
-impl From<&str> for Unit {
-  fn from(item: &str) -> Self {
+impl TryFrom<&str> for Unit {
+  type Error = String;
+
+  fn try_from(item: &str) -> Result<Self, Self::Error> {
     match item {
-      "cm" => Unit::Cm,
-      "in" => Unit::In,
-      "pc" => Unit::Pc,
-      "pt" => Unit::Pt,
-      "px" => Unit::Px,
-      "u" => Unit::Unit,
-      _ => panic!("unknown unit {}", item),
+      "cm" => Ok(Unit::Cm),
+      "mm" => Ok(Unit::Mm),
+      "in" => Ok(Unit::In),
+      "pc" => Ok(Unit::Pc),
+      "pt" => Ok(Unit::Pt),
+      "px" => Ok(Unit::Px),
+      "u" => Ok(Unit::Unit),
+      _ => Err(format!("unknown unit `{}`", item)),
     }
   }
 }
@@ -544,6 +806,7 @@ impl Length {
   pub fn pixels_for(length: f32, unit: Unit) -> f32 {
     match unit {
       Unit::Cm => (length * 39.3701).trunc(),
+      Unit::Mm => (length * 3.93701).trunc(),
       Unit::In => length * PPI,
       Unit::Pc => length * PPI / 6.,
       Unit::Pt => length * PPI / 72.,
@@ -554,6 +817,7 @@ impl Length {
   pub fn points(&self) -> f32 {
     match self.unit {
       Unit::Cm => self.length / 28.3465,
+      Unit::Mm => self.length / 2.83465,
       Unit::In => self.length / 72.,
       Unit::Pc => self.length / 12.,
       Unit::Px => self.length / 1.3333,
@@ -607,6 +871,7 @@ impl Displacement {
 pub struct ObjectEdge {
   pub(crate) id: String,
   pub(crate) edge: Edge,
+  pub(crate) terminal: Option<Terminal>,
 }
 
 impl ObjectEdge {
@@ -614,6 +879,14 @@ impl ObjectEdge {
     Self {
       id: id.into(),
       edge: edge.into(),
+      terminal: None,
     }
   }
 }
+
+/// `.start` and `.end` of a line, instead of a point on its bounds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Terminal {
+  Start,
+  End,
+}
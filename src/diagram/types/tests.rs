@@ -127,12 +127,127 @@ mod endings {
   #[test]
   fn ending() {
     assert_eq!(Ending::from("<"), Ending::Arrow);
+    assert_eq!(Ending::from("<|"), Ending::from("|>"));
+    assert_eq!(Ending::from("}|"), Ending::OneOrMany);
+    assert_eq!(Ending::from("o|"), Ending::ZeroOrOne);
   }
 
   #[test]
   fn endings() {
-    assert_eq!(Endings::from("<->"), Endings { start: Ending::Arrow, end: Ending::Arrow });
-    assert_eq!(Endings::from("<-"), Endings { start: Ending::Arrow, end: Ending::None });
-    assert_eq!(Endings::from("->"), Endings { start: Ending::None, end: Ending::Arrow });
+    assert_eq!(Endings::from("<->"), Endings { start: Ending::Arrow, end: Ending::Arrow, middle: Ending::None });
+    assert_eq!(Endings::from("<-"), Endings { start: Ending::Arrow, end: Ending::None, middle: Ending::None });
+    assert_eq!(Endings::from("->"), Endings { start: Ending::None, end: Ending::Arrow, middle: Ending::None });
+    assert_eq!(Endings::from("<>->-|>"), Endings { start: Ending::HollowDiamond, end: Ending::Hollow, middle: Ending::Arrow });
+  }
+}
+
+#[cfg(test)]
+mod polygon {
+  use skia_safe::{Point, Rect};
+  use crate::diagram::types::{Edge, Polygon};
+
+  const ALL: [Polygon; 6] = [Polygon::Diamond, Polygon::Triangle, Polygon::Hexagon, Polygon::Parallelogram, Polygon::Trapezoid, Polygon::Star];
+
+  #[test]
+  fn toward_the_outline() {
+    let rect = Rect::from_xywh(0., 0., 200., 100.);
+    let toward = |polygon: Polygon, edge: &str| polygon.outline(&rect).toward(Edge::from(edge).edge_point(&rect))
+      .map(|point| (point.x.round(), point.y.round()));
+    assert_eq!(Some((100., 0.)), toward(Polygon::Diamond, "n"));
+    assert_eq!(Some((200., 50.)), toward(Polygon::Diamond, "e"));
+    assert_eq!(Some((150., 25.)), toward(Polygon::Diamond, "ne"));
+    assert_eq!(Some((100., 50.)), toward(Polygon::Diamond, "c"));
+    assert_eq!(Some((100., 0.)), toward(Polygon::Star, "n"));
+    assert_eq!(Some((0., 100.)), toward(Polygon::Parallelogram, "sw"));
+    assert_eq!(Some((40., 20.)), toward(Polygon::Parallelogram, "nw"));
+  }
+
+  #[test]
+  fn text_inside() {
+    let rect = Rect::from_xywh(10., 20., 120., 80.);
+    for polygon in ALL {
+      let outline = polygon.outline(&rect);
+      let inner = polygon.inner(&rect);
+      assert!(!inner.is_empty(), "{:?}", polygon);
+      for corner in [(inner.left, inner.top), (inner.right, inner.top), (inner.right, inner.bottom), (inner.left, inner.bottom)] {
+        let corner = Point::from(corner);
+        let edge = outline.toward(corner).unwrap();
+        let center = rect.center();
+        assert!((corner - center).length() <= (edge - center).length() + 0.01, "{:?} {:?}", polygon, corner);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod arc {
+  use skia_safe::{Point, Vector};
+  use crate::diagram::types::Arc;
+
+  fn rounded(point: Point) -> (f32, f32) {
+    (point.x.round(), point.y.round())
+  }
+
+  #[test]
+  fn turning() {
+    let arc = Arc::turning(Point::new(0., 0.), Vector::new(1., 0.), 10., false);
+    assert_eq!((10., -10.), rounded(arc.end_point()));
+    assert_eq!((1., 0.), rounded(arc.heading(arc.start)));
+    assert_eq!((0., -1.), rounded(arc.heading(arc.start + arc.sweep)));
+
+    let arc = Arc::turning(Point::new(0., 0.), Vector::new(0., 2.), 10., true);
+    assert_eq!((-10., 10.), rounded(arc.end_point()));
+  }
+
+  #[test]
+  fn through() {
+    let quarter = Arc::through(Point::new(0., 0.), Point::new(20., 0.), None, true).unwrap();
+    assert_eq!((10., 10.), rounded(quarter.center));
+    assert_eq!(90., quarter.sweep.round());
+    assert_eq!((20., 0.), rounded(quarter.end_point()));
+
+    let half = Arc::through(Point::new(0., 0.), Point::new(20., 0.), Some(5.), false).unwrap();
+    assert_eq!((10., 10.), (half.radius, half.center.x));
+    assert_eq!(-180., half.sweep.round());
+
+    assert_eq!(None, Arc::through(Point::new(5., 5.), Point::new(5., 5.), None, true));
+  }
+}
+
+mod curve {
+  use skia_safe::Point;
+  use crate::diagram::types::Curve;
+
+  fn rounded(point: Point) -> (f32, f32) {
+    (point.x.round(), point.y.round())
+  }
+
+  #[test]
+  fn through_its_points() {
+    let points = [Point::new(0., 0.), Point::new(50., 50.), Point::new(100., 0.)];
+    let curve = Curve::through(&points, 1.);
+    assert_eq!(2, curve.0.len());
+    assert_eq!((50., 50.), rounded(curve.point_at(0.5)));
+    assert_eq!((100., 0.), rounded(curve.end()));
+    let (leaving, arriving) = curve.headings();
+    assert!(leaving.x > 0. && leaving.y > 0. && arriving.x > 0. && arriving.y < 0.);
+  }
+
+  #[test]
+  fn no_tension_is_straight() {
+    let curve = Curve::through(&[Point::new(0., 0.), Point::new(40., 0.), Point::new(40., 40.)], 0.);
+    assert_eq!((40., 0.), rounded(curve.0[0][2]));
+    assert_eq!((40., 0.), rounded(curve.0[1][1]));
+  }
+
+  #[test]
+  fn controlled() {
+    let curve = Curve::controlled(Point::new(0., 0.), &[Point::new(0., 40.), Point::new(40., 40.)], Point::new(40., 0.));
+    let (leaving, arriving) = curve.headings();
+    assert_eq!((0., 40.), rounded(leaving));
+    assert_eq!((0., -40.), rounded(arriving));
+
+    let curve = Curve::controlled(Point::new(0., 0.), &[Point::new(20., 40.)], Point::new(40., 0.));
+    assert_eq!((20., 20.), rounded(curve.point_at(0.5)));
   }
 }
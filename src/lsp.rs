@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use pest::iterators::Pair;
+use serde_json::{json, Value};
+use skia_safe::Rect;
+use url::Url;
+
+use crate::debug_rect;
+use crate::diagram::colors::Colors;
+use crate::diagram::conversion::Conversion;
+use crate::diagram::diagnostics::{Diagnostic, Severity};
+use crate::diagram::parser::{Diagram, Rule};
+use crate::diagram::source::Source;
+use crate::pic;
+use crate::pic::Dialect;
+use crate::skia::A5;
+
+#[cfg(test)]
+mod tests;
+
+const UNITS: &[&str] = &["in", "cm", "mm", "pt", "pc", "px", "u"];
+const COMPASS: &[&str] = &["n", "ne", "e", "se", "s", "sw", "w", "nw", "c"];
+
+/// A language server speaking JSON-RPC with `Content-Length` headers, usually over stdin and stdout
+pub struct Server<W: Write> {
+  output: W,
+  search: Vec<PathBuf>,
+  documents: HashMap<String, Document>,
+}
+
+/// An open file and what its last layout that parsed found
+#[derive(Debug, Default)]
+struct Document {
+  text: String,
+  ids: Vec<(String, Rect)>,
+  /// statement spans in `text`, with their used rect
+  placed: Vec<((usize, usize), Rect)>,
+}
+
+impl<W: Write> Server<W> {
+  pub fn new(output: W, search: &[PathBuf]) -> Self {
+    Self { output, search: search.to_vec(), documents: HashMap::new() }
+  }
+
+  /// Answer requests until the client says `exit`
+  pub fn serve(&mut self, mut input: impl BufRead) -> io::Result<()> {
+    while let Some(message) = Self::read(&mut input)? {
+      let method = message["method"].as_str().unwrap_or_default();
+      if method == "exit" {
+        break;
+      }
+      let result = self.handle(method, &message["params"])?;
+      if let Some(id) = message.get("id") {
+        self.write(&json!({ "jsonrpc": "2.0", "id": id, "result": result }))?;
+      }
+    }
+    Ok(())
+  }
+
+  fn handle(&mut self, method: &str, params: &Value) -> io::Result<Value> {
+    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+    Ok(match method {
+      "initialize" => json!({
+        "capabilities": {
+          "textDocumentSync": 1,
+          "completionProvider": { "triggerCharacters": [".", " "] },
+          "definitionProvider": true,
+          "hoverProvider": true,
+        },
+        "serverInfo": { "name": "picturs", "version": env!("CARGO_PKG_VERSION") },
+      }),
+      "textDocument/didOpen" => {
+        let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+        self.update(&uri, text)?;
+        Value::Null
+      }
+      "textDocument/didChange" => {
+        let changes = params["contentChanges"].as_array().cloned().unwrap_or_default();
+        if let Some(text) = changes.last().and_then(|change| change["text"].as_str()) {
+          self.update(&uri, text)?;
+        }
+        Value::Null
+      }
+      "textDocument/didClose" => {
+        self.documents.remove(&uri);
+        self.publish(&uri, vec![])?;
+        Value::Null
+      }
+      "textDocument/completion" | "textDocument/definition" | "textDocument/hover" => {
+        let Some(document) = self.documents.get(&uri) else {
+          return Ok(Value::Null);
+        };
+        let offset = offset_at(&document.text, &params["position"]);
+        match method {
+          "textDocument/completion" => Value::Array(document.completions(offset)),
+          "textDocument/definition" => document.definition(offset)
+            .map(|span| json!({ "uri": uri, "range": range(&document.text, span) }))
+            .unwrap_or(Value::Null),
+          _ => document.hover(offset)
+            .map(|(span, rect)| json!({ "contents": debug_rect(&rect), "range": range(&document.text, span) }))
+            .unwrap_or(Value::Null),
+        }
+      }
+      _ => Value::Null,
+    })
+  }
+
+  /// Lay out the new text and report what is wrong with it
+  fn update(&mut self, uri: &str, text: &str) -> io::Result<()> {
+    // decodes escapes such as `%20`, and is none for documents that are not files
+    let path = Url::parse(uri).ok().and_then(|url| url.to_file_path().ok());
+    let document = self.documents.entry(uri.to_string()).or_default();
+    document.text = text.to_string();
+    let diagnostics = match Dialect::detect(path.as_deref(), text) {
+      Some(dialect) => Document::pic_diagnostics(text, dialect),
+      None => document.analyze(path.unwrap_or_default(), &self.search),
+    };
+    self.publish(uri, diagnostics)
+  }
+
+  fn publish(&mut self, uri: &str, diagnostics: Vec<Value>) -> io::Result<()> {
+    self.write(&json!({
+      "jsonrpc": "2.0",
+      "method": "textDocument/publishDiagnostics",
+      "params": { "uri": uri, "diagnostics": diagnostics },
+    }))
+  }
+
+  fn read(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+      let mut header = String::new();
+      if input.read_line(&mut header)? == 0 {
+        return Ok(None);
+      }
+      let header = header.trim_end();
+      if header.is_empty() {
+        break;
+      }
+      if let Some(value) = header.strip_prefix("Content-Length:") {
+        length = value.trim().parse::<usize>().ok();
+      }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(io::Error::from)
+  }
+
+  fn write(&mut self, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    self.output.flush()
+  }
+}
+
+impl Document {
+  /// Diagnostics of a layout, keeping ids and placements when it parsed
+  fn analyze(&mut self, path: PathBuf, search: &[PathBuf]) -> Vec<Value> {
+    let source = Source::from_file_text(&path, self.text.clone(), search);
+    let mut diagram = Diagram::inset(A5, (0., 0.));
+    let parsed = diagram.parse_source(&source);
+    let mut diagnostics = diagram.diagnostics.iter()
+      .map(|diagnostic| self.diagnostic(diagnostic))
+      .collect::<Vec<_>>();
+    match parsed {
+      Ok(_) => self.remember(&diagram, &source),
+      Err(error) => {
+        // while typing, what comes before the broken line still has ids to complete
+        let before = line_offset(&self.text, error.line, 1);
+        if error.file.is_none() && before > 0 {
+          let source = Source::from_file_text(&path, &self.text[..before], search);
+          let mut diagram = Diagram::inset(A5, (0., 0.));
+          if diagram.parse_source(&source).is_ok() {
+            self.remember(&diagram, &source);
+          }
+        }
+        let message = match &error.file {
+          Some(file) => format!("{} in {}", error.message, file),
+          None => error.message.clone(),
+        };
+        let at = if error.file.is_some() { (1, 1) } else { (error.line, error.column) };
+        diagnostics.push(self.diagnostic(&Diagnostic {
+          severity: Severity::Error,
+          message,
+          span: error.span,
+          line: at.0,
+          column: at.1,
+          snippet: error.snippet,
+          file: None,
+        }));
+      }
+    }
+    diagnostics
+  }
+
+  fn remember(&mut self, diagram: &Diagram, source: &Source) {
+    self.ids = diagram.ids.iter()
+      .filter(|(id, _)| !id.contains('/'))
+      .map(|(id, rect)| (id.clone(), *rect))
+      .collect();
+    self.ids.sort_by(|(a, _), (b, _)| a.cmp(b));
+    self.placed = diagram.placed.iter()
+      .filter_map(|((start, end), rect)| Some(((source.original(*start)?, source.original(end - 1)? + 1), *rect)))
+      .collect();
+  }
+
+  fn pic_diagnostics(text: &str, dialect: Dialect) -> Vec<Value> {
+    let document = Document { text: text.to_string(), ..Document::default() };
+    let pictures = match pic::pictures(text, dialect) {
+      Ok(pictures) => pictures,
+      Err(error) => {
+        let offset = line_offset(text, error.line, error.column);
+        return vec![json!({ "range": range(text, (offset, offset)), "severity": 1, "message": error.message })];
+      }
+    };
+    let mut diagnostics = vec![];
+    for statements in pictures {
+      let mut diagram = Diagram::inset(A5, (0., 0.));
+      diagram.parse_pic(statements, dialect);
+      diagnostics.extend(diagram.diagnostics.iter().map(|diagnostic| document.diagnostic(diagnostic)));
+    }
+    diagnostics
+  }
+
+  /// problems in included files are shown on the first line, naming the file
+  fn diagnostic(&self, diagnostic: &Diagnostic) -> Value {
+    let (start, end, message) = match &diagnostic.file {
+      Some(file) => (0, 0, format!("{} in {}", diagnostic.message, file)),
+      None => {
+        let start = line_offset(&self.text, diagnostic.line, diagnostic.column);
+        let line_end = self.text[start..].find('\n').map(|end| start + end).unwrap_or(self.text.len());
+        let end = (start + diagnostic.span.1 - diagnostic.span.0).min(line_end);
+        (start, end, diagnostic.message.clone())
+      }
+    };
+    let severity = match diagnostic.severity {
+      Severity::Error => 1,
+      Severity::Warning => 2,
+    };
+    json!({ "range": range(&self.text, (start, end)), "severity": severity, "source": "picturs", "message": message })
+  }
+
+  /// ids after `from`, `end`, `to` and the like, compass points after an id and a dot,
+  /// colors after `color` or `fill`, and units after a number
+  fn completions(&self, offset: usize) -> Vec<Value> {
+    let before = &self.text[..offset];
+    let word_start = before.rfind(|c: char| !(c.is_alphanumeric() || c == '_')).map(|at| at + 1).unwrap_or(0);
+    let word = &before[word_start..];
+    let previous = before[..word_start].split_whitespace().last().unwrap_or_default();
+
+    let items = |kind: u32, labels: Vec<(String, Option<String>)>| labels.into_iter()
+      .map(|(label, detail)| json!({ "label": label, "kind": kind, "detail": detail }))
+      .collect::<Vec<_>>();
+
+    if let Some(object) = before[..word_start].strip_suffix('.').map(|before| before.split_whitespace().last().unwrap_or_default()) {
+      // after a shape keyword, the dot starts an id instead
+      let shape = matches!(object, "box" | "circle" | "ellipse" | "cylinder" | "oval" | "file" | "diamond" | "triangle" | "hexagon" | "parallelogram"
+        | "trapezoid" | "star" | "text" | "dot" | "line" | "sline" | "arc" | "curve" | "spline" | "path" | "group");
+      if object.starts_with(|c: char| c.is_alphabetic()) && !shape {
+        return items(12, COMPASS.iter().map(|point| (point.to_string(), None)).collect());
+      }
+    }
+    if word.starts_with(|c: char| c.is_ascii_digit()) {
+      let digits = word.trim_end_matches(|c: char| c.is_alphabetic()).len();
+      let number = &word[..digits];
+      return items(11, UNITS.iter().map(|unit| (format!("{}{}", number, unit), None)).collect());
+    }
+    match previous {
+      "from" | "end" | "to" | "at" | "last" => {
+        items(6, self.ids.iter().map(|(id, rect)| (id.clone(), Some(debug_rect(rect)))).collect())
+      }
+      "color" | "stroke" | "fill" | "text" => {
+        items(16, Colors::names().map(|color| (color.to_string(), None)).collect())
+      }
+      _ => vec![],
+    }
+  }
+
+  /// The `.id` an object reference points at
+  fn definition(&self, offset: usize) -> Option<(usize, usize)> {
+    let pairs = Conversion::pairs_for(Rule::picture, &self.text).ok()?;
+    let mut references = vec![];
+    let mut declarations = vec![];
+    pairs.for_each(|pair| Self::ids_in(pair, &mut references, &mut declarations));
+    let (id, _) = references.iter().chain(declarations.iter())
+      .find(|(_, (start, end))| (*start..=*end).contains(&offset))?;
+    declarations.iter().find(|(declared, _)| declared == id).map(|(_, span)| *span)
+  }
+
+  /// the object under the cursor, referenced by id or laid out by the innermost statement there
+  fn hover(&self, offset: usize) -> Option<((usize, usize), Rect)> {
+    if let Ok(pairs) = Conversion::pairs_for(Rule::picture, &self.text) {
+      let mut references = vec![];
+      let mut declarations = vec![];
+      pairs.for_each(|pair| Self::ids_in(pair, &mut references, &mut declarations));
+      let referenced = references.iter().chain(declarations.iter())
+        .find(|(_, (start, end))| (*start..=*end).contains(&offset))
+        .and_then(|(id, span)| self.ids.iter().find(|(known, _)| known == id).map(|(_, rect)| (*span, *rect)));
+      if referenced.is_some() {
+        return referenced;
+      }
+    }
+    self.placed.iter()
+      .filter(|((start, end), _)| (*start..*end).contains(&offset))
+      .min_by_key(|((start, end), _)| end - start)
+      .copied()
+  }
+
+  /// ids used in object references, and the ones given to shapes with `.id`
+  fn ids_in<'a>(pair: Pair<'a, Rule>, references: &mut Vec<(&'a str, (usize, usize))>, declarations: &mut Vec<(&'a str, (usize, usize))>) {
+    let rule = pair.as_rule();
+    for child in pair.into_inner() {
+      if child.as_rule() == Rule::id {
+        let span = (child.as_span().start(), child.as_span().end());
+        match rule {
+          Rule::identified => declarations.push((child.as_str(), span)),
+          Rule::object_edge | Rule::object_ref => references.push((child.as_str(), span)),
+          _ => {}
+        }
+      } else {
+        Self::ids_in(child, references, declarations);
+      }
+    }
+  }
+}
+
+/// byte offset of an LSP position, whose character counts UTF-16 code units
+fn offset_at(text: &str, position: &Value) -> usize {
+  let line = position["line"].as_u64().unwrap_or_default() as usize;
+  let character = position["character"].as_u64().unwrap_or_default() as usize;
+  let start = text.split_inclusive('\n').take(line).map(str::len).sum::<usize>();
+  let mut units = 0;
+  for (index, c) in text[start..].char_indices() {
+    if units >= character || c == '\n' {
+      return start + index;
+    }
+    units += c.len_utf16();
+  }
+  text.len()
+}
+
+fn position(text: &str, offset: usize) -> Value {
+  let before = &text[..offset.min(text.len())];
+  let line = before.matches('\n').count();
+  let character = before.rsplit('\n').next().unwrap_or_default().encode_utf16().count();
+  json!({ "line": line, "character": character })
+}
+
+fn range(text: &str, (start, end): (usize, usize)) -> Value {
+  json!({ "start": position(text, start), "end": position(text, end) })
+}
+
+/// byte offset of a 1-based line and character column, as in diagnostics
+fn line_offset(text: &str, line: usize, column: usize) -> usize {
+  let start = text.split_inclusive('\n').take(line.saturating_sub(1)).map(str::len).sum::<usize>();
+  let line = text[start..].split('\n').next().unwrap_or_default();
+  start + line.char_indices().nth(column.saturating_sub(1)).map(|(index, _)| index).unwrap_or(line.len())
+}
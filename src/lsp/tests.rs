@@ -0,0 +1,133 @@
+use serde_json::{json, Value};
+
+use crate::lsp::Server;
+
+/// frame each message, run a session and return what the server wrote
+fn session(messages: &[Value]) -> Vec<Value> {
+  let mut input = String::new();
+  for message in messages {
+    let body = message.to_string();
+    input.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+  }
+  let mut output = vec![];
+  Server::new(&mut output, &[]).serve(input.as_bytes()).unwrap();
+
+  let mut output = output.as_slice();
+  let mut replies = vec![];
+  while let Some(body) = std::str::from_utf8(output).unwrap().strip_prefix("Content-Length: ") {
+    let (length, rest) = body.split_once("\r\n\r\n").unwrap();
+    let length = length.parse::<usize>().unwrap();
+    replies.push(serde_json::from_str(&rest[..length]).unwrap());
+    output = &rest.as_bytes()[length..];
+  }
+  replies
+}
+
+fn open(text: &str) -> Value {
+  json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+    "textDocument": { "uri": "untitled:test.pic", "languageId": "pic", "version": 1, "text": text },
+  }})
+}
+
+fn request(method: &str, line: usize, character: usize) -> Value {
+  json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": {
+    "textDocument": { "uri": "untitled:test.pic" },
+    "position": { "line": line, "character": character },
+  }})
+}
+
+/// the reply to the request after opening `text`
+fn reply(text: &str, request: Value) -> Value {
+  session(&[open(text), request]).pop().unwrap()["result"].clone()
+}
+
+fn labels(result: &Value) -> Vec<&str> {
+  result.as_array().unwrap().iter().map(|item| item["label"].as_str().unwrap()).collect()
+}
+
+#[test]
+fn initialize() {
+  let replies = session(&[json!({ "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {} })]);
+  let capabilities = &replies[0]["result"]["capabilities"];
+  assert_eq!(json!(true), capabilities["hoverProvider"]);
+  assert_eq!(json!(true), capabilities["definitionProvider"]);
+}
+
+#[test]
+fn stops_at_exit() {
+  let replies = session(&[json!({ "jsonrpc": "2.0", "method": "exit" }), request("textDocument/hover", 0, 0)]);
+  assert!(replies.is_empty());
+}
+
+#[test]
+fn parse_errors() {
+  let replies = session(&[open("box.a\nbox wd )\n")]);
+  let diagnostics = replies[0]["params"]["diagnostics"].as_array().unwrap();
+  assert_eq!(1, diagnostics.len());
+  assert_eq!(json!({ "line": 1, "character": 7 }), diagnostics[0]["range"]["start"]);
+  assert_eq!(json!(1), diagnostics[0]["severity"]);
+}
+
+#[test]
+fn layout_diagnostics() {
+  let replies = session(&[open("box.a\nline from a.e end nowhere.w\n")]);
+  let diagnostics = replies[0]["params"]["diagnostics"].as_array().unwrap();
+  assert_eq!(1, diagnostics.len());
+  assert_eq!(json!(1), diagnostics[0]["range"]["start"]["line"]);
+  assert!(diagnostics[0]["message"].as_str().unwrap().contains("nowhere"), "{}", diagnostics[0]);
+}
+
+#[test]
+fn changes_are_checked_again() {
+  let change = json!({ "jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+    "textDocument": { "uri": "untitled:test.pic", "version": 2 },
+    "contentChanges": [{ "text": "box\n" }],
+  }});
+  let replies = session(&[open("box wd (\n"), change]);
+  assert_eq!(1, replies[0]["params"]["diagnostics"].as_array().unwrap().len());
+  assert!(replies[1]["params"]["diagnostics"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn includes_next_to_an_escaped_path() {
+  let directory = std::env::temp_dir().join("picturs lsp");
+  std::fs::create_dir_all(&directory).unwrap();
+  std::fs::write(directory.join("inc.pic"), "box.inner\n").unwrap();
+  let uri = format!("file://{}/main.pic", directory.display()).replace(' ', "%20");
+  let replies = session(&[json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+    "textDocument": { "uri": uri, "languageId": "pic", "version": 1, "text": "include \"inc.pic\"\narrow from inner.e\n" },
+  }})]);
+  assert_eq!(json!([]), replies[0]["params"]["diagnostics"]);
+}
+
+#[test]
+fn complete_ids() {
+  let text = "box.pic1\ncircle.c1\nline from ";
+  assert_eq!(vec!["c1", "pic1"], labels(&reply(text, request("textDocument/completion", 2, 10))));
+}
+
+#[test]
+fn complete_compass_points_colors_and_units() {
+  let text = "box.pic1\nline from pic1.\nbox fill \nbox wd 2";
+  assert!(labels(&reply(text, request("textDocument/completion", 1, 15))).contains(&"ne"));
+  assert!(labels(&reply(text, request("textDocument/completion", 2, 9))).contains(&"blue"));
+  assert!(labels(&reply(text, request("textDocument/completion", 3, 8))).contains(&"2cm"));
+  assert!(reply(text, request("textDocument/completion", 0, 3)).as_array().unwrap().is_empty());
+}
+
+#[test]
+fn go_to_definition() {
+  let text = "box.pic1\nbox.other\nline from pic1.e end other.w\n";
+  let result = reply(text, request("textDocument/definition", 2, 12));
+  assert_eq!(json!({ "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 8 } }), result["range"]);
+  assert_eq!(Value::Null, reply(text, request("textDocument/definition", 2, 2)));
+}
+
+#[test]
+fn hover_shows_used_rect() {
+  let text = "box.a wd 1in ht 1in\ngroup {\n  circle rad 1cm\n}\n";
+  let on_box = reply(text, request("textDocument/hover", 0, 1));
+  assert!(on_box["contents"].as_str().unwrap().contains("w: 96"), "{}", on_box);
+  let in_group = reply(text, request("textDocument/hover", 2, 4));
+  assert_eq!(json!(2), in_group["range"]["start"]["line"]);
+}
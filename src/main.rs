@@ -1,13 +1,18 @@
-use std::{fs, io};
-use std::path::PathBuf;
-use std::io::{Read};
+use std::{fs, io, process};
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use log::info;
+use picturs::diagram::format::Formatter;
 use picturs::diagram::parser::Diagram;
+use picturs::diagram::source::Source;
+use picturs::diagram::theme::Theme;
 use picturs::init_logging;
-use picturs::skia::A5;
+use picturs::lsp::Server;
+use picturs::pic;
+use picturs::pic::Dialect;
+use picturs::skia::{A5, Sketch};
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
@@ -16,19 +21,84 @@ struct Args {
   input: Option<PathBuf>,
   #[arg(short, long)]
   output: Option<PathBuf>,
+  /// treat warnings as errors
+  #[arg(long)]
+  strict: bool,
+  /// extra directories to look for included files in
+  #[arg(short = 'I', long = "include")]
+  includes: Vec<PathBuf>,
+  /// read GNU PIC, or a roff document with pictures between .PS and .PE
+  #[arg(long)]
+  pic: bool,
+  /// read pikchr, also chosen by a .pikchr extension or a `# pragma pikchr` line
+  #[arg(long)]
+  pikchr: bool,
+  /// light, dark, print, or a .toml or .json theme file
+  #[arg(long)]
+  theme: Option<String>,
+  /// draw as if by hand
+  #[arg(long)]
+  sketch: bool,
+  /// how `--sketch` wobbles; the same seed always draws the same
+  #[arg(long, default_value_t = 1)]
+  seed: u32,
+  #[command(subcommand)]
+  command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+  /// rewrite diagrams in canonical form, or stdin to stdout
+  Fmt {
+    files: Vec<PathBuf>,
+    /// only report what is not formatted, and fail if anything is
+    #[arg(long)]
+    check: bool,
+  },
+  /// run a language server on stdin and stdout
+  Lsp,
 }
 
 fn main() -> Result<()> {
   init_logging();
   let args = Args::parse();
-  let mut string = String::new();
-  if let Some(path) = args.input {
-    string = fs::read_to_string(&path)?;
-  } else {
-    io::stdin().read_to_string(&mut string)?;
+  match &args.command {
+    Some(Command::Fmt { files, check }) => return fmt_main(files, *check),
+    Some(Command::Lsp) => return Ok(Server::new(io::stdout(), &args.includes).serve(io::stdin().lock())?),
+    None => {}
+  }
+  let text = match &args.input {
+    Some(path) => fs::read_to_string(path)?,
+    None => io::read_to_string(io::stdin())?,
+  };
+  let dialect = match (args.pikchr, args.pic) {
+    (true, _) => Some(Dialect::Pikchr),
+    (_, true) => Some(Dialect::Gnu),
+    _ => Dialect::detect(args.input.as_deref(), &text),
   };
+  if let Some(dialect) = dialect {
+    return pic_main(&args, &text, dialect);
+  }
+  let source = match &args.input {
+    Some(path) => Source::load(path, &args.includes)?,
+    None => Source::from_string("<stdin>", text, &args.includes),
+  };
+  let name = args.input.as_ref().map(|path| path.display().to_string()).unwrap_or("<stdin>".into());
   let mut diagram = Diagram::inset(A5, (32., 32.));
-  diagram.parse_string(&string);
+  diagram.diagnostics.strict = args.strict;
+  diagram.theme = theme(&args)?;
+  diagram.sketch = sketch(&args);
+  let parsed = diagram.parse_source(&source);
+  for diagnostic in diagram.diagnostics.iter() {
+    eprintln!("{}: {}", name, diagnostic);
+  }
+  if let Err(error) = parsed {
+    eprintln!("error in {}: {}", name, error);
+    process::exit(1);
+  }
+  if diagram.diagnostics.has_errors() {
+    process::exit(1);
+  }
 
   let output = args.output.expect("Output path is required");
   diagram.shrink_to_file(output.as_os_str().to_str().unwrap(), None);
@@ -36,3 +106,104 @@ fn main() -> Result<()> {
   Ok(())
 }
 
+/// Formats files in place, or stdin to stdout; with `check` nothing is written
+fn fmt_main(files: &[PathBuf], check: bool) -> Result<()> {
+  let mut unformatted = false;
+  if files.is_empty() {
+    let text = io::read_to_string(io::stdin())?;
+    let formatted = Formatter::format(&text).unwrap_or_else(|error| {
+      eprintln!("error in <stdin>: {}", error);
+      process::exit(1);
+    });
+    match check {
+      true => unformatted = formatted != text,
+      false => print!("{}", formatted),
+    }
+  }
+  // a file that fails is reported, and the others are still formatted
+  let mut failed = false;
+  for path in files {
+    match fmt_file(path, check) {
+      Ok(true) if check => {
+        println!("{}", path.display());
+        unformatted = true;
+      }
+      Ok(_) => {}
+      Err(error) => {
+        eprintln!("error in {}: {}", path.display(), error);
+        failed = true;
+      }
+    }
+  }
+  if unformatted || failed {
+    process::exit(1);
+  }
+  Ok(())
+}
+
+/// Format a file in place, or only check it; whether it wasn't formatted already
+fn fmt_file(path: &Path, check: bool) -> Result<bool> {
+  let text = fs::read_to_string(path)?;
+  let formatted = Formatter::format(&text)?;
+  let changed = formatted != text;
+  if changed && !check {
+    fs::write(path, formatted)?;
+  }
+  Ok(changed)
+}
+
+/// Each picture goes to its own file, numbered when there is more than one
+fn pic_main(args: &Args, text: &str, dialect: Dialect) -> Result<()> {
+  let name = args.input.as_ref().map(|path| path.display().to_string()).unwrap_or("<stdin>".into());
+  let pictures = pic::pictures(text, dialect).unwrap_or_else(|error| {
+    eprintln!("error in {}: {}", name, error);
+    process::exit(1);
+  });
+  let output = args.output.clone().expect("Output path is required");
+  let count = pictures.len();
+  let theme = theme(args)?;
+  let mut failed = false;
+  for (number, statements) in pictures.into_iter().enumerate() {
+    let mut diagram = Diagram::inset(A5, (32., 32.));
+    diagram.diagnostics.strict = args.strict;
+    diagram.theme = theme.clone();
+    diagram.sketch = sketch(args);
+    diagram.parse_pic(statements, dialect);
+    for diagnostic in diagram.diagnostics.iter() {
+      eprintln!("{}: {}", name, diagnostic);
+    }
+    if diagram.diagnostics.has_errors() {
+      failed = true;
+      continue;
+    }
+    let path = if count > 1 { numbered(&output, number + 1) } else { output.clone() };
+    diagram.shrink_to_file(&path, None);
+    info!("Wrote picture to {:?}", path);
+  }
+  if failed {
+    process::exit(1);
+  }
+  Ok(())
+}
+
+fn theme(args: &Args) -> Result<Theme> {
+  match &args.theme {
+    // from the working directory, then the include directories
+    Some(name) => Theme::find(name, &[vec![PathBuf::new()], args.includes.clone()].concat()).map_err(anyhow::Error::msg),
+    None => Ok(Theme::default()),
+  }
+}
+
+fn sketch(args: &Args) -> Option<Sketch> {
+  args.sketch.then_some(Sketch { seed: args.seed, hachure: false })
+}
+
+/// `out.png` becomes `out-2.png`
+fn numbered(path: &Path, number: usize) -> PathBuf {
+  let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+  let name = match path.extension() {
+    Some(extension) => format!("{}-{}.{}", stem, number, extension.to_string_lossy()),
+    None => format!("{}-{}", stem, number),
+  };
+  path.with_file_name(name)
+}
@@ -1,7 +1,18 @@
-use log::warn;
+use std::path::Path;
+
+use log::{debug, warn};
+use pest::error::Error;
 use pest::iterators::{Pair, Pairs};
+use pest::Parser;
 use pest_derive::Parser;
 
+use crate::diagram::error::ParseError;
+
+pub(crate) mod lower;
+
+#[cfg(test)]
+mod tests;
+
 #[derive(Parser)]
 #[grammar = "pic.pest"] // relative to project `src`
 pub struct PicParser;
@@ -19,9 +30,69 @@ pub enum Shape {
   Arc,
   Arrow,
   Box,
+  Circle,
+  Cylinder,
+  Dot,
+  Ellipse,
+  File,
+  Line,
+  Move,
+  Oval,
+  Spline,
+  Text,
   Unset,
 }
 
+/// The flavours of PIC that are laid out by `Diagram::parse_pic`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Dialect {
+  /// GNU PIC, with pictures in roff documents
+  #[default]
+  Gnu,
+  /// pikchr, with bare color names, `$variables` and extra shapes
+  Pikchr,
+}
+
+impl Dialect {
+  /// A `.pikchr` file, or a `# pragma pikchr` or `# pragma pic` comment line; `None` for picturs itself
+  pub fn detect(path: Option<&Path>, text: &str) -> Option<Self> {
+    let pragma = text.lines()
+      .filter_map(|line| line.trim().strip_prefix('#').or(line.trim().strip_prefix("//")))
+      .find_map(|comment| match comment.split_whitespace().collect::<Vec<_>>()[..] {
+        ["pragma", "pikchr"] => Some(Dialect::Pikchr),
+        ["pragma", "pic"] => Some(Dialect::Gnu),
+        _ => None,
+      });
+    let extension = path.and_then(|path| path.extension()).filter(|extension| *extension == "pikchr");
+    pragma.or(extension.map(|_| Dialect::Pikchr))
+  }
+}
+
+impl From<Error<Rule>> for ParseError {
+  fn from(error: Error<Rule>) -> Self {
+    Self::from_pest(error, |rule| match rule {
+      Rule::EOI => "end of input".into(),
+      Rule::LABEL => "label".into(),
+      rule => format!("{:?}", rule).replace('_', " ")
+    })
+  }
+}
+
+/// The pictures between `.PS` and `.PE` in a roff document, or the whole text when it has none or is pikchr.
+/// Each is a `Rule::statements` pair, ready for `Diagram::parse_pic`.
+pub fn pictures(text: &str, dialect: Dialect) -> Result<Vec<Pair<'_, Rule>>, Box<ParseError>> {
+  let roff = dialect == Dialect::Gnu && text.lines().any(|line| line.starts_with(".PS"));
+  if !roff {
+    let statements = PicParser::parse(Rule::picture, text).map_err(ParseError::from)?.next().unwrap();
+    return Ok(vec![statements]);
+  }
+  let pictures = PicParser::parse(Rule::document, text).map_err(ParseError::from)?
+    .filter(|pair| pair.as_rule() == Rule::block)
+    .map(|block| block.into_inner().find(|pair| pair.as_rule() == Rule::statements).unwrap())
+    .collect();
+  Ok(pictures)
+}
+
 fn parse_next(inner: &mut Pairs<Rule>) -> Vec<Node> {
   let next = inner.next().unwrap();
   parse_nodes(next, vec![])
@@ -43,13 +114,23 @@ pub fn parse_nodes(pair: Pair<Rule>, mut ast: Vec<Node>) -> Vec<Node> {
           "arc" => Shape::Arc,
           "arrow" => Shape::Arrow,
           "box" => Shape::Box,
-          &_ => unreachable!()
+          "circle" => Shape::Circle,
+          "cylinder" => Shape::Cylinder,
+          "dot" => Shape::Dot,
+          "ellipse" => Shape::Ellipse,
+          "file" => Shape::File,
+          "line" => Shape::Line,
+          "move" => Shape::Move,
+          "oval" => Shape::Oval,
+          "spline" => Shape::Spline,
+          "text" => Shape::Text,
+          &_ => Shape::Unset
         };
         let attrs = parse_next(&mut inner);
         ast.push(Node::Primitive(shape, attrs));
       }
       Rule::path_attribute => {
-        ast.push(Node::Attribute(pair.as_str().to_string()))
+        ast.push(Node::Attribute(pair.as_str().trim_end().to_string()))
       }
       Rule::string => {
         ast.push(Node::String(pair.into_inner().as_str().to_string()))
@@ -65,7 +146,7 @@ pub fn parse_nodes(pair: Pair<Rule>, mut ast: Vec<Node>) -> Vec<Node> {
 
 pub fn dump_pic(level: usize, pair: Pair<Rule>) {
   for pair in pair.into_inner() {
-    println!("{:level$} {:?}", level, pair);
+    debug!("{:level$} {:?}", level, pair);
     dump_pic(level + 1, pair);
   }
-}
\ No newline at end of file
+}
@@ -0,0 +1,1140 @@
+use std::collections::HashMap;
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use pest::iterators::Pair;
+use skia_safe::{Color, Point, Rect, Size, Vector};
+
+use crate::diagram::attributes::Attributes;
+use crate::diagram::bounds::Bounds;
+use crate::diagram::conversion::Conversion;
+use crate::diagram::diagnostics::{Diagnostic, Diagnostics};
+use crate::diagram::parser::{Diagram, TEXT_PADDING};
+use crate::diagram::types::{Caption, CommonAttributes, Config, Edge, Ending, Endings, Node, Paragraph, Shape, PPI};
+use crate::pic::{Dialect, Rule};
+use crate::skia::{Cap, Effect, Join};
+
+/// Defaults of GNU PIC and pikchr, in inches
+const DEFAULTS: [(&str, f32); 26] = [
+  ("arcrad", 0.25),
+  ("arrowht", 0.1),
+  ("arrowwid", 0.05),
+  ("boxht", 0.5),
+  ("boxrad", 0.),
+  ("boxwid", 0.75),
+  ("circlerad", 0.25),
+  ("cylht", 0.5),
+  ("cylrad", 0.075),
+  ("cylwid", 0.75),
+  ("dashwid", 0.1),
+  ("dotrad", 0.015),
+  ("ellipseht", 0.5),
+  ("ellipsewid", 0.75),
+  ("fileht", 0.75),
+  ("filerad", 0.15),
+  ("filewid", 0.5),
+  ("fillval", 0.5),
+  ("lineht", 0.5),
+  ("linewid", 0.5),
+  ("moveht", 0.5),
+  ("movewid", 0.5),
+  ("ovalht", 0.5),
+  ("ovalwid", 1.),
+  ("textht", 0.),
+  ("textwid", 0.),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Class {
+  Arc,
+  Arrow,
+  Block,
+  Box,
+  Circle,
+  Cylinder,
+  Dot,
+  Ellipse,
+  File,
+  Line,
+  Move,
+  Oval,
+  Spline,
+  Text,
+}
+
+impl Class {
+  fn from(name: &str) -> Self {
+    match name {
+      "arc" => Class::Arc,
+      "arrow" => Class::Arrow,
+      "box" => Class::Box,
+      "circle" => Class::Circle,
+      "cylinder" => Class::Cylinder,
+      "dot" => Class::Dot,
+      "ellipse" => Class::Ellipse,
+      "file" => Class::File,
+      "line" => Class::Line,
+      "move" => Class::Move,
+      "oval" => Class::Oval,
+      "spline" => Class::Spline,
+      "[]" => Class::Block,
+      _ => Class::Text,
+    }
+  }
+
+  fn is_open(&self) -> bool {
+    matches!(self, Class::Arc | Class::Arrow | Class::Line | Class::Move | Class::Spline)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Direction {
+  Up,
+  Down,
+  Left,
+  Right,
+}
+
+impl Direction {
+  fn from(name: &str) -> Self {
+    match name {
+      "up" => Direction::Up,
+      "down" => Direction::Down,
+      "left" => Direction::Left,
+      _ => Direction::Right,
+    }
+  }
+
+  fn vector(&self) -> Vector {
+    match self {
+      Direction::Up => Vector::new(0., 1.),
+      Direction::Down => Vector::new(0., -1.),
+      Direction::Left => Vector::new(-1., 0.),
+      Direction::Right => Vector::new(1., 0.),
+    }
+  }
+
+  /// where the next object leaves off
+  fn exit(&self) -> &'static str {
+    match self {
+      Direction::Up => "n",
+      Direction::Down => "s",
+      Direction::Left => "w",
+      Direction::Right => "e",
+    }
+  }
+
+  /// where the next object is attached
+  fn entry(&self) -> &'static str {
+    match self {
+      Direction::Up => "s",
+      Direction::Down => "n",
+      Direction::Left => "e",
+      Direction::Right => "w",
+    }
+  }
+
+  fn side(name: &str) -> Self {
+    match name {
+      "above" => Direction::Up,
+      "below" => Direction::Down,
+      name => Direction::from(name),
+    }
+  }
+
+  fn turn(&self, clockwise: bool) -> Self {
+    match (self, clockwise) {
+      (Direction::Right, false) | (Direction::Left, true) => Direction::Up,
+      (Direction::Up, false) | (Direction::Down, true) => Direction::Left,
+      (Direction::Left, false) | (Direction::Right, true) => Direction::Down,
+      (Direction::Down, false) | (Direction::Up, true) => Direction::Right,
+    }
+  }
+
+  fn is_vertical(&self) -> bool {
+    matches!(self, Direction::Up | Direction::Down)
+  }
+}
+
+/// An object as placed, in inches with y going up, as PIC has it
+#[derive(Clone, Debug)]
+struct Placed {
+  class: Class,
+  center: Point,
+  width: f32,
+  height: f32,
+  start: Point,
+  end: Point,
+  /// labels inside a block
+  frame: Option<Frame>,
+}
+
+impl Placed {
+  fn corner(&self, name: &str) -> Point {
+    let (dx, dy) = match name {
+      "start" => return self.start,
+      "end" => return self.end,
+      "n" | "north" | "t" | "top" => (0., 1.),
+      "s" | "south" | "b" | "bottom" => (0., -1.),
+      "e" | "east" | "r" | "right" => (1., 0.),
+      "w" | "west" | "l" | "left" => (-1., 0.),
+      "ne" => (1., 1.),
+      "nw" => (-1., 1.),
+      "se" => (1., -1.),
+      "sw" => (-1., -1.),
+      _ => (0., 0.),
+    };
+    // corners of round things are on their outline
+    let round = matches!(self.class, Class::Circle | Class::Dot | Class::Ellipse) && dx != 0. && dy != 0.;
+    let factor = if round { FRAC_1_SQRT_2 } else { 1. };
+    Point::new(self.center.x + dx * factor * self.width / 2., self.center.y + dy * factor * self.height / 2.)
+  }
+
+  fn shift(&mut self, offset: Vector) {
+    self.center += offset;
+    self.start += offset;
+    self.end += offset;
+    if let Some(frame) = &mut self.frame {
+      frame.objects.iter_mut().for_each(|placed| placed.shift(offset));
+      frame.places.values_mut().for_each(|place| *place += offset);
+    }
+  }
+}
+
+/// Objects and labels of the picture or of a block
+#[derive(Clone, Debug, Default)]
+struct Frame {
+  objects: Vec<Placed>,
+  labels: HashMap<String, usize>,
+  places: HashMap<String, Point>,
+}
+
+/// What the attributes of an object ask for
+#[derive(Default)]
+struct Spec {
+  width: Option<f32>,
+  height: Option<f32>,
+  radius: Option<f32>,
+  at: Option<Point>,
+  with: Option<String>,
+  from: Option<Point>,
+  path: Vec<Step>,
+  strings: Vec<String>,
+  position: Option<String>,
+  endings: Option<Endings>,
+  fit: bool,
+  same: Option<Placed>,
+  invisible: bool,
+  effect: Effect,
+  fill: Option<Color>,
+  stroke: Option<Color>,
+  thickness: Option<f32>,
+  chops: Vec<Option<f32>>,
+  clockwise: bool,
+  close: bool,
+  /// the direction a line last went in, which pikchr goes on laying out in
+  out: Option<Direction>,
+}
+
+enum Step {
+  By(Vector),
+  Until(Direction, Point),
+  To(Point),
+  Then,
+}
+
+/// Lays out GNU PIC statements into the nodes `Renderer` draws
+pub(crate) struct Lowering<'a> {
+  dialect: Dialect,
+  config: Config,
+  variables: HashMap<String, f32>,
+  frames: Vec<Frame>,
+  nodes: Vec<Vec<Node<'a>>>,
+  bounds: Option<Rect>,
+  here: Point,
+  direction: Direction,
+  diagnostics: Diagnostics,
+}
+
+impl<'a> Lowering<'a> {
+  pub(crate) fn nodes_from(statements: Pair<'a, Rule>, dialect: Dialect, diagnostics: Diagnostics) -> (Vec<Node<'a>>, Rect, Diagnostics) {
+    let mut lowering = Self {
+      dialect,
+      config: Config::default(),
+      variables: DEFAULTS.iter().map(|(name, value)| (name.to_string(), *value)).collect(),
+      frames: vec![Frame::default()],
+      nodes: vec![vec![]],
+      bounds: None,
+      here: Point::new(0., 0.),
+      direction: Direction::Right,
+      diagnostics,
+    };
+    lowering.statements(statements);
+    let nodes = lowering.nodes.pop().unwrap_or_default();
+    (nodes, lowering.bounds.unwrap_or_default(), lowering.diagnostics)
+  }
+
+  fn statements(&mut self, pair: Pair<'a, Rule>) {
+    for statement in pair.into_inner() {
+      let Some(inner) = statement.into_inner().next() else {
+        continue;
+      };
+      match inner.as_rule() {
+        Rule::labeled => {
+          let mut parts = inner.into_inner();
+          let label = parts.next().unwrap().as_str();
+          let labeled = parts.next().unwrap();
+          match labeled.as_rule() {
+            Rule::definition => self.definition(labeled, Some(label)),
+            _ => {
+              let place = self.position_from(labeled);
+              self.frame().places.insert(label.into(), place);
+            }
+          }
+        }
+        Rule::assignment => {
+          let mut parts = inner.into_inner();
+          let name = parts.next().unwrap().as_str();
+          let operator = parts.next().unwrap().as_str();
+          let expr = parts.next().unwrap();
+          let value = self.expr_from(expr.clone(), 1.);
+          let current = self.variable(name);
+          let value = match operator {
+            "+=" => current + value,
+            "-=" => current - value,
+            "*=" => current * value,
+            "/=" if value == 0. => {
+              self.error(&expr, "division by zero");
+              current
+            }
+            "/=" => current / value,
+            _ => value,
+          };
+          self.variables.insert(name.into(), value);
+        }
+        Rule::direction => self.direction = Direction::from(inner.as_str()),
+        Rule::definition => self.definition(inner, None),
+        Rule::braces => {
+          let (here, direction) = (self.here, self.direction);
+          self.statements(inner.into_inner().next().unwrap());
+          (self.here, self.direction) = (here, direction);
+        }
+        _ => {}
+      }
+    }
+  }
+
+  fn frame(&mut self) -> &mut Frame {
+    self.frames.last_mut().unwrap()
+  }
+
+  fn variable(&self, name: &str) -> f32 {
+    self.variables.get(name).copied().unwrap_or_default()
+  }
+
+  fn definition(&mut self, pair: Pair<'a, Rule>, label: Option<&'a str>) {
+    let inner = pair.into_inner().next().unwrap();
+    let placed = match inner.as_rule() {
+      Rule::object_definition => {
+        let mut parts = inner.into_inner();
+        let class = Class::from(parts.next().unwrap().as_str());
+        let spec = self.spec_from(parts.next().unwrap(), class);
+        match class {
+          Class::Arc => self.arc(spec, label),
+          class if class.is_open() => self.open(class, spec, label),
+          class => self.closed(class, spec, label),
+        }
+      }
+      Rule::text_object => {
+        let mut parts = inner.into_inner();
+        let first = Self::string_from(parts.next().unwrap());
+        let mut spec = self.spec_from(parts.next().unwrap(), Class::Text);
+        spec.strings.insert(0, first);
+        self.closed(Class::Text, spec, label)
+      }
+      _ => self.block(inner, label),
+    };
+
+    let frame = self.frame();
+    if let Some(label) = label {
+      frame.labels.insert(label.into(), frame.objects.len());
+    }
+    frame.objects.push(placed);
+  }
+
+  fn spec_from(&mut self, pair: Pair<'a, Rule>, class: Class) -> Spec {
+    let mut spec = Spec::default();
+    for attribute in pair.into_inner() {
+      let attribute = attribute.into_inner().next().unwrap();
+      match attribute.as_rule() {
+        Rule::location => spec.at = Some(self.position_from(attribute.into_inner().next().unwrap())),
+        Rule::with => spec.with = Some(attribute.into_inner().next().unwrap().as_str().into()),
+        Rule::size_attribute => spec.fit = true,
+        Rule::same_as => spec.same = self.object_from(attribute.into_inner().next().unwrap()),
+        Rule::same_attribute => spec.same = self.nth(class, 1, true),
+        Rule::endings => spec.endings = Some(Endings::from(attribute.as_str())),
+        Rule::path_attribute => self.path_from(attribute.into_inner().next().unwrap(), class, &mut spec),
+        Rule::string => spec.strings.push(Self::string_from(attribute)),
+        Rule::text_position => spec.position = Some(attribute.as_str().into()),
+        // text is drawn in the one font of the theme
+        Rule::text_style => self.warning(&attribute, format!("ignored `{}`", attribute.as_str())),
+        Rule::numeric_property => {
+          let mut parts = attribute.into_inner();
+          let dimension = parts.next().unwrap().as_str();
+          let value = parts.next().unwrap();
+          // percentages are of the default size
+          let (width, height) = self.default_size(class);
+          match dimension {
+            "height" | "ht" => spec.height = Some(self.expr_from(value, height)),
+            "width" | "wid" => spec.width = Some(self.expr_from(value, width)),
+            "radius" | "rad" => spec.radius = Some(self.expr_from(value, width / 2.)),
+            "diameter" | "diam" => spec.radius = Some(self.expr_from(value, width) / 2.),
+            // in points for GNU PIC, in inches for pikchr
+            _ => spec.thickness = Some(match self.dialect {
+              Dialect::Gnu => self.expr_from(value, 1.) * PPI / 72.,
+              Dialect::Pikchr => self.expr_from(value, 1.) * PPI,
+            }),
+          }
+        }
+        Rule::style => self.style_from(attribute.into_inner().next().unwrap(), &mut spec),
+        _ => {}
+      }
+    }
+    spec
+  }
+
+  fn path_from(&mut self, pair: Pair<'a, Rule>, class: Class, spec: &mut Spec) {
+    match pair.as_rule() {
+      Rule::source => spec.from = Some(self.position_from(pair.into_inner().next().unwrap())),
+      Rule::target => {
+        let target = self.position_from(pair.into_inner().next().unwrap());
+        spec.path.push(Step::To(target));
+      }
+      Rule::then => spec.path.push(Step::Then),
+      _ => {
+        let mut direction = self.direction;
+        let mut heading: Option<f32> = None;
+        let mut length: Option<Pair<Rule>> = None;
+        for part in pair.into_inner() {
+          match part.as_rule() {
+            Rule::until => {
+              let mut parts = part.into_inner();
+              let direction = Direction::from(parts.next().unwrap().as_str());
+              let until = self.position_from(parts.next().unwrap());
+              spec.path.push(Step::Until(direction, until));
+              spec.out = Some(direction);
+              return;
+            }
+            Rule::direction => {
+              direction = Direction::from(part.as_str());
+              spec.out = Some(direction);
+            }
+            Rule::heading => heading = Some(self.expr_from(part.into_inner().next().unwrap(), 1.)),
+            _ => length = part.into_inner().next(),
+          }
+        }
+        let default = self.default_length(class, direction);
+        let length = length.map(|length| self.expr_from(length, default)).unwrap_or(default);
+        // headings are in degrees clockwise from north
+        let vector = match heading {
+          Some(degrees) => Vector::new(degrees.to_radians().sin(), degrees.to_radians().cos()),
+          None => direction.vector(),
+        };
+        spec.path.push(Step::By(vector * length));
+      }
+    }
+  }
+
+  fn style_from(&mut self, pair: Pair<'a, Rule>, spec: &mut Spec) {
+    match pair.as_rule() {
+      Rule::dashed => spec.effect = Effect::Dashed,
+      Rule::dotted => spec.effect = Effect::Dotted,
+      Rule::solid => spec.effect = Effect::Solid,
+      Rule::close => spec.close = true,
+      Rule::invisible => spec.invisible = true,
+      Rule::fill => {
+        let value = pair.into_inner().next()
+          .map(|value| self.expr_from(value, 1.))
+          .unwrap_or(self.variable("fillval"));
+        spec.fill = match self.dialect {
+          Dialect::Gnu => {
+            let gray = ((1. - value.clamp(0., 1.)) * 255.) as u8;
+            Some(Color::from_rgb(gray, gray, gray))
+          }
+          Dialect::Pikchr => Self::color_from(value),
+        };
+      }
+      Rule::chop => {
+        let chop = pair.into_inner().next().map(|value| self.expr_from(value, 1.));
+        spec.chops.push(chop);
+      }
+      Rule::color => {
+        let mut parts = pair.into_inner();
+        let kind = parts.next().unwrap().as_str();
+        let value = parts.next().unwrap();
+        let color = match value.as_rule() {
+          Rule::string => {
+            let color = Conversion::named_color(&Self::string_from(value.clone()));
+            if color.is_none() {
+              self.error(&value, format!("unknown color {}", value.as_str()));
+            }
+            color
+          }
+          _ => {
+            let value = self.expr_from(value, 1.);
+            Self::color_from(value)
+          }
+        };
+        let Some(color) = color else {
+          return;
+        };
+        match kind {
+          "shaded" => spec.fill = Some(color),
+          "outline" | "outlined" => spec.stroke = Some(color),
+          // `color` also fills what is filled
+          _ => {
+            spec.stroke = Some(color);
+            spec.fill = spec.fill.map(|_| color);
+          }
+        }
+      }
+      Rule::thick => spec.thickness = Some(3.),
+      Rule::thin => spec.thickness = Some(0.5),
+      Rule::rotation => spec.clockwise = pair.as_str() == "cw",
+      // objects are drawn in the order they come
+      Rule::behind => self.warning(&pair, "ignored `behind`"),
+      _ => {}
+    }
+  }
+
+  /// width and height by the `*wid`, `*ht` and `*rad` variables of a class
+  fn default_size(&self, class: Class) -> (f32, f32) {
+    let size = |width: &str, height: &str| (self.variable(width), self.variable(height));
+    let round = |radius: &str| (2. * self.variable(radius), 2. * self.variable(radius));
+    match class {
+      Class::Circle => round("circlerad"),
+      Class::Cylinder => size("cylwid", "cylht"),
+      Class::Dot => round("dotrad"),
+      Class::Ellipse => size("ellipsewid", "ellipseht"),
+      Class::File => size("filewid", "fileht"),
+      Class::Oval => size("ovalwid", "ovalht"),
+      Class::Text => size("textwid", "textht"),
+      _ => size("boxwid", "boxht"),
+    }
+  }
+
+  /// pikchr colors are numbers like 0xff0000, and negative for none
+  fn color_from(value: f32) -> Option<Color> {
+    (value >= 0.).then(|| {
+      let rgb = value as u32;
+      Color::from_rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+    })
+  }
+
+  fn default_length(&self, class: Class, direction: Direction) -> f32 {
+    match (class, direction.is_vertical()) {
+      (Class::Move, true) => self.variable("moveht"),
+      (Class::Move, false) => self.variable("movewid"),
+      (_, true) => self.variable("lineht"),
+      (_, false) => self.variable("linewid"),
+    }
+  }
+
+  /// boxes, circles, ellipses and text: sized, then attached to the previous object or put `at` a place
+  fn closed(&mut self, class: Class, spec: Spec, label: Option<&'a str>) -> Placed {
+    let title = (!spec.strings.is_empty()).then(|| spec.strings.join("\n"));
+    let text = self.text_size(&spec.strings);
+    let (default_width, default_height) = spec.same.as_ref()
+      .map(|same| (same.width, same.height))
+      .unwrap_or(self.default_size(class));
+    let fitted = spec.fit.then(|| (text.width + 2. * TEXT_PADDING / PPI, text.height + 2. * TEXT_PADDING / PPI));
+    let (width, height) = match class {
+      Class::Circle | Class::Dot => {
+        let radius = spec.radius.or(spec.width.map(|width| width / 2.))
+          .or(fitted.map(|(width, height)| width.max(height) / 2.))
+          .unwrap_or(default_width / 2.);
+        (radius * 2., radius * 2.)
+      }
+      Class::Text => (
+        spec.width.unwrap_or(text.width.max(default_width)),
+        spec.height.unwrap_or(text.height.max(default_height)),
+      ),
+      _ => (
+        spec.width.or(fitted.map(|(width, _)| width)).unwrap_or(default_width),
+        spec.height.or(fitted.map(|(_, height)| height)).unwrap_or(default_height),
+      ),
+    };
+
+    let mut placed = Placed { class, center: Point::default(), width, height, start: Point::default(), end: Point::default(), frame: None };
+    self.attach(&mut placed, &spec);
+    if class == Class::Text {
+      let offset = match spec.position.as_deref() {
+        Some("ljust") => Vector::new(width / 2., 0.),
+        Some("rjust") => Vector::new(-width / 2., 0.),
+        Some("above") => Vector::new(0., height / 2.),
+        Some("below") => Vector::new(0., -height / 2.),
+        _ => Vector::default(),
+      };
+      placed.shift(offset);
+    }
+
+    let used = self.rect(&placed);
+    let node = match class {
+      Class::Text => {
+        let paragraph = self.paragraph(title.unwrap_or_default(), used.width());
+        let common = CommonAttributes::new(label, used, spec.stroke.unwrap_or(Color::BLACK), 1.);
+        Node::Primitive(common, Shape::Text(paragraph, None))
+      }
+      _ => {
+        let paragraph = title.map(|title| self.paragraph(title, used.width()));
+        let radius = match class {
+          Class::Box => spec.radius.unwrap_or(self.variable("boxrad")) * PPI,
+          _ => 0.,
+        };
+        let shape = match class {
+          Class::Circle | Class::Dot => Shape::Circle,
+          Class::Cylinder => Shape::Cylinder,
+          Class::Ellipse => Shape::Ellipse,
+          Class::File => Shape::File,
+          Class::Oval => Shape::Oval,
+          _ => Shape::Rectangle,
+        };
+        let mut attributes = Self::closed_attributes(label, &spec, used, radius);
+        if let (Class::Dot, Attributes::Closed { fill, stroke, .. }) = (class, &mut attributes) {
+          *fill = *stroke;
+        }
+        Node::Closed(attributes, used, paragraph, shape)
+      }
+    };
+    self.push(node, used);
+    placed
+  }
+
+  /// place an object by its `with` corner `at` a position, or attach it to the previous one
+  fn attach(&mut self, placed: &mut Placed, spec: &Spec) {
+    let (corner, target) = match (&spec.at, &spec.with) {
+      (Some(at), with) => (with.as_deref().unwrap_or("c"), *at),
+      (None, Some(with)) => (with.as_str(), self.here),
+      (None, None) => (self.direction.entry(), self.here),
+    };
+    let offset = target - placed.corner(corner);
+    placed.shift(offset);
+    placed.start = placed.corner(self.direction.entry());
+    placed.end = placed.corner(self.direction.exit());
+    self.here = placed.end;
+  }
+
+  fn closed_attributes(label: Option<&'a str>, spec: &Spec, used: Rect, radius: f32) -> Attributes<'a> {
+    Attributes::Closed {
+      id: label,
+      same: spec.same.is_some(),
+      width: Some(used.width()),
+      height: Some(used.height()),
+      padding: 0.,
+      radius,
+      space: 0.,
+      title: None,
+      location: None,
+      endings: None,
+      stroke: if spec.invisible { Color::TRANSPARENT } else { spec.stroke.unwrap_or(Color::BLACK) },
+      fill: spec.fill.unwrap_or(Color::TRANSPARENT),
+      shading: None,
+      text: Color::BLACK,
+      thickness: if spec.invisible { 0. } else { spec.thickness.unwrap_or(1.) },
+      effect: spec.effect.clone(),
+      shadow: None,
+    }
+  }
+
+  /// lines, arrows, splines and moves follow their path from the current position
+  fn open(&mut self, class: Class, spec: Spec, label: Option<&'a str>) -> Placed {
+    let start = spec.from.unwrap_or(self.here);
+    let mut points = vec![start];
+    let mut offset: Option<Vector> = None;
+    for step in spec.path.iter() {
+      match step {
+        Step::By(by) => offset = Some(offset.unwrap_or_default() + *by),
+        Step::Until(direction, until) => {
+          let from = *points.last().unwrap() + offset.unwrap_or_default();
+          let by = match direction.is_vertical() {
+            true => Vector::new(0., until.y - from.y),
+            false => Vector::new(until.x - from.x, 0.),
+          };
+          offset = Some(offset.unwrap_or_default() + by);
+        }
+        Step::Then => {
+          if let Some(offset) = offset.take() {
+            points.push(*points.last().unwrap() + offset);
+          }
+        }
+        Step::To(to) => {
+          if let Some(offset) = offset.take() {
+            points.push(*points.last().unwrap() + offset);
+          }
+          points.push(*to);
+        }
+      }
+    }
+    if let Some(offset) = offset {
+      points.push(*points.last().unwrap() + offset);
+    }
+    if points.len() == 1 {
+      let offset = match spec.same.as_ref() {
+        Some(same) => same.end - same.start,
+        None => self.direction.vector() * self.default_length(class, self.direction),
+      };
+      points.push(start + offset);
+    }
+
+    if let Some(at) = spec.at {
+      let center = Bounds::bounds_from_points(&points).center();
+      points.iter_mut().for_each(|point| *point += at - center);
+    }
+    if spec.close {
+      points.push(points[0]);
+    }
+    self.chop(&mut points, &spec.chops);
+    self.here = *points.last().unwrap();
+    if let (Dialect::Pikchr, Some(out)) = (self.dialect, spec.out) {
+      self.direction = out;
+    }
+    self.line(class, points, &spec, label)
+  }
+
+  /// shorten the ends of a line, by the circle radius when no amount is given
+  fn chop(&self, points: &mut [Point], chops: &[Option<f32>]) {
+    let radius = self.variable("circlerad");
+    let (start, end) = match chops {
+      [] => return,
+      [one] => (one.unwrap_or(radius), one.unwrap_or(radius)),
+      [first, second, ..] => (first.unwrap_or(radius), second.unwrap_or(radius)),
+    };
+    let last = points.len() - 1;
+    let shorten = |from: Point, to: Point, amount: f32| {
+      let vector = to - from;
+      let length = vector.length();
+      if length > 0. { from + vector * (amount / length).min(1.) } else { from }
+    };
+    points[0] = shorten(points[0], points[1], start);
+    points[last] = shorten(points[last], points[last - 1], end);
+  }
+
+  /// a quarter circle, turning left from the current direction, or right when `cw`
+  fn arc(&mut self, spec: Spec, label: Option<&'a str>) -> Placed {
+    let clockwise = spec.clockwise;
+    let start = spec.from.unwrap_or(self.here);
+    let turn = if clockwise { -1. } else { 1. };
+    let target = spec.path.iter().find_map(|step| match step {
+      Step::To(to) => Some(*to),
+      _ => None
+    });
+
+    let (center, radius) = match target {
+      Some(end) => {
+        let chord = end - start;
+        let middle = start + chord * 0.5;
+        let normal = Vector::new(-chord.y, chord.x) * (0.5 * turn);
+        (middle + normal, chord.length() * FRAC_1_SQRT_2)
+      }
+      None => {
+        let radius = spec.radius.unwrap_or(self.variable("arcrad"));
+        let forward = self.direction.vector();
+        (start + Vector::new(-forward.y, forward.x) * (radius * turn), radius)
+      }
+    };
+
+    let from = (start.y - center.y).atan2(start.x - center.x);
+    let points = (0..=16).map(|step| {
+      let angle = from + turn * std::f32::consts::FRAC_PI_2 * step as f32 / 16.;
+      Point::new(center.x + radius * angle.cos(), center.y + radius * angle.sin())
+    }).collect::<Vec<_>>();
+
+    self.direction = self.direction.turn(clockwise);
+    self.here = *points.last().unwrap();
+    self.line(Class::Arc, points, &spec, label)
+  }
+
+  fn line(&mut self, class: Class, points: Vec<Point>, spec: &Spec, label: Option<&'a str>) -> Placed {
+    let extent = Bounds::bounds_from_points(&points);
+    let placed = Placed {
+      class,
+      center: extent.center(),
+      width: extent.width(),
+      height: extent.height(),
+      start: points[0],
+      end: *points.last().unwrap(),
+      frame: None,
+    };
+
+    let pixels = points.iter().map(|point| Self::pixels(*point)).collect::<Vec<_>>();
+    let rect = Bounds::bounds_from_points(&pixels);
+    if class == Class::Move {
+      self.push(Node::Move(rect), rect);
+      return placed;
+    }
+
+    let endings = spec.endings.clone().unwrap_or(match class {
+      Class::Arrow => Endings { end: Ending::Arrow, ..Endings::default() },
+      _ => Endings::default(),
+    });
+    let caption = (!spec.strings.is_empty()).then(|| self.caption(spec.strings.join(" "), spec.position.as_deref()));
+    let mut used = rect;
+    if let Some(caption) = &caption {
+      Bounds::bounds_from_rect(&mut used, caption.place_in_rect(&rect));
+    }
+
+    let attributes = Attributes::Open {
+      id: label,
+      same: spec.same.is_some(),
+      caption: caption.clone(),
+      length: (placed.end - placed.start).length() * PPI,
+      endings: endings.clone(),
+      source: None,
+      target: None,
+      movement: None,
+      stroke: if spec.invisible { Color::TRANSPARENT } else { spec.stroke.unwrap_or(Color::BLACK) },
+      thickness: spec.thickness.unwrap_or(1.),
+      effect: spec.effect.clone(),
+      cap: Cap::default(),
+      join: Join::default(),
+      double: false,
+      shadow: None,
+    };
+    let shape = match class {
+      Class::Arrow => Shape::Arrow(pixels, caption, endings),
+      _ => Shape::Line(pixels, caption, endings),
+    };
+    self.push(Node::Open(attributes, rect, shape), used);
+    placed
+  }
+
+  /// `[ ... ]` lays out its statements on their own, then moves them like a box
+  fn block(&mut self, pair: Pair<'a, Rule>, label: Option<&'a str>) -> Placed {
+    let mut parts = pair.into_inner();
+    let statements = parts.next().unwrap();
+    let spec = self.spec_from(parts.next().unwrap(), Class::Block);
+
+    let (here, direction) = (self.here, self.direction);
+    self.frames.push(Frame::default());
+    self.nodes.push(vec![]);
+    self.here = Point::default();
+    self.statements(statements);
+    let frame = self.frames.pop().unwrap();
+    let mut nodes = self.nodes.pop().unwrap();
+    (self.here, self.direction) = (here, direction);
+
+    let extent = frame.objects.iter()
+      .map(|placed| Rect::from_xywh(placed.center.x - placed.width / 2., placed.center.y - placed.height / 2., placed.width, placed.height))
+      .reduce(|mut bounds, rect| {
+        Bounds::bounds_from_rect(&mut bounds, rect);
+        bounds
+      })
+      .unwrap_or_default();
+
+    let mut placed = Placed {
+      class: Class::Block,
+      center: extent.center(),
+      width: spec.width.unwrap_or(extent.width()),
+      height: spec.height.unwrap_or(extent.height()),
+      start: Point::default(),
+      end: Point::default(),
+      frame: Some(frame),
+    };
+    let center = placed.center;
+    self.attach(&mut placed, &spec);
+    let offset = placed.center - center;
+    Diagram::shift_nodes(&mut nodes, Vector::new(offset.x * PPI, -offset.y * PPI));
+
+    let used = self.rect(&placed);
+    let mut attributes = Self::closed_attributes(label, &spec, used, 0.);
+    if let Attributes::Closed { thickness, stroke, .. } = &mut attributes {
+      *thickness = 0.;
+      *stroke = Color::TRANSPARENT;
+    }
+    self.push(Node::Group(attributes, used, nodes), used);
+    placed
+  }
+
+  fn push(&mut self, node: Node<'a>, used: Rect) {
+    if self.nodes.len() == 1 {
+      let bounds = self.bounds.get_or_insert(used);
+      Bounds::bounds_from_rect(bounds, used);
+    }
+    self.nodes.last_mut().unwrap().push(node);
+  }
+
+  /// from inches with y going up to pixels with y going down
+  fn pixels(point: Point) -> Point {
+    Point::new(point.x * PPI, -point.y * PPI)
+  }
+
+  fn rect(&self, placed: &Placed) -> Rect {
+    let top_left = Self::pixels(Point::new(placed.center.x - placed.width / 2., placed.center.y + placed.height / 2.));
+    Rect::from_xywh(top_left.x, top_left.y, placed.width * PPI, placed.height * PPI)
+  }
+
+  /// size of lines of text, in inches
+  fn text_size(&self, strings: &[String]) -> Size {
+    let (font_height, _) = self.config.font.metrics();
+    let width = strings.iter().map(|string| self.config.measure_string(string).width()).fold(0., f32::max);
+    Size::new(width / PPI, font_height * strings.len() as f32 / PPI)
+  }
+
+  fn paragraph(&self, text: String, width: f32) -> Paragraph {
+    let (widths, height) = self.config.measure_strings(&text, width - 2. * TEXT_PADDING);
+    Paragraph { text, widths, height, size: Size::new(width, height) }
+  }
+
+  fn caption(&self, text: String, position: Option<&str>) -> Caption {
+    let caption_edge = match position {
+      Some("above") => Edge::below(),
+      Some("below") => Edge::above(),
+      Some("ljust") => Edge::left(),
+      Some("rjust") => Edge::right(),
+      _ => Edge::center(),
+    };
+    let bounds = self.config.measure_string(&text);
+    Caption { text, rect_edge: Edge::center(), caption_edge, bounds, opaque: false }
+  }
+
+  fn string_from(pair: Pair<Rule>) -> String {
+    pair.into_inner().next().unwrap().as_str().replace("\\\"", "\"")
+  }
+
+  fn error(&mut self, pair: &Pair<Rule>, message: impl Into<String>) {
+    self.diagnostics.push(Diagnostic::error(&pair.as_span(), message));
+  }
+
+  fn warning(&mut self, pair: &Pair<Rule>, message: impl Into<String>) {
+    self.diagnostics.push(Diagnostic::warning(&pair.as_span(), message));
+  }
+
+  // positions
+
+  fn position_from(&mut self, pair: Pair<Rule>) -> Point {
+    let mut inner = pair.into_inner();
+    let mut point = self.term_from(inner.next().unwrap());
+    while let (Some(operator), Some(term)) = (inner.next(), inner.next()) {
+      let term = self.term_from(term);
+      match operator.as_str() {
+        "-" => point -= term,
+        _ => point += term,
+      }
+    }
+    point
+  }
+
+  fn term_from(&mut self, pair: Pair<Rule>) -> Point {
+    match pair.as_rule() {
+      Rule::between => {
+        let mut parts = pair.into_inner();
+        let fraction = self.expr_from(parts.next().unwrap(), 1.);
+        let from = self.position_from(parts.next().unwrap());
+        let to = self.position_from(parts.next().unwrap());
+        from + (to - from) * fraction
+      }
+      Rule::relative => {
+        let mut parts = pair.into_inner();
+        let distance = self.expr_from(parts.next().unwrap(), 1.);
+        let side = Direction::side(parts.next().unwrap().as_str());
+        let from = self.position_from(parts.next().unwrap());
+        from + side.vector() * distance
+      }
+      Rule::coordinates | Rule::pair => {
+        let mut parts = pair.into_inner();
+        let x = self.expr_from(parts.next().unwrap(), 1.);
+        let y = self.expr_from(parts.next().unwrap(), 1.);
+        Point::new(x, y)
+      }
+      Rule::mixed => {
+        let mut parts = pair.into_inner();
+        let x = self.position_from(parts.next().unwrap()).x;
+        let y = self.position_from(parts.next().unwrap()).y;
+        Point::new(x, y)
+      }
+      Rule::position => self.position_from(pair),
+      _ => self.place_from(pair),
+    }
+  }
+
+  fn place_from(&mut self, pair: Pair<Rule>) -> Point {
+    let parts = pair.into_inner().collect::<Vec<_>>();
+    let corner = parts.iter().find(|part| part.as_rule() == Rule::corner).map(|corner| corner.as_str());
+    let Some(object) = parts.iter().find(|part| part.as_rule() == Rule::object) else {
+      return self.here;
+    };
+    if let Some(place) = self.place_label(object) {
+      return place;
+    }
+    self.object_from(object.clone())
+      .map(|placed| placed.corner(corner.unwrap_or("c")))
+      .unwrap_or(self.here)
+  }
+
+  /// a label given to a position rather than an object
+  fn place_label(&self, pair: &Pair<Rule>) -> Option<Point> {
+    let name = pair.as_str();
+    self.frames.iter().rev().find_map(|frame| frame.places.get(name).copied())
+  }
+
+  fn object_from(&mut self, pair: Pair<Rule>) -> Option<Placed> {
+    let inner = pair.clone().into_inner().next().unwrap();
+    let placed = match inner.as_rule() {
+      Rule::object_name => self.named(inner.as_str()),
+      _ => {
+        let mut ordinal = 1;
+        let mut last = false;
+        let mut class: Option<Class> = None;
+        for part in inner.into_inner() {
+          match part.as_rule() {
+            Rule::ordinal => ordinal = part.as_str().trim_end_matches(char::is_alphabetic).parse().unwrap_or(1),
+            Rule::last | Rule::previous => last = true,
+            Rule::first => {}
+            _ => class = Some(Class::from(part.as_str())),
+          }
+        }
+        match class {
+          Some(class) => self.nth(class, ordinal, last),
+          None => ordinal.checked_sub(1)
+            .and_then(|nth| self.frames.iter().rev().find_map(|frame| match last {
+              true => frame.objects.iter().rev().nth(nth),
+              false => frame.objects.get(nth),
+            }))
+            .cloned(),
+        }
+      }
+    };
+    if placed.is_none() {
+      self.error(&pair, format!("there is no {}", pair.as_str()));
+    }
+    placed
+  }
+
+  /// `B.A` is object `A` in block `B`
+  fn named(&self, name: &str) -> Option<Placed> {
+    let mut parts = name.split('.');
+    let first = parts.next().unwrap();
+    let mut placed = self.frames.iter().rev()
+      .find_map(|frame| frame.labels.get(first).map(|index| frame.objects[*index].clone()))?;
+    for part in parts {
+      let frame = placed.frame?;
+      placed = frame.labels.get(part).map(|index| frame.objects[*index].clone())?;
+    }
+    Some(placed)
+  }
+
+  /// counted in the innermost block that has objects of the class
+  fn nth(&self, class: Class, ordinal: usize, last: bool) -> Option<Placed> {
+    let frame = self.frames.iter().rev()
+      .find(|frame| frame.objects.iter().any(|placed| placed.class == class))?;
+    let mut objects = frame.objects.iter().filter(|placed| placed.class == class);
+    let placed = match last {
+      true => objects.rev().nth(ordinal.checked_sub(1)?),
+      false => objects.nth(ordinal.checked_sub(1)?),
+    };
+    placed.cloned()
+  }
+
+  // expressions
+
+  /// `percent` is what 100% stands for
+  fn expr_from(&mut self, pair: Pair<Rule>, percent: f32) -> f32 {
+    match pair.as_rule() {
+      Rule::expr | Rule::expr_term => {
+        let mut inner = pair.into_inner();
+        let mut value = self.expr_from(inner.next().unwrap(), percent);
+        while let (Some(operator), Some(operand)) = (inner.next(), inner.next()) {
+          let right = self.expr_from(operand.clone(), percent);
+          value = match operator.as_str() {
+            "+" => value + right,
+            "-" => value - right,
+            "*" => value * right,
+            _ if right == 0. => {
+              self.error(&operand, "division by zero");
+              0.
+            }
+            "/" => value / right,
+            _ => value % right,
+          };
+        }
+        value
+      }
+      Rule::negation => -self.expr_from(pair.into_inner().next().unwrap(), percent),
+      Rule::number => Self::number_from(pair.as_str(), percent),
+      Rule::variable => {
+        let name = pair.as_str();
+        match self.variables.get(name) {
+          Some(value) => *value,
+          // pikchr has the color names as constants
+          None if self.dialect == Dialect::Pikchr && Conversion::named_color(name).is_some() => {
+            let color = Conversion::named_color(name).unwrap();
+            ((color.r() as u32) << 16 | (color.g() as u32) << 8 | color.b() as u32) as f32
+          }
+          None => {
+            self.error(&pair, format!("unknown variable `{}`", name));
+            0.
+          }
+        }
+      }
+      Rule::property => self.property_from(pair),
+      Rule::function => {
+        let mut inner = pair.into_inner();
+        let name = inner.next().unwrap().as_str();
+        let arguments = inner.map(|argument| self.expr_from(argument, percent)).collect::<Vec<_>>();
+        let (first, second) = (arguments[0], arguments.get(1).copied().unwrap_or_default());
+        match name {
+          "sin" => first.sin(),
+          "cos" => first.cos(),
+          "atan2" => first.atan2(second),
+          "log" => first.log10(),
+          "exp" => 10f32.powf(first),
+          "sqrt" => first.sqrt(),
+          "max" => arguments.into_iter().fold(f32::MIN, f32::max),
+          "min" => arguments.into_iter().fold(f32::MAX, f32::min),
+          "int" => first.trunc(),
+          _ => first.abs(),
+        }
+      }
+      _ => 0.
+    }
+  }
+
+  /// numbers are inches, unless they have a unit
+  fn number_from(text: &str, percent: f32) -> f32 {
+    if let Some(hex) = text.strip_prefix("0x") {
+      return u32::from_str_radix(hex, 16).unwrap_or_default() as f32;
+    }
+    let split = text.find(|char: char| char.is_ascii_alphabetic() && char != 'e' && char != 'E' || char == '%').unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number = number.parse::<f32>().unwrap_or_default();
+    match unit {
+      "%" => number / 100. * percent,
+      "px" => number / PPI,
+      "pt" => number / 72.,
+      "cm" => number / 2.54,
+      "mm" => number / 25.4,
+      _ => number,
+    }
+  }
+
+  fn property_from(&mut self, pair: Pair<Rule>) -> f32 {
+    let mut parts = pair.into_inner();
+    let place = parts.next().unwrap();
+    let name = parts.next().unwrap().as_str();
+    match name {
+      "x" => self.place_from(place).x,
+      "y" => self.place_from(place).y,
+      _ => {
+        let object = place.into_inner().find(|part| part.as_rule() == Rule::object);
+        let Some(placed) = object.and_then(|object| self.object_from(object)) else {
+          return 0.;
+        };
+        match name {
+          "height" | "ht" => placed.height,
+          "width" | "wid" => placed.width,
+          "radius" | "rad" => placed.width / 2.,
+          _ => placed.width,
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,250 @@
+use pest::Parser;
+use skia_safe::{Point, Rect};
+
+use crate::diagram::diagnostics::Severity;
+use crate::diagram::parser::Diagram;
+use crate::diagram::types::{Node, Shape};
+use crate::pic;
+use crate::pic::{parse_nodes, pictures, Dialect, PicParser, Rule};
+use crate::skia::A5;
+
+fn layout(string: &str) -> Diagram<'_> {
+  layout_in(string, Dialect::Gnu)
+}
+
+fn layout_in(string: &str, dialect: Dialect) -> Diagram<'_> {
+  let statements = pictures(string, dialect).unwrap().remove(0);
+  let mut diagram = Diagram::inset(A5, (0., 0.));
+  diagram.parse_pic(statements, dialect);
+  diagram
+}
+
+fn rects(diagram: &Diagram) -> Vec<Rect> {
+  diagram.nodes.iter().filter_map(|node| match node {
+    Node::Closed(_, used, _, _) | Node::Group(_, used, _) | Node::Open(_, used, _) | Node::Move(used) => Some(*used),
+    Node::Primitive(common, _) => Some(common.used),
+    _ => None,
+  }).collect()
+}
+
+fn points(diagram: &Diagram) -> Vec<Vec<Point>> {
+  diagram.nodes.iter().filter_map(|node| match node {
+    Node::Open(_, _, Shape::Line(points, _, _) | Shape::Arrow(points, _, _)) => Some(points.clone()),
+    _ => None,
+  }).collect()
+}
+
+fn messages(diagram: &Diagram) -> Vec<String> {
+  diagram.diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect()
+}
+
+#[test]
+fn pictures_between_ps_and_pe() {
+  let document = ".TL\nA title\n.PS\nbox\n.PE\nSome text\n.PS 2i\ncircle; arrow\n.PE\n";
+  let pictures = pictures(document, Dialect::Gnu).unwrap();
+  assert_eq!(vec!["box", "circle; arrow"], pictures.iter().map(|pair| pair.as_str().trim()).collect::<Vec<_>>());
+}
+
+#[test]
+fn whole_text_without_ps() {
+  let pictures = pictures("box\ncircle\n", Dialect::Gnu).unwrap();
+  assert_eq!(1, pictures.len());
+}
+
+#[test]
+fn boxes_flow_right() {
+  let diagram = layout("box; box");
+  assert_eq!(vec![Rect::from_xywh(0., -24., 72., 48.), Rect::from_xywh(72., -24., 72., 48.)], rects(&diagram));
+}
+
+#[test]
+fn direction_changes_the_flow() {
+  let diagram = layout("down; box; arrow; circle");
+  assert_eq!(vec![
+    Rect::from_xywh(-36., 0., 72., 48.),
+    Rect::from_xywh(0., 48., 0., 48.),
+    Rect::from_xywh(-24., 96., 48., 48.),
+  ], rects(&diagram));
+}
+
+#[test]
+fn then_paths() {
+  let diagram = layout("line right 1 then up 1 then left 1");
+  let expected = vec![Point::new(0., 0.), Point::new(96., 0.), Point::new(96., -96.), Point::new(0., -96.)];
+  assert_eq!(vec![expected], points(&diagram));
+}
+
+#[test]
+fn segments_combine_until_then() {
+  let diagram = layout("line up 1 right 1");
+  assert_eq!(vec![vec![Point::new(0., 0.), Point::new(96., -96.)]], points(&diagram));
+}
+
+#[test]
+fn at_with() {
+  let diagram = layout("box; circle at last box.ne; box with .sw at (1, 1)");
+  let rects = rects(&diagram);
+  assert_eq!(Rect::from_xywh(48., -48., 48., 48.), rects[1]);
+  assert_eq!(Rect::from_xywh(96., -144., 72., 48.), rects[2]);
+}
+
+#[test]
+fn ordinals() {
+  let diagram = layout("box; circle; box wid 1; arrow from 1st box.n to 2nd box.n; line from last circle.s to 2nd last box.s");
+  assert_eq!(vec![
+    vec![Point::new(36., -24.), Point::new(168., -24.)],
+    vec![Point::new(96., 24.), Point::new(36., 24.)],
+  ], points(&diagram));
+}
+
+#[test]
+fn arcs_turn() {
+  let diagram = layout("arc; arc");
+  let points = points(&diagram);
+  let end = *points[1].last().unwrap();
+  assert!((end - Point::new(0., -48.)).length() < 0.01, "{:?}", end);
+}
+
+#[test]
+fn labels_in_blocks() {
+  let diagram = layout("A: box; B: [ C: circle ] with .w at A.e + (0.5, 0); arrow from A.e to B.C.w");
+  assert_eq!(vec![vec![Point::new(72., 0.), Point::new(120., 0.)]], points(&diagram));
+  match &diagram.nodes[1] {
+    Node::Group(_, used, nodes) => {
+      assert_eq!(Rect::from_xywh(120., -24., 48., 48.), *used);
+      assert_eq!(1, nodes.len());
+    }
+    node => panic!("expected a group, not {:?}", node),
+  }
+}
+
+#[test]
+fn expressions() {
+  let diagram = layout("boxwid = 1 + 2 / 4\nA: box\ncircle rad A.wid / 6 at A.c");
+  let rects = rects(&diagram);
+  assert_eq!(144., rects[0].width());
+  assert_eq!(Rect::from_xywh(48., -24., 48., 48.), rects[1]);
+}
+
+#[test]
+fn same_size() {
+  let diagram = layout("box wid 2 ht 1; box same");
+  let rects = rects(&diagram);
+  assert_eq!(rects[0].size(), rects[1].size());
+}
+
+#[test]
+fn diagnostics() {
+  let diagram = layout("box; arrow from 3rd box.n to Nowhere\nx = 1 / 0\nbox wid y");
+  assert_eq!(vec![
+    "there is no 3rd box",
+    "there is no Nowhere",
+    "division by zero",
+    "unknown variable `y`",
+  ], messages(&diagram));
+}
+
+#[test]
+fn ignored_attributes() {
+  let diagram = layout("A: box\nbox \"note\" bold italic behind A");
+  assert_eq!(vec!["ignored `bold`", "ignored `italic`", "ignored `behind`"], messages(&diagram));
+  assert!(diagram.diagnostics.iter().all(|diagnostic| diagnostic.severity == Severity::Warning));
+}
+
+#[test]
+fn homepage() {
+  let string = std::fs::read_to_string("tests/pic/homepage.pic").unwrap();
+  let diagram = layout(&string);
+  let kinds = diagram.nodes.iter().map(|node| match node {
+    Node::Closed(..) => "closed",
+    Node::Open(..) => "open",
+    _ => "other",
+  }).collect::<Vec<_>>();
+  assert_eq!(vec!["open", "closed", "open", "open", "closed"], kinds);
+  assert!(diagram.diagnostics.is_empty());
+}
+
+#[test]
+fn nodes_of_every_class() {
+  let pair = PicParser::parse(Rule::picture, "cylinder; dot; file; oval; text \"t\"").unwrap().next().unwrap();
+  let shapes = parse_nodes(pair, vec![]).into_iter().filter_map(|node| match node {
+    pic::Node::Primitive(shape, _) => Some(shape),
+    _ => None,
+  }).collect::<Vec<_>>();
+  assert_eq!(vec![pic::Shape::Cylinder, pic::Shape::Dot, pic::Shape::File, pic::Shape::Oval, pic::Shape::Text], shapes);
+}
+
+
+mod pikchr {
+  use skia_safe::{Color, Point, Rect};
+
+  use crate::diagram::attributes::Attributes;
+  use crate::diagram::types::Node;
+  use crate::pic::Dialect;
+
+  use super::{layout_in, messages, points, rects};
+
+  #[test]
+  fn bare_and_numeric_colors() {
+    let diagram = layout_in("box color red fill 0x00ff00", Dialect::Pikchr);
+    match &diagram.nodes[0] {
+      Node::Closed(Attributes::Closed { stroke, fill, .. }, ..) => assert_eq!((Color::RED, Color::GREEN), (*stroke, *fill)),
+      node => panic!("expected a box, not {:?}", node),
+    }
+  }
+
+  #[test]
+  fn variables_and_percentages() {
+    let diagram = layout_in("$w = 1\n$w += 0.5\nbox wid $w\nbox wid 50% ht 150%", Dialect::Pikchr);
+    let rects = rects(&diagram);
+    assert_eq!(144., rects[0].width());
+    assert_eq!((36., 72.), (rects[1].width(), rects[1].height()));
+  }
+
+  #[test]
+  fn relative_positions() {
+    let diagram = layout_in("box; circle at 1 right of previous; oval at 0.5 below first box", Dialect::Pikchr);
+    let rects = rects(&diagram);
+    assert_eq!(Point::new(132., 0.), rects[1].center());
+    assert_eq!(Point::new(36., 48.), rects[2].center());
+  }
+
+  #[test]
+  fn until_even_with() {
+    let diagram = layout_in("B: box at (2, -1)\nline from (0, 0) down until even with B then right until even with B.w", Dialect::Pikchr);
+    assert_eq!(vec![Point::new(0., 0.), Point::new(0., 96.), Point::new(156., 96.)], points(&diagram)[0]);
+  }
+
+  #[test]
+  fn headings() {
+    let diagram = layout_in("line go 1 heading 90 then go 1 heading 180", Dialect::Pikchr);
+    let points = &points(&diagram)[0];
+    assert!((points[1] - Point::new(96., 0.)).length() < 0.01);
+    assert!((points[2] - Point::new(96., 96.)).length() < 0.01);
+  }
+
+  #[test]
+  fn closed_lines() {
+    let diagram = layout_in("line right 1 then down 1 close", Dialect::Pikchr);
+    let points = &points(&diagram)[0];
+    assert_eq!(points.first(), points.last());
+  }
+
+  #[test]
+  fn shapes() {
+    let diagram = layout_in("cylinder; file; oval; dot", Dialect::Pikchr);
+    let rects = rects(&diagram);
+    assert_eq!(vec![
+      Rect::from_xywh(0., -24., 72., 48.),
+      Rect::from_xywh(72., -36., 48., 72.),
+      Rect::from_xywh(120., -24., 96., 48.),
+    ], rects[..3]);
+    assert!((rects[3].width() - 2.88).abs() < 0.01);
+  }
+
+  #[test]
+  fn color_names_are_pikchr_only() {
+    let diagram = layout_in("box color red", Dialect::Gnu);
+    assert_eq!(vec!["unknown variable `red`"], messages(&diagram));
+  }
+}
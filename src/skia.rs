@@ -2,17 +2,94 @@ use std::fs::File;
 use std::io::Write;
 use std::mem;
 
-use skia_safe::{Color, Data, EncodedImageFormat, Font, FontMgr, FontStyle, ISize, Paint, PaintStyle, Path, PathEffect, Point, Rect, scalar, Surface, surfaces};
+use skia_safe::{BlendMode, Color, Data, EncodedImageFormat, FilterMode, Font, FontMgr, FontStyle, image_filters, ISize, Matrix, Paint, PaintStyle, Path, PathEffect, PathMeasure, PictureRecorder, Point, Rect, scalar, Shader, Surface, surfaces, TileMode, Vector};
+use skia_safe::canvas::SaveLayerRec;
+use skia_safe::paint::{Cap as PaintCap, Join as PaintJoin};
 use skia_safe::textlayout::{FontCollection, Paragraph, ParagraphBuilder, ParagraphStyle, StrutStyle, TextAlign, TextStyle};
 
 pub static A5: (i32, i32) = (798, 562);
+/// the folded corner of a file
+pub const FOLD: f32 = 16.;
 
-#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub enum Effect {
   Dashed,
   Dotted,
   #[default]
   Solid,
+  /// on and off lengths in pixels
+  Dash(Vec<f32>),
+}
+
+/// How strokes end
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Cap {
+  #[default]
+  Butt,
+  Round,
+  Square,
+}
+
+/// How strokes turn corners
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Join {
+  #[default]
+  Miter,
+  Round,
+  Bevel,
+}
+
+/// Fills beyond a plain color, for closed shapes
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shading {
+  /// towards an angle in degrees, clockwise from up as in CSS
+  Linear(f32, Vec<Color>),
+  /// from the center out
+  Radial(Vec<Color>),
+  /// lines or dots `spacing` apart, turned counterclockwise by `angle` degrees, in the stroke color unless given
+  Pattern { pattern: Pattern, spacing: f32, angle: f32, color: Option<Color> },
+}
+
+impl Shading {
+  pub fn map_colors(self, map: impl Fn(Color) -> Color) -> Self {
+    match self {
+      Shading::Linear(angle, colors) => Shading::Linear(angle, colors.into_iter().map(map).collect()),
+      Shading::Radial(colors) => Shading::Radial(colors.into_iter().map(map).collect()),
+      Shading::Pattern { pattern, spacing, angle, color } => Shading::Pattern { pattern, spacing, angle, color: color.map(map) },
+    }
+  }
+}
+
+/// A blurred copy of a shape, offset under it or glowing around it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shadow {
+  pub offset: Vector,
+  pub blur: f32,
+  /// the stroke color unless given
+  pub color: Option<Color>,
+}
+
+impl Shadow {
+  /// where the shadow of `rect` reaches, three sigmas of the blur it is painted with
+  pub fn bounds(&self, rect: &Rect) -> Rect {
+    let reach = self.blur * 1.5;
+    rect.with_offset(self.offset).with_outset((reach, reach))
+  }
+}
+
+/// Strokes drawn twice with a seeded wobble, as if by hand; the same seed always wobbles the same way
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sketch {
+  pub seed: u32,
+  /// fills drawn as hatching in their color
+  pub hachure: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+  Hatch,
+  CrossHatch,
+  Dots,
 }
 
 pub struct Canvas {
@@ -20,6 +97,14 @@ pub struct Canvas {
   path: Path,
   pub paint: Paint,
   pub font: Font,
+  /// how open shapes end
+  pub arrowhead: scalar,
+  pub caption_background: Color,
+  pub sketch: Option<Sketch>,
+  /// strokes as two thin lines with a gap between them
+  pub double: bool,
+  /// strokes drawn so far, so each sketched one wobbles its own way
+  strokes: u32,
   font_collection: FontCollection,
 }
 
@@ -45,6 +130,11 @@ impl Canvas {
       path,
       paint,
       font,
+      arrowhead: 15.,
+      caption_background: Color::LIGHT_GRAY,
+      sketch: None,
+      double: false,
+      strokes: 0,
       font_collection,
     }
   }
@@ -61,10 +151,6 @@ impl Canvas {
     self.canvas().save();
   }
 
-  pub fn path_effect(&mut self) {
-    self.paint.set_path_effect(PathEffect::discrete(10.0, 0.5, None));
-  }
-
   pub fn translate(&mut self, dx: f32, dy: f32) {
     self.canvas().translate((dx, dy));
   }
@@ -86,7 +172,6 @@ impl Canvas {
     self.path.quad_to((cpx, cpy), (x, y));
   }
 
-  #[allow(dead_code)]
   pub fn cubic_to(&mut self, cp1x: f32, cp1y: f32, cp2x: f32, cp2y: f32, x: f32, y: f32) {
     self.path.cubic_to((cp1x, cp1y), (cp2x, cp2y), (x, y));
   }
@@ -97,39 +182,146 @@ impl Canvas {
   }
 
   pub fn begin_path(&mut self) {
-    let new_path = Path::new();
-    self.surface.canvas().draw_path(&self.path, &self.paint);
-    let _ = mem::replace(&mut self.path, new_path);
+    let path = mem::take(&mut self.path);
+    self.draw(&path);
   }
 
   pub fn stroke(&mut self) {
     self.paint.set_style(PaintStyle::Stroke);
-    self.surface.canvas().draw_path(&self.path, &self.paint);
-    self.path.reset();
+    let path = mem::take(&mut self.path);
+    self.draw(&path);
   }
 
   pub fn fill(&mut self) {
     self.paint.set_style(PaintStyle::Fill);
-    self.surface.canvas().draw_path(&self.path, &self.paint);
+    let path = self.path.clone();
+    self.draw(&path);
+  }
+
+  /// a double stroke is a wide one with its middle cleared, in a layer of its own so nothing under it is cleared
+  fn draw(&mut self, path: &Path) {
+    if !self.double || self.paint.style() != PaintStyle::Stroke {
+      return self.trace(path);
+    }
+    let width = self.paint.stroke_width().max(1.);
+    self.surface.canvas().save_layer(&SaveLayerRec::default());
+    self.paint.set_stroke_width(width * 3.);
+    self.trace(path);
+    self.paint.set_stroke_width(width);
+    self.paint.set_blend_mode(BlendMode::Clear);
+    self.trace(path);
+    self.paint.set_blend_mode(BlendMode::SrcOver);
+    self.surface.canvas().restore();
+  }
+
+  /// strokes twice when sketching, each time with its own wobble under any dashes
+  fn trace(&mut self, path: &Path) {
+    let seed = match &self.sketch {
+      Some(sketch) if self.paint.style() != PaintStyle::Fill => sketch.seed,
+      _ => {
+        self.surface.canvas().draw_path(path, &self.paint);
+        return;
+      }
+    };
+    let mut paint = self.paint.clone();
+    for _ in 0..2 {
+      self.strokes += 1;
+      let wobble = PathEffect::discrete(8., 1.2, seed.wrapping_add(self.strokes)).unwrap();
+      paint.set_path_effect(match self.paint.path_effect() {
+        Some(effect) => PathEffect::compose(effect, wobble),
+        None => wobble,
+      });
+      self.surface.canvas().draw_path(path, &paint);
+    }
   }
 
   pub fn stroke_with(&mut self, width: f32, color: Color, effect: &Effect) {
     self.paint.set_style(PaintStyle::Stroke);
+    self.paint.set_shader(None);
     self.paint.set_stroke_width(width);
     self.paint.set_color(color);
     let effect = match effect {
       Effect::Dashed => PathEffect::dash(&[10., 10.], 0.),
       Effect::Dotted => PathEffect::dash(&[2., 4.], 0.),
+      Effect::Dash(intervals) => PathEffect::dash(intervals, 0.),
       Effect::Solid => None
     };
     self.paint.set_path_effect(effect);
   }
 
+  pub fn cap_with(&mut self, cap: Cap, join: Join) {
+    self.paint.set_stroke_cap(match cap {
+      Cap::Butt => PaintCap::Butt,
+      Cap::Round => PaintCap::Round,
+      Cap::Square => PaintCap::Square,
+    });
+    self.paint.set_stroke_join(match join {
+      Join::Miter => PaintJoin::Miter,
+      Join::Round => PaintJoin::Round,
+      Join::Bevel => PaintJoin::Bevel,
+    });
+  }
+
   pub fn fill_with(&mut self, color: Color) {
     self.paint.set_style(PaintStyle::Fill);
+    self.paint.set_shader(None);
     self.paint.set_color(color);
   }
 
+  /// fills with a gradient or pattern laid over `bounds`, until the next `fill_with` or `stroke_with`
+  pub fn shade_with(&mut self, shading: &Shading, bounds: &Rect, stroke: Color) {
+    let shader = match shading {
+      Shading::Linear(angle, colors) => {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let half = (bounds.width() * sin.abs() + bounds.height() * cos.abs()) / 2.;
+        let (center, offset) = (bounds.center(), Point::new(sin * half, -cos * half));
+        Shader::linear_gradient((center - offset, center + offset), colors.as_slice(), None, TileMode::Clamp, None, None)
+      }
+      Shading::Radial(colors) => {
+        let radius = bounds.width().hypot(bounds.height()) / 2.;
+        Shader::radial_gradient(bounds.center(), radius, colors.as_slice(), None, TileMode::Clamp, None, None)
+      }
+      Shading::Pattern { pattern, spacing, angle, color } => Some(Self::pattern(*pattern, *spacing, *angle, color.unwrap_or(stroke))),
+    };
+    self.paint.set_style(PaintStyle::Fill);
+    self.paint.set_color(Color::BLACK);
+    self.paint.set_shader(shader);
+  }
+
+  /// draws only the shadow of what comes until the next `restore`
+  pub fn shadow_with(&mut self, shadow: &Shadow, stroke: Color) {
+    let sigma = shadow.blur / 2.;
+    let filter = image_filters::drop_shadow_only(shadow.offset, (sigma, sigma), shadow.color.unwrap_or(stroke), None, None, None);
+    let mut paint = Paint::default();
+    paint.set_image_filter(filter);
+    self.surface.canvas().save_layer(&SaveLayerRec::default().paint(&paint));
+  }
+
+  /// a tile with one line, two crossing lines or a dot, repeated and turned
+  fn pattern(pattern: Pattern, spacing: f32, angle: f32, color: Color) -> Shader {
+    let tile = Rect::from_wh(spacing, spacing);
+    let mut paint = Paint::default();
+    paint.set_anti_alias(true);
+    paint.set_color(color);
+    let mut recorder = PictureRecorder::new();
+    let canvas = recorder.begin_recording(tile, false);
+    let middle = spacing / 2.;
+    match pattern {
+      Pattern::Dots => {
+        canvas.draw_circle((middle, middle), (spacing / 6.).max(0.5), &paint);
+      }
+      Pattern::Hatch | Pattern::CrossHatch => {
+        paint.set_style(PaintStyle::Stroke);
+        canvas.draw_line((0., middle), (spacing, middle), &paint);
+        if pattern == Pattern::CrossHatch {
+          canvas.draw_line((middle, 0.), (middle, spacing), &paint);
+        }
+      }
+    }
+    let picture = recorder.finish_recording_as_picture(None).unwrap();
+    picture.to_shader((TileMode::Repeat, TileMode::Repeat), FilterMode::Linear, &Matrix::rotate_deg(-angle), &tile)
+  }
+
   pub fn text(&mut self, text: &str, origin: impl Into<Point>) {
     self.surface.canvas().draw_str(text, origin, &self.font, &self.paint);
   }
@@ -184,52 +376,105 @@ impl Canvas {
   }
 
   pub fn rectangle(&mut self, rect: &Rect, radius: f32) {
-    self.surface.canvas().draw_round_rect(rect, radius, radius, &self.paint);
+    let mut path = Path::new();
+    path.add_round_rect(rect, (radius, radius), None);
+    self.draw(&path);
   }
 
   pub fn circle(&mut self, point: &Point, radius: f32) {
-    self.surface.canvas().draw_circle(*point, radius, &self.paint);
+    let mut path = Path::new();
+    path.add_circle(*point, radius, None);
+    self.draw(&path);
   }
 
   pub fn ellipse(&mut self, rect: &Rect) {
-    self.surface.canvas().draw_oval(rect, &self.paint);
+    let mut path = Path::new();
+    path.add_oval(rect, None);
+    self.draw(&path);
   }
 
+  /// filled, only the outline; stroked, the folded corner too
   pub fn file(&mut self, rect: &Rect) {
-    let fold = 16.;
-    self.move_to(rect.left, rect.top);
-    self.line_to(rect.left, rect.bottom);
-    self.line_to(rect.right, rect.bottom);
-    self.line_to(rect.right, rect.top + fold);
-    self.line_to(rect.right - fold, rect.top + fold);
-    self.line_to(rect.right - fold, rect.top);
-    self.line_to(rect.right, rect.top + fold);
-    self.move_to(rect.left, rect.top);
-    self.line_to(rect.right - fold, rect.top);
-    self.stroke();
+    let mut path = Path::new();
+    path.move_to((rect.left, rect.top));
+    path.line_to((rect.left, rect.bottom));
+    path.line_to((rect.right, rect.bottom));
+    path.line_to((rect.right, rect.top + FOLD));
+    path.line_to((rect.right - FOLD, rect.top));
+    path.close();
+    if self.paint.style() != PaintStyle::Fill {
+      path.move_to((rect.right - FOLD, rect.top));
+      path.line_to((rect.right - FOLD, rect.top + FOLD));
+      path.line_to((rect.right, rect.top + FOLD));
+    }
+    self.draw(&path);
   }
 
+  /// filled, only the outline; stroked, the front of the top too
   pub fn cylinder(&mut self, rect: &Rect) {
     let top = Rect::from_xywh(rect.left, rect.top, rect.width(), rect.height() / 3.);
     let bottom = Rect::from_xywh(rect.left, rect.bottom - top.height(), rect.width(), top.height());
     let height = top.height() / 2.;
 
-    self.path.arc_to(top, 180., 359., true);
-    self.path.line_to((rect.left, bottom.top + height));
-    self.path.arc_to(bottom, 180., -180., false);
-    self.path.move_to((bottom.right, bottom.top + height));
-    self.path.line_to((top.right, top.top + height));
-    self.stroke();
+    let mut path = Path::new();
+    if self.paint.style() == PaintStyle::Fill {
+      path.arc_to(top, 180., 180., true);
+      path.line_to((rect.right, bottom.top + height));
+      path.arc_to(bottom, 0., 180., false);
+      path.close();
+    } else {
+      path.arc_to(top, 180., 359., true);
+      path.line_to((rect.left, bottom.top + height));
+      path.arc_to(bottom, 180., -180., false);
+      path.move_to((bottom.right, bottom.top + height));
+      path.line_to((top.right, top.top + height));
+    }
+    self.draw(&path);
   }
 
   pub fn oval(&mut self, rect: &Rect) {
     let left = Rect::from_xywh(rect.left, rect.top, rect.height(), rect.height());
     let right = Rect::from_xywh(rect.right - rect.height(), rect.top, rect.height(), rect.height());
-    self.path.arc_to(left, -90., -180., true);
-    self.path.line_to((right.left, right.bottom));
-    self.path.arc_to(right, 90., -180., false);
-    self.path.close();
-    self.stroke();
+    let mut path = Path::new();
+    path.arc_to(left, -90., -180., true);
+    path.line_to((right.left, right.bottom));
+    path.arc_to(right, 90., -180., false);
+    path.close();
+    self.draw(&path);
+  }
+
+  /// starts a path along an arc, to stroke later
+  pub fn arc_to(&mut self, oval: &Rect, start: f32, sweep: f32) {
+    self.begin_path();
+    self.path.arc_to(oval, start, sweep, true);
+  }
+
+  /// how long the path so far is
+  pub fn length(&self) -> f32 {
+    PathMeasure::new(&self.path, false, None).length()
+  }
+
+  /// the point that far along the path so far, and its direction there
+  pub fn along(&self, distance: f32) -> Option<(Point, Vector)> {
+    PathMeasure::new(&self.path, false, None).pos_tan(distance)
+  }
+
+  /// keeps only the stretches of the path between these distances along it
+  pub fn keep(&mut self, stretches: &[(f32, f32)]) {
+    let mut measure = PathMeasure::new(&self.path, false, None);
+    let mut kept = Path::new();
+    for (from, to) in stretches.iter().filter(|(from, to)| from < to) {
+      if let Some(segment) = measure.segment(*from, *to, true) {
+        kept.add_path(&segment, (0., 0.), None);
+      }
+    }
+    self.path = kept;
+  }
+
+  pub fn polygon(&mut self, corners: &[Point]) {
+    let mut path = Path::new();
+    path.add_poly(corners, true);
+    self.draw(&path);
   }
 
   pub fn data(&mut self) -> Data {
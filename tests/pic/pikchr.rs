@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod corpus {
+  use std::fs;
+  use std::path::Path;
+
+  use skia_safe::Rect;
+
+  use picturs::diagram::parser::Diagram;
+  use picturs::diagram::types::Node;
+  use picturs::pic::{pictures, Dialect};
+  use picturs::skia::A5;
+
+  const PPI: f32 = 96.;
+
+  fn rects(diagram: &Diagram) -> Vec<Rect> {
+    diagram.nodes.iter().filter_map(|node| match node {
+      Node::Closed(_, used, ..) | Node::Group(_, used, _) | Node::Open(_, used, _) | Node::Move(used) => Some(*used),
+      Node::Primitive(common, _) => Some(common.used),
+      _ => None,
+    }).collect()
+  }
+
+  /// lay out a pikchr file without complaints, and compare where its objects went with the `.expected` file next to it
+  fn assert_pikchr(path: &Path) {
+    let text = fs::read_to_string(path).unwrap();
+    let statements = pictures(&text, Dialect::Pikchr).unwrap_or_else(|error| panic!("{}: {}", path.display(), error)).remove(0);
+    let mut diagram = Diagram::inset(A5, (32., 32.));
+    diagram.parse_pic(statements, Dialect::Pikchr);
+    assert!(diagram.diagnostics.is_empty(), "{}: {}", path.display(), diagram.diagnostics);
+
+    let rects = rects(&diagram);
+    let origin = rects[0].center();
+    let actual = rects.iter()
+      .map(|rect| [rect.center_x() - origin.x, origin.y - rect.center_y(), rect.width(), rect.height()].map(|pixels| pixels / PPI))
+      .collect::<Vec<_>>();
+
+    let expected = fs::read_to_string(path.with_extension("expected")).unwrap();
+    let expected = expected.lines().filter(|line| !line.starts_with('#')).collect::<Vec<_>>();
+    assert_eq!(expected.len(), actual.len(), "{}: objects", path.display());
+    for (row, line) in expected.iter().enumerate() {
+      for (column, value) in line.split_whitespace().enumerate() {
+        let wanted = match value {
+          "-" => continue,
+          value if value.starts_with('=') => actual[value[1..].parse::<usize>().unwrap() - 1][column],
+          value => value.parse().unwrap(),
+        };
+        let got = actual[row][column];
+        assert!((wanted - got).abs() < 0.01, "{}: object {}, column {}: expected {}, got {}", path.display(), row + 1, column + 1, wanted, got);
+      }
+    }
+  }
+
+  #[test]
+  fn homepage() {
+    assert_pikchr(Path::new("tests/pic/homepage.pic"));
+  }
+
+  #[test]
+  fn documentation_examples() {
+    let mut paths = fs::read_dir("tests/pic/pikchr").unwrap()
+      .map(|entry| entry.unwrap().path())
+      .filter(|path| path.extension().is_some_and(|extension| extension == "pikchr"))
+      .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty());
+    for path in paths {
+      assert_pikchr(&path);
+    }
+  }
+
+  #[test]
+  fn pragma() {
+    assert_eq!(Some(Dialect::Pikchr), Dialect::detect(None, "# pragma pikchr\nbox\n"));
+    assert_eq!(Some(Dialect::Gnu), Dialect::detect(Some(Path::new("a.pikchr")), "// pragma pic\nbox\n"));
+    assert_eq!(None, Dialect::detect(Some(Path::new("a.pic")), "box\n"));
+  }
+}
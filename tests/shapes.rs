@@ -112,6 +112,23 @@ mod shapes {
     assert_eq!(round(intersect), Some(Point::new(7., -8.)));
   }
 
+  #[test]
+  fn toward_round_outlines() {
+    let ellipse = EdgeFinder::ellipse(0., 0., 40., 20.);
+    assert_eq!(round(ellipse.toward(Point::new(40., 10.))), Some(Point::new(40., 10.)));
+    assert_eq!(round(ellipse.toward(Point::new(40., 0.))), Some(Point::new(34., 3.)));
+
+    let cylinder = EdgeFinder::cylinder(0., 0., 40., 60.);
+    assert_eq!(round(cylinder.toward(Point::new(20., 0.))), Some(Point::new(20., 0.)));
+    assert_eq!(round(cylinder.toward(Point::new(40., 20.))), Some(Point::new(40., 20.)));
+    assert_eq!(round(cylinder.toward(Point::new(20., 60.))), Some(Point::new(20., 60.)));
+
+    let oval = EdgeFinder::oval(0., 0., 80., 20.);
+    assert_eq!(round(oval.toward(Point::new(40., 0.))), Some(Point::new(40., 0.)));
+    assert_eq!(round(oval.toward(Point::new(0., 10.))), Some(Point::new(0., 10.)));
+    assert_eq!(round(oval.toward(Point::new(80., 0.))), Some(Point::new(75., 1.)));
+  }
+
   // http://www.csharphelper.com/howtos/howto_line_ellipse_intersection.html
   // https://github.com/davidfig/intersects/blob/master/ellipse-line.js
   #[test]
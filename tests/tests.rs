@@ -1,5 +1,6 @@
 mod pic {
   mod nested_pic;
+  mod pikchr;
   mod simple;
 }
 
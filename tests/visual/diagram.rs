@@ -134,4 +134,10 @@ mod diagram {
       "#;
     assert_diagram!(string);
   }
+
+  #[test]
+  fn styles() {
+    let string = std::fs::read_to_string("tests/styles.pic").unwrap();
+    assert_diagram!(&string);
+  }
 }